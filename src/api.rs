@@ -1,6 +1,7 @@
 use std::{
     cmp::min,
     path::{Path, PathBuf},
+    sync::Arc,
     time::Duration,
 };
 
@@ -8,82 +9,90 @@ use anyhow::Result;
 use async_trait::async_trait;
 use futures::StreamExt;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use reqwest::{Body, Client, StatusCode};
+use reqwest::{
+    Body, Certificate, Client, ClientBuilder, Identity, NoProxy, Proxy, RequestBuilder, StatusCode,
+};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use std::collections::HashMap;
 use time::OffsetDateTime;
 use tokio::fs::{create_dir_all, File};
 use tokio::io;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
 
 use crate::{
-    bundle::ApplicationBundle,
-    errors::{ApiError, EnvArgError, InputError},
-    filtering::model::SparseMarathonfile,
-    pull::PullFileConfig,
+    constraints::DeviceConstraints,
+    errors::{ApiError, ConfigurationError, EnvArgError, InputError},
+    spec::{PlatformRunSpec, RunSpec},
 };
 
 use tokio_util::io::ReaderStream;
 
+const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Marathon Cloud REST API
 #[async_trait]
 pub trait RapiClient {
-    async fn get_token(&self) -> Result<String>;
-    async fn create_run(
+    /// Uploads the binaries referenced by the spec and submits a new run, returning its id
+    async fn create_run(&self, spec: RunSpec, no_progress_bar: bool) -> Result<String> {
+        let binaries = self.upload_binaries(&spec, no_progress_bar).await?;
+        self.submit_run(spec, &binaries).await
+    }
+
+    /// Uploads the binaries referenced by the spec, they can be shared by several runs
+    async fn upload_binaries(
         &self,
-        app: Option<PathBuf>,
-        test_app: Option<PathBuf>,
-        name: Option<String>,
-        link: Option<String>,
-        branch: Option<String>,
-        platform: String,
-        os_version: Option<String>,
-        system_image: Option<String>,
-        device: Option<String>,
-        xcode_version: Option<String>,
-        isolated: Option<bool>,
-        collect_code_coverage: Option<bool>,
-        retry_quota_test_uncompleted: Option<u32>,
-        retry_quota_test_preventive: Option<u32>,
-        retry_quota_test_reactive: Option<u32>,
-        analytics_read_only: Option<bool>,
-        profiling: bool,
-        mock_location: bool,
-        filtering_configuration: Option<SparseMarathonfile>,
+        spec: &RunSpec,
         no_progress_bar: bool,
-        flavor: Option<String>,
-        env_args: Option<Vec<String>>,
-        test_env_args: Option<Vec<String>>,
-        pull_file_config: Option<PullFileConfig>,
-        concurrency_limit: Option<u32>,
-        test_timeout_default: Option<u32>,
-        test_timeout_max: Option<u32>,
-        project: Option<String>,
-        application_bundle: Option<Vec<ApplicationBundle>>,
-        library_bundle: Option<Vec<PathBuf>>,
-        granted_permission: Option<Vec<String>>,
-    ) -> Result<String>;
+    ) -> Result<UploadedBinaries>;
+
+    /// Submits a new run of already uploaded binaries, returning its id
+    async fn submit_run(&self, spec: RunSpec, binaries: &UploadedBinaries) -> Result<String>;
     async fn get_run(&self, id: &str) -> Result<TestRun>;
 
-    async fn list_artifact(&self, jwt_token: &str, id: &str) -> Result<Vec<Artifact>>;
+    /// Polls the run every `interval` until it is completed
+    async fn wait_for_run(&self, id: &str, interval: Duration) -> Result<TestRun> {
+        loop {
+            let run = self.get_run(id).await?;
+            if run.completed.is_some() {
+                return Ok(run);
+            }
+            sleep(interval).await;
+        }
+    }
+
+    /// Lists artifacts of a run, `id` is either the run id or the id of an artifact directory
+    async fn list_artifact(&self, id: &str) -> Result<Vec<Artifact>>;
+    /// Downloads a single artifact into `base_path` preserving its path relative to the run
     async fn download_artifact(
         &self,
-        jwt_token: &str,
         artifact: Artifact,
         base_path: PathBuf,
         run_id: &str,
     ) -> Result<()>;
 
-    async fn get_devices_android(&self, jwt_token: &str) -> Result<Vec<AndroidDevice>>;
+    async fn get_devices_android(&self) -> Result<Vec<AndroidDevice>>;
+
+    async fn get_devices_ios(&self) -> Result<Vec<IosDevice>>;
+
+    async fn get_device_constraints(&self) -> Result<DeviceConstraints>;
 }
 
+/// [`RapiClient`] implementation backed by reqwest.
+///
+/// Clones share the connection pool and the authentication token.
 #[derive(Clone)]
 pub struct RapiReqwestClient {
     base_url: String,
     api_key: String,
     client: Client,
+    // JWT shared between all clones of the client, obtained lazily and refreshed on 401
+    token: Arc<Mutex<Option<String>>>,
 }
 
 impl RapiReqwestClient {
+    /// Creates a client for the API at `base_url`, e.g. `https://cloud.marathonlabs.io/api`
     pub fn new(base_url: &str, api_key: &str) -> RapiReqwestClient {
         let non_sanitized = base_url.to_string();
         RapiReqwestClient {
@@ -95,202 +104,378 @@ impl RapiReqwestClient {
             ..Default::default()
         }
     }
-}
 
-impl Default for RapiReqwestClient {
-    fn default() -> Self {
-        Self {
-            base_url: String::from("https:://cloud.marathonlabs.io/api"),
-            api_key: "".into(),
-            client: Client::builder()
-                .pool_idle_timeout(Some(Duration::from_secs(20)))
-                .pool_max_idle_per_host(16)
-                .build()
-                .unwrap(),
-        }
+    /// Same as [`RapiReqwestClient::new`] with custom network settings
+    pub fn with_config(
+        base_url: &str,
+        api_key: &str,
+        config: &ClientConfig,
+    ) -> Result<RapiReqwestClient> {
+        let client = config
+            .apply(client_builder())?
+            .build()
+            .map_err(|error| ApiError::RequestFailed { error })?;
+        Ok(RapiReqwestClient {
+            client,
+            ..RapiReqwestClient::new(base_url, api_key)
+        })
     }
-}
 
-#[async_trait]
-impl RapiClient for RapiReqwestClient {
-    async fn get_token(&self) -> Result<String> {
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Exchanges the API key for a JWT, the one request that carries the key. It is sent in
+    /// a header, servers that only take the documented `api_key` query parameter are asked
+    /// again with it. Errors of either request drop the url.
+    async fn fetch_token(&self) -> Result<String> {
         let url = format!("{}/v1/user/jwt", self.base_url);
-        let params = [("api_key", self.api_key.clone())];
-        let url = reqwest::Url::parse_with_params(&url, &params)
-            .map_err(|error| ApiError::InvalidParameters { error })?;
-        let response = self.client.get(url).send().await?;
+        let send = |request: RequestBuilder| async move {
+            request
+                .send()
+                .await
+                .map_err(|error| ApiError::RequestFailed {
+                    error: error.without_url(),
+                })
+        };
+        let mut response = send(
+            self.client
+                .get(&url)
+                .header(API_KEY_HEADER, self.api_key.as_str()),
+        )
+        .await?;
+        if matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        ) {
+            response = send(self.client.get(&url).query(&[("api_key", &self.api_key)])).await?;
+        }
         let response = api_error_adapter(response)
             .await?
             .json::<GetTokenResponse>()
             .await
-            .map_err(|error| ApiError::DeserializationFailure { error })?;
+            .map_err(|error| ApiError::DeserializationFailure {
+                error: error.without_url(),
+            })?;
         Ok(response.token)
     }
 
-    async fn create_run(
-        &self,
-        app: Option<PathBuf>,
-        test_app: Option<PathBuf>,
-        name: Option<String>,
-        link: Option<String>,
-        branch: Option<String>,
-        platform: String,
-        os_version: Option<String>,
-        system_image: Option<String>,
-        device: Option<String>,
-        xcode_version: Option<String>,
-        isolated: Option<bool>,
-        code_coverage: Option<bool>,
-        retry_quota_test_uncompleted: Option<u32>,
-        retry_quota_test_preventive: Option<u32>,
-        retry_quota_test_reactive: Option<u32>,
-        analytics_read_only: Option<bool>,
-        profiling: bool,
-        mock_location: bool,
-        filtering_configuration: Option<SparseMarathonfile>,
-        no_progress_bar: bool,
-        flavor: Option<String>,
-        env_args: Option<Vec<String>>,
-        test_env_args: Option<Vec<String>>,
-        pull_file_config: Option<PullFileConfig>,
-        concurrency_limit: Option<u32>,
-        test_timeout_default: Option<u32>,
-        test_timeout_max: Option<u32>,
-        project: Option<String>,
-        application_bundle: Option<Vec<ApplicationBundle>>,
-        library_bundle: Option<Vec<PathBuf>>,
-        granted_permission: Option<Vec<String>>,
-    ) -> Result<String> {
-        let url = format!("{}/v2/run", self.base_url);
-        let params = [("api_key", self.api_key.clone())];
-        let url = reqwest::Url::parse_with_params(&url, &params)
-            .map_err(|error| ApiError::InvalidParameters { error })?;
+    /// Returns the cached JWT, requesting a new one if there is none yet
+    async fn token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        match token.as_ref() {
+            Some(token) => Ok(token.clone()),
+            None => {
+                let fresh = self.fetch_token().await?;
+                *token = Some(fresh.clone());
+                Ok(fresh)
+            }
+        }
+    }
 
-        let mut s3_test_app_path = None;
-        if let Some(test_app) = test_app {
-            s3_test_app_path = Some(
-                upload_to_s3(
-                    &self.client,
-                    self.base_url.clone(),
-                    self.api_key.clone(),
-                    test_app.clone(),
-                    no_progress_bar,
-                )
-                .await?,
-            );
+    /// Replaces the `stale` JWT unless another request has already refreshed it
+    async fn refresh_token(&self, stale: &str) -> Result<String> {
+        let mut token = self.token.lock().await;
+        match token.as_deref() {
+            Some(current) if current != stale => Ok(current.to_owned()),
+            _ => {
+                let fresh = self.fetch_token().await?;
+                *token = Some(fresh.clone());
+                Ok(fresh)
+            }
         }
+    }
 
-        let mut s3_app_path = None;
-        if let Some(app) = app {
-            s3_app_path = Some(
-                upload_to_s3(
-                    &self.client,
-                    self.base_url.clone(),
-                    self.api_key.clone(),
-                    app.clone(),
-                    no_progress_bar,
-                )
-                .await?,
-            );
+    /// Sends the request with the JWT in the Authorization header.
+    /// If the API rejects the token then it is refreshed and the request is repeated once.
+    async fn send_authorized(&self, request: RequestBuilder) -> Result<reqwest::Response> {
+        let token = self.token().await?;
+        let retry = request.try_clone();
+        let response =
+            request
+                .bearer_auth(&token)
+                .send()
+                .await
+                .map_err(|error| ApiError::RequestFailed {
+                    error: error.without_url(),
+                })?;
+        match (response.status(), retry) {
+            (StatusCode::UNAUTHORIZED, Some(retry)) => {
+                let token = self.refresh_token(&token).await?;
+                Ok(retry.bearer_auth(&token).send().await.map_err(|error| {
+                    ApiError::RequestFailed {
+                        error: error.without_url(),
+                    }
+                })?)
+            }
+            _ => Ok(response),
         }
+    }
 
-        let mut create_run_bundles: Vec<CreateRunBundle> = Vec::new();
+    async fn upload_to_s3(&self, file_path: PathBuf, no_progress_bar: bool) -> Result<String> {
+        // Open file
+        let file = File::open(&file_path)
+            .await
+            .map_err(|error| InputError::OpenFileFailure {
+                path: file_path.clone(),
+                error,
+            })?;
+
+        // Extract filename from PathBuf
+        let file_name = file_path
+            .file_name()
+            .map(|val| val.to_string_lossy().to_string())
+            .ok_or(InputError::InvalidFileName {
+                path: file_path.clone(),
+            })?;
+
+        // Request upload URL
+        let url = format!("{}/v2/upload/presigned-url", self.base_url);
+        let request_body = UploadRequest {
+            filename: file_name.to_string(),
+        };
+        let upload_url_response = self
+            .send_authorized(self.client.post(url).json(&request_body))
+            .await?;
+        let upload_url_response = api_error_adapter(upload_url_response)
+            .await?
+            .json::<UploadUrlResponse>()
+            .await
+            .map_err(|error| ApiError::DeserializationFailure { error })?;
 
-        if let Some(app_bundles) = application_bundle {
-            for app_bundle in app_bundles {
-                let s3_app_path = upload_to_s3(
-                    &self.client,
-                    self.base_url.clone(),
-                    self.api_key.clone(),
-                    app_bundle.app_path.clone(),
-                    no_progress_bar,
-                )
-                .await?;
-
-                let s3_test_app_path = upload_to_s3(
-                    &self.client,
-                    self.base_url.clone(),
-                    self.api_key.clone(),
-                    app_bundle.test_app_path.clone(),
-                    no_progress_bar,
-                )
-                .await?;
-
-                let create_run_bundle = CreateRunBundle {
-                    s3_app_path: Some(s3_app_path),
-                    s3_test_app_path: s3_test_app_path.clone(),
-                };
-                create_run_bundles.push(create_run_bundle);
+        // Progress stuff
+        let file_total_size = file.metadata().await?.len();
+        let mut file_reader = ReaderStream::new(file);
+        let mut multi_progress: Option<MultiProgress> = if !no_progress_bar {
+            Some(MultiProgress::new())
+        } else {
+            None
+        };
+        let file_progress_bar;
+        let file_body;
+        if !no_progress_bar {
+            let sty = ProgressStyle::with_template(
+                "{spinner:.blue} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})"
+            )
+            .unwrap()
+            .progress_chars("#>-");
+
+            let pb = ProgressBar::new(file_total_size);
+            pb.enable_steady_tick(Duration::from_millis(80));
+            file_progress_bar = multi_progress.as_mut().unwrap().add(pb);
+            file_progress_bar.set_style(sty.clone());
+            let mut file_progress = 0u64;
+            let file_stream = async_stream::stream! {
+                while let Some(chunk) = file_reader.next().await {
+                    let file_progress_bar = file_progress_bar.clone();
+                    if let Ok(chunk) = &chunk {
+                        let new = min(file_progress + (chunk.len() as u64), file_total_size);
+                        file_progress = new;
+                        file_progress_bar.set_position(new);
+                        if file_progress >= file_total_size {
+                            file_progress_bar.finish_and_clear();
+                        }
+                    }
+                    yield chunk;
+                }
+            };
+            file_body = Body::wrap_stream(file_stream);
+        } else {
+            file_body = Body::wrap_stream(file_reader);
+        }
+
+        // Presigned url carries its own credentials, no Authorization header here
+        let s3_response = self
+            .client
+            .put(upload_url_response.url.clone())
+            .header("Content-Length", file_total_size)
+            .body(file_body)
+            .send()
+            .await
+            .map_err(|error| ApiError::RequestFailed {
+                error: error.without_url(),
+            })?;
+        api_error_adapter(s3_response).await?;
+
+        Ok(upload_url_response.file_path.clone())
+    }
+}
+
+impl Default for RapiReqwestClient {
+    fn default() -> Self {
+        Self {
+            base_url: String::from("https:://cloud.marathonlabs.io/api"),
+            api_key: "".into(),
+            client: client_builder().build().unwrap(),
+            token: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+fn client_builder() -> ClientBuilder {
+    Client::builder()
+        .pool_idle_timeout(Some(Duration::from_secs(20)))
+        .pool_max_idle_per_host(16)
+}
+
+/// Network settings of [`RapiReqwestClient`].
+///
+/// They apply to the API calls as well as to the presigned uploads and artifact downloads.
+#[derive(Debug, Default, Clone)]
+pub struct ClientConfig {
+    /// Proxy url for all requests. When missing, HTTP_PROXY, HTTPS_PROXY and NO_PROXY are respected
+    pub proxy: Option<String>,
+    /// PEM bundle with additional trusted root certificates, e.g. of a TLS-intercepting proxy
+    pub ca_cert: Option<PathBuf>,
+    /// PEM file with the client certificate chain for mutual TLS
+    pub client_cert: Option<PathBuf>,
+    /// PEM file with the private key of `client_cert`, can be omitted if `client_cert` contains it
+    pub client_key: Option<PathBuf>,
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, InputError> {
+    std::fs::read(path).map_err(|error| InputError::OpenFileFailure {
+        path: path.to_owned(),
+        error,
+    })
+}
+
+impl ClientConfig {
+    fn apply(&self, mut builder: ClientBuilder) -> Result<ClientBuilder> {
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy)
+                .map_err(|error| ConfigurationError::InvalidProxy {
+                    proxy: proxy.clone(),
+                    error,
+                })?
+                .no_proxy(NoProxy::from_env());
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(ca_cert) = &self.ca_cert {
+            let certificates =
+                Certificate::from_pem_bundle(&read_pem(ca_cert)?).map_err(|error| {
+                    ConfigurationError::InvalidCertificate {
+                        path: ca_cert.clone(),
+                        error,
+                    }
+                })?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
             }
         }
 
-        if let Some(library_bundles) = library_bundle {
-            for lib_bundle in library_bundles {
-                let s3_test_app_path = upload_to_s3(
-                    &self.client,
-                    self.base_url.clone(),
-                    self.api_key.clone(),
-                    lib_bundle.clone(),
-                    no_progress_bar,
-                )
-                .await?;
-
-                let create_run_bundle = CreateRunBundle {
-                    s3_app_path: None,
-                    s3_test_app_path: s3_test_app_path.clone(),
-                };
-                create_run_bundles.push(create_run_bundle);
+        if let Some(client_cert) = &self.client_cert {
+            let mut pem = read_pem(client_cert)?;
+            if let Some(client_key) = &self.client_key {
+                pem.push(b'\n');
+                pem.extend(read_pem(client_key)?);
             }
+            let identity = Identity::from_pem(&pem).map_err(|error| {
+                ConfigurationError::InvalidCertificate {
+                    path: client_cert.clone(),
+                    error,
+                }
+            })?;
+            builder = builder.identity(identity);
+        }
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
         }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        Ok(builder)
+    }
+}
+
+#[async_trait]
+impl RapiClient for RapiReqwestClient {
+    async fn upload_binaries(
+        &self,
+        spec: &RunSpec,
+        no_progress_bar: bool,
+    ) -> Result<UploadedBinaries> {
+        let mut s3_app_path = None;
+        let mut s3_test_app_path = None;
+        let mut create_run_bundles: Vec<CreateRunBundle> = Vec::new();
+
+        match &spec.platform {
+            PlatformRunSpec::Android(android) => {
+                if let Some(test_app) = &android.test_application {
+                    s3_test_app_path =
+                        Some(self.upload_to_s3(test_app.clone(), no_progress_bar).await?);
+                }
+
+                if let Some(app) = &android.application {
+                    s3_app_path = Some(self.upload_to_s3(app.clone(), no_progress_bar).await?);
+                }
+
+                for app_bundle in android.application_bundle.iter().flatten() {
+                    let s3_app_path = self
+                        .upload_to_s3(app_bundle.app_path.clone(), no_progress_bar)
+                        .await?;
+
+                    let s3_test_app_path = self
+                        .upload_to_s3(app_bundle.test_app_path.clone(), no_progress_bar)
+                        .await?;
+
+                    create_run_bundles.push(CreateRunBundle {
+                        s3_app_path: Some(s3_app_path),
+                        s3_test_app_path,
+                    });
+                }
 
-        let bundles = if create_run_bundles.is_empty() {
+                for lib_bundle in android.library_bundle.iter().flatten() {
+                    let s3_test_app_path = self
+                        .upload_to_s3(lib_bundle.clone(), no_progress_bar)
+                        .await?;
+
+                    create_run_bundles.push(CreateRunBundle {
+                        s3_app_path: None,
+                        s3_test_app_path,
+                    });
+                }
+            }
+            PlatformRunSpec::iOS(ios) => {
+                s3_test_app_path = Some(
+                    self.upload_to_s3(ios.test_application.clone(), no_progress_bar)
+                        .await?,
+                );
+                s3_app_path = Some(
+                    self.upload_to_s3(ios.application.clone(), no_progress_bar)
+                        .await?,
+                );
+            }
+        }
+
+        Ok(UploadedBinaries {
+            s3_app_path,
+            s3_test_app_path,
+            bundles: create_run_bundles,
+        })
+    }
+
+    async fn submit_run(&self, spec: RunSpec, binaries: &UploadedBinaries) -> Result<String> {
+        let url = format!("{}/v2/run", self.base_url);
+
+        let bundles = if binaries.bundles.is_empty() {
             None
         } else {
-            Some(create_run_bundles)
+            Some(binaries.bundles.clone())
         };
 
-        let env_args_map = vec_to_hashmap(env_args)?;
-        let test_env_args_map = vec_to_hashmap(test_env_args)?;
-
-        let create_request = CreateRunRequest {
-            s3_test_app_path: s3_test_app_path.clone(),
-            platform: platform.clone(),
-            s3_app_path: s3_app_path.clone(),
-            analytics_read_only: analytics_read_only.clone(),
-            profiling: profiling,
-            mock_location: mock_location,
-            code_coverage: code_coverage.clone(),
-            concurrency_limit: concurrency_limit.clone(),
-            country: None,
-            device: device.clone(),
-            filtering_configuration: filtering_configuration
-                .map(|config| serde_json::to_string(&config).ok())
-                .flatten(),
-            flavor: flavor.clone(),
-            isolated: isolated.clone(),
-            language: None,
-            link: link.clone(),
-            name: name.clone(),
-            branch: branch.clone(),
-            os_version: os_version.clone(),
-            project: project.clone(),
-            pull_file_config: pull_file_config
-                .map(|config| serde_json::to_string(&config).ok())
-                .flatten(),
-            retry_quota_test_preventive: retry_quota_test_preventive.clone(),
-            retry_quota_test_reactive: retry_quota_test_reactive.clone(),
-            retry_quota_test_uncompleted: retry_quota_test_uncompleted.clone(),
-            system_image: system_image.clone(),
-            xcode_version: xcode_version.clone(),
-            test_timeout_default: test_timeout_default.clone(),
-            test_timeout_max: test_timeout_max.clone(),
-            env_args: env_args_map,
-            test_env_args: test_env_args_map,
-            bundles: bundles,
-            granted_permission: granted_permission.clone(),
-        };
+        let mut create_request = CreateRunRequest::from(spec);
+        create_request.s3_app_path = binaries.s3_app_path.clone();
+        create_request.s3_test_app_path = binaries.s3_test_app_path.clone();
+        create_request.bundles = bundles;
 
-        let response = self.client.post(url).json(&create_request).send().await?;
+        let response = self
+            .send_authorized(self.client.post(url).json(&create_request))
+            .await?;
         let response = api_error_adapter(response)
             .await?
             .json::<CreateRunResponse>()
@@ -302,11 +487,8 @@ impl RapiClient for RapiReqwestClient {
 
     async fn get_run(&self, id: &str) -> Result<TestRun> {
         let url = format!("{}/v1/run/{}", self.base_url, id);
-        let params = [("api_key", self.api_key.clone())];
-        let url = reqwest::Url::parse_with_params(&url, &params)
-            .map_err(|error| ApiError::InvalidParameters { error })?;
 
-        let response = self.client.get(url).send().await?;
+        let response = self.send_authorized(self.client.get(url)).await?;
         let response = api_error_adapter(response)
             .await?
             .json::<TestRun>()
@@ -315,15 +497,10 @@ impl RapiClient for RapiReqwestClient {
         Ok(response)
     }
 
-    async fn list_artifact(&self, jwt_token: &str, id: &str) -> Result<Vec<Artifact>> {
+    async fn list_artifact(&self, id: &str) -> Result<Vec<Artifact>> {
         let url = format!("{}/v1/artifact/{}", self.base_url, id);
 
-        let response = self
-            .client
-            .get(url)
-            .header("Authorization", format!("Bearer {}", jwt_token))
-            .send()
-            .await?;
+        let response = self.send_authorized(self.client.get(url)).await?;
         let response = api_error_adapter(response)
             .await?
             .json::<Vec<Artifact>>()
@@ -335,7 +512,6 @@ impl RapiClient for RapiReqwestClient {
 
     async fn download_artifact(
         &self,
-        jwt_token: &str,
         artifact: Artifact,
         base_path: PathBuf,
         run_id: &str,
@@ -353,12 +529,7 @@ impl RapiClient for RapiReqwestClient {
         let mut absolute_path = base_path.clone();
         absolute_path.push(relative_path);
 
-        let src = self
-            .client
-            .get(url)
-            .header("Authorization", format!("Bearer {}", jwt_token))
-            .send()
-            .await?;
+        let src = self.send_authorized(self.client.get(url)).await?;
 
         let mut src = api_error_adapter(src).await?.bytes_stream();
 
@@ -377,15 +548,10 @@ impl RapiClient for RapiReqwestClient {
         Ok(())
     }
 
-    async fn get_devices_android(&self, jwt_token: &str) -> Result<Vec<AndroidDevice>> {
+    async fn get_devices_android(&self) -> Result<Vec<AndroidDevice>> {
         let url = format!("{}/v1/devices/android", self.base_url);
 
-        let response = self
-            .client
-            .get(url)
-            .header("Authorization", format!("Bearer {}", jwt_token))
-            .send()
-            .await?;
+        let response = self.send_authorized(self.client.get(url)).await?;
         let response = api_error_adapter(response)
             .await?
             .json::<Vec<AndroidDevice>>()
@@ -394,9 +560,35 @@ impl RapiClient for RapiReqwestClient {
 
         Ok(response)
     }
+
+    async fn get_devices_ios(&self) -> Result<Vec<IosDevice>> {
+        let url = format!("{}/v1/devices/ios", self.base_url);
+
+        let response = self.send_authorized(self.client.get(url)).await?;
+        let response = api_error_adapter(response)
+            .await?
+            .json::<Vec<IosDevice>>()
+            .await
+            .map_err(|error| ApiError::DeserializationFailure { error })?;
+
+        Ok(response)
+    }
+
+    async fn get_device_constraints(&self) -> Result<DeviceConstraints> {
+        let url = format!("{}/v1/devices/constraints", self.base_url);
+
+        let response = self.send_authorized(self.client.get(url)).await?;
+        let response = api_error_adapter(response)
+            .await?
+            .json::<DeviceConstraints>()
+            .await
+            .map_err(|error| ApiError::DeserializationFailure { error })?;
+
+        Ok(response)
+    }
 }
 
-fn vec_to_hashmap(
+pub(crate) fn vec_to_hashmap(
     vec: Option<Vec<String>>,
 ) -> Result<Option<HashMap<String, String>>, EnvArgError> {
     match vec {
@@ -409,7 +601,7 @@ fn vec_to_hashmap(
                     let value = key_value
                         .get(1)
                         .map(|val| val.to_string())
-                        .unwrap_or_else(|| "".to_string());
+                        .unwrap_or_default();
                     if value.is_empty() {
                         return Err(EnvArgError::MissingValue {
                             env_arg: arg.clone(),
@@ -434,7 +626,7 @@ async fn api_error_adapter(response: reqwest::Response) -> Result<reqwest::Respo
         Err(error) => {
             //Strip sensitive information
             let error = error.without_url();
-            let body = response.text().await?;
+            let body = response.text().await.map_err(reqwest::Error::without_url)?;
             if let Some(status_code) = error.status() {
                 match status_code {
                     StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
@@ -454,97 +646,6 @@ async fn api_error_adapter(response: reqwest::Response) -> Result<reqwest::Respo
     }
 }
 
-async fn upload_to_s3(
-    client: &Client,
-    base_url_with_params: String,
-    api_key: String,
-    file_path: PathBuf,
-    no_progress_bar: bool,
-) -> Result<String> {
-    // Open file
-    let file = File::open(&file_path)
-        .await
-        .map_err(|error| InputError::OpenFileFailure {
-            path: file_path.clone(),
-            error,
-        })?;
-
-    // Extract filename from PathBuf
-    let file_name = file_path
-        .file_name()
-        .map(|val| val.to_string_lossy().to_string())
-        .ok_or(InputError::InvalidFileName {
-            path: file_path.clone(),
-        })?;
-
-    // Request upload URL
-    let url = format!("{}/v2/upload/presigned-url", base_url_with_params);
-    let params = [("api_key", api_key.clone())];
-    let url = reqwest::Url::parse_with_params(&url, &params)
-        .map_err(|error| ApiError::InvalidParameters { error })?;
-
-    let request_body = UploadRequest {
-        filename: file_name.to_string(),
-    };
-    let upload_url_response = client.post(url).json(&request_body).send().await?;
-    let upload_url_response = api_error_adapter(upload_url_response)
-        .await?
-        .json::<UploadUrlResponse>()
-        .await
-        .map_err(|error| ApiError::DeserializationFailure { error })?;
-
-    // Progress stuff
-    let file_total_size = file.metadata().await?.len();
-    let mut file_reader = ReaderStream::new(file);
-    let mut multi_progress: Option<MultiProgress> = if !no_progress_bar {
-        Some(MultiProgress::new())
-    } else {
-        None
-    };
-    let file_progress_bar;
-    let file_body;
-    if !no_progress_bar {
-        let sty = ProgressStyle::with_template(
-            "{spinner:.blue} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})"
-        )
-        .unwrap()
-        .progress_chars("#>-");
-
-        let pb = ProgressBar::new(file_total_size);
-        pb.enable_steady_tick(Duration::from_millis(80));
-        file_progress_bar = multi_progress.as_mut().unwrap().add(pb);
-        file_progress_bar.set_style(sty.clone());
-        let mut file_progress = 0u64;
-        let file_stream = async_stream::stream! {
-            while let Some(chunk) = file_reader.next().await {
-                let file_progress_bar = file_progress_bar.clone();
-                if let Ok(chunk) = &chunk {
-                    let new = min(file_progress + (chunk.len() as u64), file_total_size);
-                    file_progress = new;
-                    file_progress_bar.set_position(new);
-                    if file_progress >= file_total_size {
-                        file_progress_bar.finish_and_clear();
-                    }
-                }
-                yield chunk;
-            }
-        };
-        file_body = Body::wrap_stream(file_stream);
-    } else {
-        file_body = Body::wrap_stream(file_reader);
-    }
-
-    let s3_response = client
-        .put(upload_url_response.url.clone())
-        .header("Content-Length", file_total_size)
-        .body(file_body)
-        .send()
-        .await?;
-    api_error_adapter(s3_response).await?;
-
-    Ok(upload_url_response.file_path.clone())
-}
-
 #[derive(Serialize, Deserialize, Debug)]
 struct UploadRequest {
     filename: String,
@@ -624,7 +725,82 @@ struct CreateRunRequest {
     granted_permission: Option<Vec<String>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl From<RunSpec> for CreateRunRequest {
+    fn from(spec: RunSpec) -> Self {
+        let mut request = CreateRunRequest {
+            platform: spec.platform.to_string(),
+            s3_test_app_path: None,
+            s3_app_path: None,
+            analytics_read_only: spec.analytics_read_only,
+            profiling: false,
+            mock_location: false,
+            code_coverage: spec.code_coverage,
+            concurrency_limit: spec.concurrency_limit,
+            country: None,
+            device: None,
+            filtering_configuration: spec
+                .filtering_configuration
+                .and_then(|config| serde_json::to_string(&config).ok()),
+            flavor: None,
+            isolated: spec.isolated,
+            language: None,
+            link: spec.link,
+            name: spec.name,
+            branch: spec.branch,
+            os_version: None,
+            project: spec.project,
+            pull_file_config: None,
+            retry_quota_test_preventive: spec.retry_quota_test_preventive,
+            retry_quota_test_reactive: spec.retry_quota_test_reactive,
+            retry_quota_test_uncompleted: spec.retry_quota_test_uncompleted,
+            system_image: None,
+            xcode_version: None,
+            test_timeout_default: spec.test_timeout_default,
+            test_timeout_max: spec.test_timeout_max,
+            env_args: None,
+            test_env_args: None,
+            bundles: None,
+            granted_permission: None,
+        };
+
+        match spec.platform {
+            PlatformRunSpec::Android(android) => {
+                request.profiling = android.profiling;
+                request.mock_location = android.mock_location;
+                request.device = android.device;
+                request.flavor = android.flavor;
+                request.os_version = android.os_version;
+                request.system_image = android.system_image;
+                request.env_args = android.instrumentation_args;
+                request.pull_file_config = android
+                    .pull_file_config
+                    .and_then(|config| serde_json::to_string(&config).ok());
+            }
+            PlatformRunSpec::iOS(ios) => {
+                request.device = ios.device;
+                request.os_version = ios.os_version;
+                request.xcode_version = ios.xcode_version;
+                request.env_args = ios.xctestrun_env;
+                request.test_env_args = ios.xctestrun_test_env;
+                request.granted_permission = ios.granted_permission;
+                request.language = ios.language;
+                request.country = ios.country;
+            }
+        }
+
+        request
+    }
+}
+
+/// Storage paths of uploaded binaries, see [`RapiClient::upload_binaries`]
+#[derive(Clone, Debug)]
+pub struct UploadedBinaries {
+    s3_app_path: Option<String>,
+    s3_test_app_path: Option<String>,
+    bundles: Vec<CreateRunBundle>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct CreateRunBundle {
     #[serde(rename = "s3_test_app_path")]
     s3_test_app_path: String,
@@ -693,11 +869,82 @@ pub struct AndroidDevice {
     pub dpi: u32,
 }
 
+/// Supported combination of simulator device, iOS runtime and Xcode
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct IosDevice {
+    #[serde(rename = "device")]
+    pub device: String,
+    #[serde(rename = "os_version")]
+    pub os_version: String,
+    #[serde(rename = "xcode_version")]
+    pub xcode_version: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::collections::HashMap;
 
+    fn tls_fixture(name: &str) -> PathBuf {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        Path::new(&manifest_dir)
+            .join("fixture")
+            .join("tls")
+            .join(name)
+    }
+
+    #[test]
+    fn test_with_config_tls() {
+        let config = ClientConfig {
+            ca_cert: Some(tls_fixture("client.crt")),
+            client_cert: Some(tls_fixture("client.crt")),
+            client_key: Some(tls_fixture("client.key")),
+            connect_timeout: Some(Duration::from_secs(5)),
+            read_timeout: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+
+        let result = RapiReqwestClient::with_config("https://localhost/api", "key", &config);
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_with_config_missing_ca_cert() {
+        let config = ClientConfig {
+            ca_cert: Some(tls_fixture("missing.crt")),
+            ..Default::default()
+        };
+
+        let result = RapiReqwestClient::with_config("https://localhost/api", "key", &config);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_with_config_client_cert_without_key() {
+        let config = ClientConfig {
+            client_cert: Some(tls_fixture("client.crt")),
+            ..Default::default()
+        };
+
+        let result = RapiReqwestClient::with_config("https://localhost/api", "key", &config);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_with_config_invalid_proxy() {
+        let config = ClientConfig {
+            proxy: Some("not a url".into()),
+            ..Default::default()
+        };
+
+        let result = RapiReqwestClient::with_config("https://localhost/api", "key", &config);
+
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_vec_to_hashmap_valid_input() {
         let input = Some(vec![
@@ -2,42 +2,44 @@ use serde_json::Value;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::Path;
-use std::path::PathBuf;
 
 use ::futures::{stream, StreamExt, TryStreamExt};
 use anyhow::Result;
 use indicatif::ProgressBar;
 use log::debug;
 
-use crate::api::{Artifact, RapiClient, RapiReqwestClient};
+use crate::api::{Artifact, RapiClient};
 use crate::errors::ArtifactError;
 
-pub async fn fetch_artifact_list(
-    client: &RapiReqwestClient,
-    id: &str,
-    token: &str,
-) -> Result<Vec<Artifact>> {
+const DOWNLOAD_RETRIES: u32 = 3;
+
+/// Recursively lists all files produced by the run
+pub async fn fetch_artifact_list<C>(client: &C, id: &str) -> Result<Vec<Artifact>>
+where
+    C: RapiClient + Clone + Send + Sync + 'static,
+{
     let mut artifacts: Vec<Artifact> = Vec::new();
     let mut list: Vec<String> = vec![id.to_owned()];
 
     loop {
-        let stats: Vec<Artifact> = stream::iter(list.clone().into_iter())
+        let stats: Vec<Result<Vec<Artifact>>> = stream::iter(list.clone())
             .map(|dir| {
                 let client = client.clone();
-                let token = token.to_owned();
-                tokio::spawn(async move { client.list_artifact(&token, &dir).await.unwrap() })
+                tokio::spawn(async move { client.list_artifact(&dir).await })
             })
             .buffer_unordered(num_cpus::get())
-            .try_concat()
+            .try_collect()
             .await
             .map_err(|error| ArtifactError::ListFailed { error })?;
 
         list.clear();
         for f in stats {
-            if f.is_file {
-                artifacts.push(f);
-            } else {
-                list.push(f.id);
+            for f in f? {
+                if f.is_file {
+                    artifacts.push(f);
+                } else {
+                    list.push(f.id);
+                }
             }
         }
 
@@ -49,14 +51,17 @@ pub async fn fetch_artifact_list(
     Ok(artifacts)
 }
 
-pub async fn download_artifacts(
-    client: &RapiReqwestClient,
+/// Downloads artifacts into `path`, each file is retried up to 3 times
+pub async fn download_artifacts<C>(
+    client: &C,
     run_id: &str,
     artifacts: Vec<Artifact>,
-    path: &PathBuf,
-    token: &str,
+    path: &Path,
     no_progress_bar: bool,
-) -> Result<()> {
+) -> Result<()>
+where
+    C: RapiClient + Clone + Send + Sync + 'static,
+{
     debug!("Downloading {} artifacts:", artifacts.len());
 
     artifacts.iter().for_each(|f| debug!("{}", f.id));
@@ -66,38 +71,36 @@ pub async fn download_artifacts(
         progress_bar = Some(ProgressBar::new(artifacts.len() as u64))
     }
 
-    stream::iter(artifacts.into_iter())
+    let results: Vec<Result<(), ArtifactError>> = stream::iter(artifacts)
         .map(|artifact| {
             let client = client.clone();
-            let token = token.to_owned();
-            let base_path = path.clone();
+            let base_path = path.to_path_buf();
             let run_id = run_id.to_owned().clone();
             let progress_bar = progress_bar.clone();
             tokio::spawn(async move {
-                for _try in 1..=3 {
-                    let download_result = &client
-                        .download_artifact(&token, artifact.clone(), base_path.clone(), &run_id)
+                let mut last_error = None;
+                for _try in 1..=DOWNLOAD_RETRIES {
+                    let download_result = client
+                        .download_artifact(artifact.clone(), base_path.clone(), &run_id)
                         .await;
                     match download_result {
                         Ok(_) => {
                             if let Some(progress_bar) = progress_bar {
                                 progress_bar.inc(1);
                             }
-                            return;
+                            return Ok(());
                         }
                         Err(error) => {
-                            if _try < 4 {
-                                debug!("Error fetching {}, retrying", artifact.id);
-                                continue;
-                            } else {
-                                panic!(
-                                    "Error fetching {}. All {} retries failed. {}",
-                                    artifact.id, 3, error
-                                );
-                            }
+                            debug!("Error fetching {}, retrying", artifact.id);
+                            last_error = Some(error);
                         }
                     }
                 }
+                Err(ArtifactError::RetriesExhausted {
+                    id: artifact.id,
+                    retries: DOWNLOAD_RETRIES,
+                    message: last_error.map(|e| e.to_string()).unwrap_or_default(),
+                })
             })
         })
         .buffer_unordered(num_cpus::get())
@@ -105,12 +108,17 @@ pub async fn download_artifacts(
         .await
         .map_err(|error| ArtifactError::DownloadFailed { error })?;
 
+    results
+        .into_iter()
+        .collect::<Result<Vec<()>, ArtifactError>>()?;
+
     if let Some(progress_bar) = progress_bar {
         progress_bar.finish_with_message("done");
     }
     Ok(())
 }
 
+/// Rewrites attachment paths of the Allure results in `output` so that the report works locally
 pub async fn patch_allure_paths(output: &Path) -> Result<()> {
     // Define the required path
     let required_path = output.join("report/allure-results");
@@ -122,21 +130,16 @@ pub async fn patch_allure_paths(output: &Path) -> Result<()> {
     }
 
     // Iterate over each file in the required path
-    match fs::read_dir(&required_path) {
-        Ok(entries) => {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("json") {
-                        if let Err(e) = patch_file(&path).await {
-                            panic!("Failed to patch file {:?}: {}", path, e);
-                        }
-                    }
-                }
-            }
-        }
-        Err(e) => {
-            panic!("Failed to read directory {:?}: {}", required_path, e);
+    let entries = fs::read_dir(&required_path).map_err(|error| ArtifactError::PatchFailed {
+        path: required_path.clone(),
+        error,
+    })?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("json") {
+            patch_file(&path)
+                .await
+                .map_err(|error| ArtifactError::PatchFailed { path, error })?;
         }
     }
     Ok(())
@@ -144,7 +147,7 @@ pub async fn patch_allure_paths(output: &Path) -> Result<()> {
 
 async fn patch_file(path: &Path) -> io::Result<()> {
     // Read the JSON file
-    let mut file = File::open(&path)?;
+    let mut file = File::open(path)?;
     let mut content = String::new();
     file.read_to_string(&mut content)?;
 
@@ -173,7 +176,7 @@ async fn patch_file(path: &Path) -> io::Result<()> {
     }
 
     // Write the patched JSON back to the file
-    let mut file = File::create(&path)?;
+    let mut file = File::create(path)?;
     file.write_all(serde_json::to_string_pretty(&json_value)?.as_bytes())?;
     file.flush()?;
 
@@ -1,7 +1,7 @@
 use crate::errors::InputError;
 use std::path::PathBuf;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ApplicationBundle {
     pub app_path: PathBuf,
     pub test_app_path: PathBuf,
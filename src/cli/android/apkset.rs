@@ -0,0 +1,237 @@
+//! `.apks` sets and `.aab` bundles are turned into a single installable APK before upload.
+use std::{
+    ffi::OsStr,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use async_zip::tokio::read::fs::ZipFileReader;
+use tokio::{fs::File, process::Command};
+use tokio_util::compat::TokioAsyncWriteCompatExt;
+
+use crate::{
+    cli::validate::api_level,
+    errors::{ConfigurationError, InputError},
+};
+
+const UNIVERSAL_APK: &str = "universal.apk";
+const STANDALONE_PREFIX: &str = "standalones/standalone-";
+const SPLITS_PREFIX: &str = "splits/";
+/// Runs happen on emulators, ARM only native code is skipped
+const EMULATOR_ABIS: [&str; 2] = ["x86_64", "x86"];
+const ABI_PREFIXES: [&str; 3] = ["arm", "x86", "mips"];
+/// Older devices can't install split APKs, only standalone ones
+const SPLITS_MIN_SDK_VERSION: u32 = 21;
+
+/// Device an APK is selected for
+#[derive(Debug, Default)]
+pub(crate) struct DeviceSpec {
+    /// Lowest API level among the `--os-version` of the runs, `None` for the server default
+    pub sdk_version: Option<u32>,
+}
+
+impl DeviceSpec {
+    pub fn new(os_versions: &[Option<String>]) -> Self {
+        Self {
+            sdk_version: os_versions
+                .iter()
+                .flatten()
+                .filter_map(|os_version| api_level(os_version))
+                .min(),
+        }
+    }
+}
+
+/// Returns `path` unless it's an `.apks` or `.aab`, which are converted into an APK inside `workdir`
+pub(crate) async fn ensure_apk(
+    path: PathBuf,
+    spec: &DeviceSpec,
+    workdir: &Path,
+) -> Result<PathBuf> {
+    match path.extension().and_then(OsStr::to_str) {
+        Some("apks") => extract_apk(&path, spec, workdir).await,
+        Some("aab") => {
+            let apks = build_apks(&path, workdir).await?;
+            extract_apk(&apks, spec, workdir).await
+        }
+        _ => Ok(path),
+    }
+}
+
+/// Runs `bundletool build-apks --mode=universal`, the universal APK installs on any device
+/// so the device spec isn't needed
+async fn build_apks(bundle: &Path, workdir: &Path) -> Result<PathBuf> {
+    let output = tempfile::tempdir_in(workdir)?.into_path().join(
+        bundle
+            .with_extension("apks")
+            .file_name()
+            .unwrap_or_default(),
+    );
+    let mut command = match std::env::var_os("BUNDLETOOL") {
+        Some(bundletool) if Path::new(&bundletool).extension() == Some(OsStr::new("jar")) => {
+            let mut command = Command::new("java");
+            command.arg("-jar").arg(bundletool);
+            command
+        }
+        Some(bundletool) => Command::new(bundletool),
+        None => Command::new("bundletool"),
+    };
+    command
+        .args(["build-apks", "--mode=universal", "--bundle"])
+        .arg(bundle)
+        .arg("--output")
+        .arg(&output);
+
+    let result = match command.output().await {
+        Ok(result) => result,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            anyhow::bail!(ConfigurationError::BundletoolNotFound {
+                path: bundle.to_path_buf(),
+            })
+        }
+        Err(error) => return Err(error.into()),
+    };
+    if !result.status.success() {
+        anyhow::bail!(ConfigurationError::BundletoolFailed {
+            path: bundle.to_path_buf(),
+            message: String::from_utf8_lossy(&result.stderr).trim().to_owned(),
+        });
+    }
+    Ok(output)
+}
+
+async fn extract_apk(apks: &Path, spec: &DeviceSpec, workdir: &Path) -> Result<PathBuf> {
+    let invalid = |message: String| InputError::InvalidApkSet {
+        path: apks.to_path_buf(),
+        message,
+    };
+    let reader = ZipFileReader::new(apks)
+        .await
+        .map_err(|error| invalid(error.to_string()))?;
+    let entries = reader
+        .file()
+        .entries()
+        .iter()
+        .map(|entry| entry.filename().as_str().map(str::to_owned))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| invalid(error.to_string()))?;
+    let index = select_apk(&entries, spec).map_err(|message| {
+        invalid(format!(
+            "{}, build it with `bundletool build-apks --mode=universal`",
+            message
+        ))
+    })?;
+
+    let output = tempfile::tempdir_in(workdir)?
+        .into_path()
+        .join(apks.with_extension("apk").file_name().unwrap_or_default());
+    let mut entry = reader
+        .reader_with_entry(index)
+        .await
+        .map_err(|error| invalid(error.to_string()))?;
+    let mut file = File::create(&output).await?.compat_write();
+    futures::io::copy(&mut entry, &mut file).await?;
+    Ok(output)
+}
+
+/// Index of the universal APK, or of a standalone APK when the device can't install splits.
+/// Split APKs aren't merged, a run installs a single application APK.
+fn select_apk(entries: &[String], spec: &DeviceSpec) -> Result<usize, &'static str> {
+    if let Some(index) = entries.iter().position(|name| name == UNIVERSAL_APK) {
+        return Ok(index);
+    }
+    let has_splits = entries.iter().any(|name| name.starts_with(SPLITS_PREFIX));
+    let needs_standalone = spec
+        .sdk_version
+        .is_some_and(|sdk_version| sdk_version < SPLITS_MIN_SDK_VERSION);
+    if has_splits && !needs_standalone {
+        return Err("it has no universal APK and its split APKs can't be uploaded as a single APK, standalone APKs are only used for --os-version below 5.0");
+    }
+    entries
+        .iter()
+        .enumerate()
+        .filter_map(|(index, name)| {
+            let variant = name.strip_prefix(STANDALONE_PREFIX)?.strip_suffix(".apk")?;
+            Some((abi_rank(variant)?, index))
+        })
+        .min()
+        .map(|(_, index)| index)
+        .ok_or("it has neither a universal APK nor a standalone APK for x86 emulators")
+}
+
+/// Preference of a standalone variant like `x86_64_hdpi`, APKs without native code come last
+fn abi_rank(variant: &str) -> Option<usize> {
+    let emulator_abi = EMULATOR_ABIS.iter().position(|abi| {
+        variant.strip_prefix(abi).is_some_and(|rest| {
+            rest.is_empty() || (rest.starts_with('_') && !rest.starts_with("_64"))
+        })
+    });
+    match emulator_abi {
+        Some(rank) => Some(rank),
+        None if ABI_PREFIXES.iter().any(|abi| variant.starts_with(abi)) => None,
+        None => Some(EMULATOR_ABIS.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_device_spec() {
+        let spec = DeviceSpec::new(&[Some("14".into()), None, Some("8.1".into())]);
+        assert_eq!(spec.sdk_version, Some(27));
+        assert_eq!(DeviceSpec::new(&[None]).sdk_version, None);
+    }
+
+    #[test]
+    fn test_select_universal_apk() {
+        let names = entries(&["toc.pb", "splits/base-master.apk", "universal.apk"]);
+        assert_eq!(select_apk(&names, &DeviceSpec::default()), Ok(2));
+    }
+
+    #[test]
+    fn test_select_standalone_apk() {
+        let names = entries(&[
+            "toc.pb",
+            "splits/base-master.apk",
+            "standalones/standalone-armeabi_v7a_hdpi.apk",
+            "standalones/standalone-x86_hdpi.apk",
+            "standalones/standalone-x86_64_hdpi.apk",
+        ]);
+        let legacy = DeviceSpec {
+            sdk_version: Some(19),
+        };
+        assert_eq!(select_apk(&names, &legacy), Ok(4));
+        assert_eq!(select_apk(&names[..4], &legacy), Ok(3));
+        assert!(select_apk(&names[..3], &legacy).is_err());
+        assert!(select_apk(&names, &DeviceSpec::default()).is_err());
+    }
+
+    #[test]
+    fn test_select_split_only_apk_set() {
+        let names = entries(&[
+            "toc.pb",
+            "splits/base-master.apk",
+            "splits/base-x86_64.apk",
+            "splits/base-xxhdpi.apk",
+        ]);
+        let error = select_apk(&names, &DeviceSpec::default()).unwrap_err();
+        assert!(error.contains("split APKs"), "{}", error);
+        let lollipop = DeviceSpec {
+            sdk_version: Some(21),
+        };
+        assert_eq!(select_apk(&names, &lollipop), Err(error));
+        let legacy = DeviceSpec {
+            sdk_version: Some(19),
+        };
+        assert!(select_apk(&names, &legacy)
+            .unwrap_err()
+            .contains("standalone"));
+    }
+}
@@ -1,18 +1,22 @@
-use crate::{errors::InputError, pull::parse_pull_args};
+mod apkset;
+
+use crate::pull::parse_pull_args;
 use anyhow::Result;
-use std::{fmt::Display, path::PathBuf};
+use std::fmt::Display;
+
+use apkset::{ensure_apk, DeviceSpec};
 
 use crate::{
     bundle,
-    cli::{self, AnalyticsArgs, ApiArgs, CommonRunArgs, RetryArgs},
-    errors::ConfigurationError,
+    cli::{self, AndroidRunArgs},
+    constraints::{self, DeviceConstraints},
     filtering,
-    interactor::TriggerTestRunInteractor,
+    interactor::{TriggerMatrixRunInteractor, TriggerTestRunInteractor},
+    matrix,
     pull::PullFileConfig,
+    spec::{AndroidRunSpec, PlatformRunSpec, RunSpec},
 };
 
-use super::ProfilingArgs;
-
 #[derive(Debug, clap::ValueEnum, Clone)]
 pub enum SystemImage {
     #[clap(name = "default")]
@@ -30,35 +34,6 @@ impl Display for SystemImage {
     }
 }
 
-#[derive(Debug, clap::ValueEnum, Clone)]
-pub enum OsVersion {
-    #[clap(name = "10")]
-    Android10,
-    #[clap(name = "11")]
-    Android11,
-    #[clap(name = "12")]
-    Android12,
-    #[clap(name = "13")]
-    Android13,
-    #[clap(name = "14")]
-    Android14,
-    #[clap(name = "15")]
-    Android15,
-}
-
-impl Display for OsVersion {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            OsVersion::Android10 => f.write_str("10"),
-            OsVersion::Android11 => f.write_str("11"),
-            OsVersion::Android12 => f.write_str("12"),
-            OsVersion::Android13 => f.write_str("13"),
-            OsVersion::Android14 => f.write_str("14"),
-            OsVersion::Android15 => f.write_str("15"),
-        }
-    }
-}
-
 #[derive(Debug, clap::ValueEnum, Clone)]
 pub enum Flavor {
     #[clap(name = "native")]
@@ -79,173 +54,135 @@ impl Display for Flavor {
     }
 }
 
-pub(crate) async fn run(
-    application: Option<std::path::PathBuf>,
-    test_application: Option<std::path::PathBuf>,
-    os_version: Option<OsVersion>,
-    system_image: Option<SystemImage>,
+type AndroidParameters = (
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// Fills the dimensions that have a single supported value, others are left to the server defaults
+pub(crate) fn infer_parameters(
+    constraints: &DeviceConstraints,
     device: Option<String>,
-    common: CommonRunArgs,
-    api_args: ApiArgs,
-    flavor: Option<Flavor>,
-    instrumentation_arg: Option<Vec<String>>,
-    retry_args: RetryArgs,
-    analytics_args: AnalyticsArgs,
-    profiling_args: ProfilingArgs,
-    pull_files: Option<Vec<String>>,
-    application_bundle: Option<Vec<String>>,
-    library_bundle: Option<Vec<PathBuf>>,
-    mock_location: bool,
-) -> Result<bool> {
-    if application.is_none()
-        && test_application.is_none()
-        && application_bundle.is_none()
-        && library_bundle.is_none()
-    {
-        return Err(ConfigurationError::UnsupportedRunConfiguration {
-            message:
-                "Please set up APKs for testing. The following argument combinations are possible:
---application <APPLICATION> --test-application <TEST_APPLICATION> - for application testing
---application-bundle <APPLICATION>,<TEST_APPLICATION> - advanced mode that allows setting up one or more application bundles for testing
---library-bundle <TEST_APPLICATION> - advanced mode that allows setting up one or more library bundles for testing"
-                    .into(),
-        }
-        .into());
-    }
+    flavor: Option<String>,
+    system_image: Option<String>,
+    os_version: Option<String>,
+) -> Result<AndroidParameters> {
+    let inference = constraints::infer(
+        &constraints.android,
+        &constraints::ANDROID_DIMENSIONS,
+        &[device, flavor, system_image, os_version],
+    )?;
+    let [device, flavor, system_image, os_version] =
+        <[Option<String>; 4]>::try_from(inference.values).expect("a value for each dimension");
+    Ok((device, flavor, system_image, os_version))
+}
 
-    if application.is_some()
-        && test_application.is_none()
-        && application_bundle.is_none()
-        && library_bundle.is_none()
-    {
-        return Err(ConfigurationError::UnsupportedRunConfiguration {
-            message: "Please set up Testing APK:
---test-application <TEST_APPLICATION>"
-                .into(),
-        }
-        .into());
-    }
+pub(crate) async fn run(args: AndroidRunArgs) -> Result<bool> {
+    let AndroidRunArgs {
+        application,
+        test_application,
+        os_version,
+        system_image,
+        device,
+        flavor,
+        common,
+        api_args,
+        retry_args,
+        analytics_args,
+        profiling_args,
+        instrumentation_arg,
+        pull_files,
+        application_bundle,
+        library_bundle,
+        mock_location,
+    } = args;
 
-    if application.is_none()
-        && test_application.is_some()
-        && application_bundle.is_none()
-        && library_bundle.is_none()
+    let client = api_args.client()?;
+    let cells = matrix::expand(
+        &common.matrix,
+        &constraints::ANDROID_DIMENSIONS,
+        &[
+            device,
+            flavor.map(|x| x.to_string()),
+            system_image.map(|x| x.to_string()),
+            os_version,
+        ],
+    )?;
+    let constraints = if cells
+        .iter()
+        .flat_map(|(_, values)| values)
+        .any(Option::is_some)
     {
-        return Err(ConfigurationError::UnsupportedRunConfiguration {
-            message: "Please set up Application APK:
---application <TEST_APPLICATION>
-If you are interesting in library testing then please use advance mode with --library-bundle argument"
-                .into(),
-        }
-        .into());
-    }
-
-    if let Some(bundles) = &application_bundle {
-        if bundles.len() > 1 && mock_location {
-            return Err(ConfigurationError::UnsupportedRunConfiguration {
-                message: "Mock location access doesn't support multiple application bundles".into(),
-            }
-            .into());
-        }
-    }
-
-    if application_bundle.is_none() && application.is_none() && mock_location {
-        return Err(ConfigurationError::UnsupportedRunConfiguration {
-            message: "There is no Application where mock location can be used".into(),
-        }
-        .into());
-    }
-
-    match (device.as_deref(), &flavor, &system_image, &os_version) {
-        (Some("watch"), _, Some(SystemImage::Default) | None, Some(_) | None)
-        | (
-            Some("watch"),
-            _,
-            Some(_),
-            Some(OsVersion::Android10)
-            | Some(OsVersion::Android12)
-            | Some(OsVersion::Android14)
-            | Some(OsVersion::Android15),
-        ) => {
-            return Err(ConfigurationError::UnsupportedRunConfiguration {
-                message:
-                    "Android Watch only supports google-apis system image and os versions 11 and 13"
-                        .into(),
-            }
-            .into());
-        }
-        (Some("tv"), _, Some(SystemImage::Default), Some(_) | None) => {
-            return Err(ConfigurationError::UnsupportedRunConfiguration {
-                message: "Android TV only supports google-apis system image".into(),
-            }
-            .into());
-        }
-        (Some("tv"), _, _, Some(OsVersion::Android15)) => {
-            return Err(ConfigurationError::UnsupportedRunConfiguration {
-                message: "Android TV doesn't support os version 15".into(),
-            }
-            .into());
-        }
-        (Some("wear"), _, _, Some(OsVersion::Android15)) => {
-            return Err(ConfigurationError::UnsupportedRunConfiguration {
-                message: "Android Wear doesn't support os version 15".into(),
-            }
-            .into());
-        }
-        (
-            Some("tv") | Some("watch"),
-            Some(Flavor::JsJestAppium) | Some(Flavor::PythonRobotFrameworkAppium),
-            _,
-            _,
-        ) => {
-            return Err(ConfigurationError::UnsupportedRunConfiguration {
-                message:
-                    "js-jest-appium and python-robotframework-appium only support 'phone' devices"
-                        .into(),
-            }
-            .into());
-        }
-        (_, _, Some(SystemImage::Default) | None, Some(OsVersion::Android15)) => {
-            return Err(ConfigurationError::UnsupportedRunConfiguration {
-                message: "Android OS version 15 only supports google-apis system image".into(),
+        Some(DeviceConstraints::load(&client).await)
+    } else {
+        None
+    };
+    let mut configurations = Vec::with_capacity(cells.len());
+    for (cell, values) in cells {
+        let [device, flavor, system_image, os_version] =
+            <[Option<String>; 4]>::try_from(values).expect("a value for each dimension");
+        let parameters = match &constraints {
+            Some(constraints) => {
+                infer_parameters(constraints, device, flavor, system_image, os_version)?
             }
-            .into());
-        }
-        _ => {}
+            None => (None, None, None, None),
+        };
+        configurations.push((cell, parameters));
     }
 
-    if let Some(app_path) = application.clone() {
-        if !app_path.exists() {
-            return Err(InputError::InvalidFileName { path: app_path })?;
-        }
-    }
-
-    if let Some(app_path) = test_application.clone() {
-        if !app_path.exists() {
-            return Err(InputError::InvalidFileName { path: app_path })?;
-        }
-    }
+    let os_versions: Vec<_> = configurations
+        .iter()
+        .map(|(_, (_, _, _, os_version))| os_version.clone())
+        .collect();
+    let device_spec = DeviceSpec::new(&os_versions);
+    // Holds APKs converted from .apks and .aab inputs until they're uploaded
+    let workdir = tempfile::tempdir()?;
+    let workdir = workdir.path();
 
+    let application = match application {
+        Some(path) => Some(ensure_apk(path, &device_spec, workdir).await?),
+        None => None,
+    };
+    let test_application = match test_application {
+        Some(path) => Some(ensure_apk(path, &device_spec, workdir).await?),
+        None => None,
+    };
     let mut transformed_application_bundle = None;
     if let Some(application_bundle) = application_bundle {
-        transformed_application_bundle =
-            Some(bundle::transform_and_validate_bundle(application_bundle)?);
+        let mut bundles = bundle::transform_and_validate_bundle(application_bundle)?;
+        for bundle in &mut bundles {
+            bundle.app_path = ensure_apk(bundle.app_path.clone(), &device_spec, workdir).await?;
+            bundle.test_app_path =
+                ensure_apk(bundle.test_app_path.clone(), &device_spec, workdir).await?;
+        }
+        transformed_application_bundle = Some(bundles);
     }
-
-    if let Some(lib_bundles) = library_bundle.clone() {
-        for bundle in lib_bundles {
-            if !bundle.exists() {
-                return Err(InputError::InvalidFileName { path: bundle })?;
+    let library_bundle = match library_bundle {
+        Some(paths) => {
+            let mut converted = Vec::with_capacity(paths.len());
+            for path in paths {
+                converted.push(ensure_apk(path, &device_spec, workdir).await?);
             }
+            Some(converted)
         }
-    }
-
-    let filter_file = common.filter_file.map(filtering::convert::convert);
-    let filtering_configuration = match filter_file {
-        Some(future) => Some(future.await?),
         None => None,
     };
 
+    let filtering_configuration = if common.filter_file.is_empty() {
+        None
+    } else {
+        Some(filtering::convert::convert_all(common.filter_file).await?)
+    };
+    let filtering_configuration = filtering::convert::with_inline_filters(
+        filtering_configuration,
+        common.inline_filter_args.into(),
+    )
+    .await?;
+    let filtering_configuration =
+        filtering::convert::with_shard(filtering_configuration, common.shard);
+
     let retry_args = cli::validate::retry_args(retry_args);
     cli::validate::result_file_args(&common.result_file_args)?;
 
@@ -254,13 +191,34 @@ If you are interesting in library testing then please use advance mode with --li
         None => None,
     };
 
-    if let Some(limit) = common.concurrency_limit {
-        if limit == 0 {
-            return Err(InputError::NonPositiveValue {
-                arg: "--concurrency-limit".to_owned(),
-            })?;
-        }
+    let android = AndroidRunSpec {
+        application,
+        test_application,
+        application_bundle: transformed_application_bundle,
+        library_bundle,
+        pull_file_config,
+        profiling: profiling_args.profiling,
+        mock_location,
+        ..Default::default()
     }
+    .with_instrumentation_args(instrumentation_arg)?;
+
+    cli::validate::android_binaries(&android, &os_versions).await?;
+
+    let spec = RunSpec::builder(android)
+        .name(common.name)
+        .link(common.link)
+        .branch(common.branch)
+        .project(common.project)
+        .isolated(common.isolated)
+        .code_coverage(common.code_coverage)
+        .retry_quota_test_uncompleted(retry_args.retry_quota_test_uncompleted)
+        .retry_quota_test_preventive(retry_args.retry_quota_test_preventive)
+        .retry_quota_test_reactive(retry_args.retry_quota_test_reactive)
+        .analytics_read_only(analytics_args.analytics_read_only)
+        .filtering_configuration(filtering_configuration)
+        .concurrency_limit(common.concurrency_limit)
+        .build()?;
 
     let present_wait: bool = match common.wait {
         None => true,
@@ -268,45 +226,57 @@ If you are interesting in library testing then please use advance mode with --li
         Some(false) => false,
     };
 
-    TriggerTestRunInteractor {}
+    if common.matrix.is_empty() {
+        let (_, (device, flavor, system_image, os_version)) = configurations.remove(0);
+        let spec = with_device(&spec, device, flavor, system_image, os_version);
+        return TriggerTestRunInteractor {}
+            .execute(
+                &client,
+                spec,
+                present_wait,
+                common.ignore_test_failures,
+                &common.output,
+                common.progress_args.no_progress_bars,
+                common.result_file_args.result_file,
+            )
+            .await;
+    }
+
+    let runs = configurations
+        .into_iter()
+        .map(|(cell, (device, flavor, system_image, os_version))| {
+            (
+                cell,
+                with_device(&spec, device, flavor, system_image, os_version),
+            )
+        })
+        .collect();
+    TriggerMatrixRunInteractor {}
         .execute(
-            &api_args.base_url,
-            &api_args.api_key,
-            common.name,
-            common.link,
-            common.branch,
+            &client,
+            runs,
             present_wait,
-            common.isolated,
             common.ignore_test_failures,
-            common.code_coverage,
-            retry_args.retry_quota_test_uncompleted,
-            retry_args.retry_quota_test_preventive,
-            retry_args.retry_quota_test_reactive,
-            analytics_args.analytics_read_only,
-            profiling_args.profiling,
-            mock_location,
-            filtering_configuration,
             &common.output,
-            application,
-            test_application,
-            os_version.map(|x| x.to_string()),
-            system_image.map(|x| x.to_string()),
-            device,
-            None,
-            flavor.map(|x| x.to_string()),
-            "Android".to_owned(),
             common.progress_args.no_progress_bars,
             common.result_file_args.result_file,
-            instrumentation_arg,
-            None,
-            pull_file_config,
-            common.concurrency_limit,
-            None,
-            None,
-            common.project,
-            transformed_application_bundle,
-            library_bundle,
-            None,
         )
         .await
 }
+
+fn with_device(
+    spec: &RunSpec,
+    device: Option<String>,
+    flavor: Option<String>,
+    system_image: Option<String>,
+    os_version: Option<String>,
+) -> RunSpec {
+    let mut spec = spec.clone();
+    if let PlatformRunSpec::Android(android) = &mut spec.platform {
+        android.device = device;
+        android.flavor = flavor;
+        android.system_image = system_image;
+        android.os_version = os_version;
+    }
+    spec
+}
@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use tokio::fs;
+
+use crate::{
+    errors::InputError,
+    filtering::{
+        self,
+        evaluate::{Evaluation, Verdict},
+    },
+};
+
+use super::inventory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum FilterFormat {
+    Marathon,
+    Xctestplan,
+}
+
+pub(crate) async fn convert(
+    from: FilterFormat,
+    to: FilterFormat,
+    input: PathBuf,
+    output: Option<PathBuf>,
+    target_names: Vec<String>,
+) -> Result<bool> {
+    match (from, to) {
+        (FilterFormat::Xctestplan, FilterFormat::Marathon) => {
+            let marathonfile = filtering::convert::convert_xctestplan(input, target_names).await?;
+            let yaml = serde_yaml::to_string(&marathonfile)?;
+            match output {
+                Some(output) => fs::write(output, yaml).await?,
+                None => print!("{}", yaml),
+            }
+        }
+        (FilterFormat::Marathon, FilterFormat::Xctestplan) => {
+            let output = output.expect("clap requires --output for --to xctestplan");
+            filtering::convert::update_xctestplan(vec![input], output, target_names).await?;
+        }
+        (from, to) => anyhow::bail!(InputError::InvalidConversion {
+            from: format!("{:?}", from).to_lowercase(),
+            to: format!("{:?}", to).to_lowercase(),
+        }),
+    }
+    Ok(true)
+}
+
+pub(crate) async fn evaluate(
+    filter_file: PathBuf,
+    tests: Option<PathBuf>,
+    test_application: Option<PathBuf>,
+) -> Result<bool> {
+    let marathonfile = filtering::convert::convert(filter_file).await?;
+    let tests = match (tests, test_application) {
+        (Some(tests), _) => filtering::evaluate::read_tests(tests).await?,
+        (None, Some(test_application)) => inventory::read_tests(&test_application).await?,
+        (None, None) => vec![],
+    };
+    let total = tests.len();
+    let evaluations = filtering::evaluate::evaluate(&marathonfile.filtering_configuration, tests)?;
+
+    let (selected, excluded): (Vec<Evaluation>, Vec<Evaluation>) = evaluations
+        .into_iter()
+        .partition(|evaluation| evaluation.verdict == Verdict::Selected);
+
+    println!("Selected {} of {} tests:", selected.len(), total);
+    for evaluation in &selected {
+        println!("  {}", evaluation.test);
+    }
+    println!("Excluded {} of {} tests:", excluded.len(), total);
+    for evaluation in &excluded {
+        if let Verdict::Excluded { reason } = &evaluation.verdict {
+            println!("  {}: {}", evaluation.test, reason);
+        }
+    }
+    Ok(true)
+}
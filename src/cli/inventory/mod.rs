@@ -0,0 +1,68 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{errors::InputError, filtering::evaluate::TestCase, inventory};
+
+use super::model::Platform;
+
+#[derive(Serialize)]
+struct TestApplicationInventory {
+    test_application: PathBuf,
+    tests: Vec<TestEntry>,
+}
+
+#[derive(Serialize)]
+struct TestEntry {
+    name: String,
+    annotations: Vec<String>,
+}
+
+fn platform(test_application: &Path) -> Result<Platform> {
+    match test_application.extension().and_then(|e| e.to_str()) {
+        Some("apk") => Ok(Platform::Android),
+        Some("app" | "xctest" | "zip" | "ipa") => Ok(Platform::iOS),
+        extension => anyhow::bail!(InputError::InvalidFileExtension {
+            extension: extension.unwrap_or_default().to_owned(),
+            supported: "apk, app, xctest, zip, ipa".to_owned(),
+        }),
+    }
+}
+
+pub(crate) async fn read_tests(test_application: &Path) -> Result<Vec<TestCase>> {
+    match platform(test_application)? {
+        Platform::Android => inventory::android_tests(test_application).await,
+        Platform::iOS => inventory::ios_tests(test_application).await,
+    }
+}
+
+pub(crate) async fn list(
+    test_application: Option<PathBuf>,
+    library_bundle: Option<Vec<PathBuf>>,
+) -> Result<bool> {
+    let test_applications = test_application
+        .into_iter()
+        .chain(library_bundle.unwrap_or_default());
+
+    let mut inventories = vec![];
+    for test_application in test_applications {
+        let platform = platform(&test_application)?;
+        let tests = read_tests(&test_application).await?;
+        inventories.push(TestApplicationInventory {
+            test_application,
+            tests: tests
+                .into_iter()
+                .map(|test| TestEntry {
+                    name: match platform {
+                        Platform::Android => test.fully_qualified_test_name(),
+                        Platform::iOS => test.xctest_identifier(),
+                    },
+                    annotations: test.annotations,
+                })
+                .collect(),
+        });
+    }
+    println!("{}", serde_json::to_string_pretty(&inventories)?);
+    Ok(true)
+}
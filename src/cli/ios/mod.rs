@@ -1,96 +1,29 @@
+mod xctestrun;
+
 use std::ffi::OsStr;
-use std::fmt::Display;
 
 use anyhow::Result;
-use std::collections::HashSet;
 use tokio::fs::File;
-use walkdir::WalkDir;
 
 use crate::{
-    cli::{self},
+    cli::{self, IosRunArgs},
     compression,
-    errors::ConfigurationError,
-    interactor::TriggerTestRunInteractor,
+    constraints::{self, DeviceConstraints},
+    interactor::{TriggerMatrixRunInteractor, TriggerTestRunInteractor},
+    matrix,
+    spec::{IosRunSpec, PlatformRunSpec, RunSpec},
 };
 use crate::{errors::InputError, filtering};
 
-#[derive(Debug, clap::ValueEnum, Clone, PartialEq, Eq)]
-pub enum IosDevice {
-    #[clap(name = "iPhone-11")]
-    IPhone11,
-    #[clap(name = "iPhone-15")]
-    IPhone15,
-    #[clap(name = "iPhone-15-Pro")]
-    IPhone15Pro,
-    #[clap(name = "iPhone-15-Pro-Max")]
-    IPhone15ProMax,
-    #[clap(name = "iPhone-16")]
-    IPhone16,
-    #[clap(name = "iPhone-16-Pro")]
-    IPhone16Pro,
-    #[clap(name = "iPhone-16-Pro-Max")]
-    IPhone16ProMax,
-}
-
-impl Display for IosDevice {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            IosDevice::IPhone11 => f.write_str("com.apple.CoreSimulator.SimDeviceType.iPhone-11"),
-            IosDevice::IPhone15 => f.write_str("com.apple.CoreSimulator.SimDeviceType.iPhone-15"),
-            IosDevice::IPhone15Pro => {
-                f.write_str("com.apple.CoreSimulator.SimDeviceType.iPhone-15-Pro")
-            }
-            IosDevice::IPhone15ProMax => {
-                f.write_str("com.apple.CoreSimulator.SimDeviceType.iPhone-15-Pro-Max")
-            }
-            IosDevice::IPhone16 => f.write_str("com.apple.CoreSimulator.SimDeviceType.iPhone-16"),
-            IosDevice::IPhone16Pro => {
-                f.write_str("com.apple.CoreSimulator.SimDeviceType.iPhone-16-Pro")
-            }
-            IosDevice::IPhone16ProMax => {
-                f.write_str("com.apple.CoreSimulator.SimDeviceType.iPhone-16-Pro-Max")
-            }
-        }
-    }
-}
-
-#[derive(Debug, clap::ValueEnum, Clone, PartialEq, Eq)]
-pub enum OsVersion {
-    #[clap(name = "17.5")]
-    Ios17_5,
-    #[clap(name = "18.2")]
-    Ios18_2,
-}
-
-impl Display for OsVersion {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            OsVersion::Ios17_5 => f.write_str("com.apple.CoreSimulator.SimRuntime.iOS-17-5"),
-            OsVersion::Ios18_2 => f.write_str("com.apple.CoreSimulator.SimRuntime.iOS-18-2"),
-        }
-    }
-}
-
-#[derive(Debug, clap::ValueEnum, Clone, PartialEq, Eq)]
-pub enum XcodeVersion {
-    #[clap(name = "15.4")]
-    Xcode15_4,
-    #[clap(name = "16.2")]
-    Xcode16_2,
-}
-
-impl Display for XcodeVersion {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            XcodeVersion::Xcode15_4 => f.write_str("15.4"),
-            XcodeVersion::Xcode16_2 => f.write_str("16.2"),
-        }
-    }
-}
+const DEFAULT_TEST_TIMEOUT: u32 = 300;
 
-pub(crate) async fn ensure_format(path: std::path::PathBuf) -> Result<std::path::PathBuf> {
-    let supported_extensions_file = vec!["zip", "ipa"];
-    let supported_extensions_dir = vec!["app", "xctest"];
+/// Folders are zipped into `workdir`, archives are used as is
+pub(crate) async fn ensure_format(
+    path: std::path::PathBuf,
+    workdir: &std::path::Path,
+) -> Result<std::path::PathBuf> {
+    let supported_extensions_file = ["zip", "ipa"];
+    let supported_extensions_dir = ["app", "xctest"];
     if path.is_file()
         && path
             .extension()
@@ -104,19 +37,17 @@ pub(crate) async fn ensure_format(path: std::path::PathBuf) -> Result<std::path:
             .and_then(OsStr::to_str)
             .is_some_and(|ext| supported_extensions_dir.contains(&ext))
     {
-        let dst = &path.with_extension("zip");
-        let dst_file = File::create(dst).await?;
-
-        let walkdir = WalkDir::new(&path);
-        let it = walkdir.into_iter();
-        let prefix = &path
-            .parent()
-            .unwrap_or(&path)
-            .to_str()
-            .ok_or(InputError::NonUTF8Path { path: path.clone() })?;
-
-        compression::zip_dir(&mut it.filter_map(|e| e.ok()), prefix, dst_file).await?;
-        Ok(dst.to_owned())
+        let file_name = path
+            .with_extension("zip")
+            .file_name()
+            .ok_or(InputError::NonUTF8Path { path: path.clone() })?
+            .to_owned();
+        // Each archive gets its own folder so that bundles with the same name don't collide
+        let dst = tempfile::tempdir_in(workdir)?.into_path().join(file_name);
+        let dst_file = File::create(&dst).await?;
+
+        compression::zip_dir(&path, dst_file).await?;
+        Ok(dst)
     } else {
         Err(InputError::UnsupportedArtifact {
             path,
@@ -127,218 +58,193 @@ pub(crate) async fn ensure_format(path: std::path::PathBuf) -> Result<std::path:
     }
 }
 
-pub(crate) fn get_supported_configs(
-) -> Vec<(Option<IosDevice>, Option<XcodeVersion>, Option<OsVersion>)> {
-    vec![
-        (
-            Some(IosDevice::IPhone15),
-            Some(XcodeVersion::Xcode15_4),
-            Some(OsVersion::Ios17_5),
-        ),
-        (
-            Some(IosDevice::IPhone15Pro),
-            Some(XcodeVersion::Xcode15_4),
-            Some(OsVersion::Ios17_5),
-        ),
-        (
-            Some(IosDevice::IPhone15ProMax),
-            Some(XcodeVersion::Xcode15_4),
-            Some(OsVersion::Ios17_5),
-        ),
-        (
-            Some(IosDevice::IPhone11),
-            Some(XcodeVersion::Xcode15_4),
-            Some(OsVersion::Ios17_5),
-        ),
-        (
-            Some(IosDevice::IPhone16),
-            Some(XcodeVersion::Xcode16_2),
-            Some(OsVersion::Ios18_2),
-        ),
-        (
-            Some(IosDevice::IPhone16Pro),
-            Some(XcodeVersion::Xcode16_2),
-            Some(OsVersion::Ios18_2),
-        ),
-        (
-            Some(IosDevice::IPhone16ProMax),
-            Some(XcodeVersion::Xcode16_2),
-            Some(OsVersion::Ios18_2),
-        ),
-        (
-            Some(IosDevice::IPhone11),
-            Some(XcodeVersion::Xcode16_2),
-            Some(OsVersion::Ios18_2),
-        ),
-    ]
-}
-
-pub(crate) async fn infer_parameters(
-    device: Option<IosDevice>,
-    xcode_version: Option<XcodeVersion>,
-    os_version: Option<OsVersion>,
-) -> Result<(IosDevice, XcodeVersion, OsVersion)> {
-    let supported_configs = get_supported_configs();
-
-    // Filter out configurations that match the provided parameters
-    let filtered_configs: Vec<&(Option<IosDevice>, Option<XcodeVersion>, Option<OsVersion>)> =
-        supported_configs
-            .iter()
-            .filter(|(d, x, o)| {
-                (device.is_none() || d == &device)
-                    && (xcode_version.is_none() || x == &xcode_version)
-                    && (os_version.is_none() || o == &os_version)
-            })
-            .collect();
-
-    // If no valid configuration is found, return an error
-    if filtered_configs.is_empty() {
-        return Err(anyhow::anyhow!("Invalid parameters"));
-    }
-
-    // If only one valid configuration is found, use it
-    if filtered_configs.len() == 1 {
-        let (final_device, final_xcode, final_os) = filtered_configs[0];
-        return Ok((
-            final_device.clone().unwrap(),
-            final_xcode.clone().unwrap(),
-            final_os.clone().unwrap(),
-        ));
-    }
-
-    // If multiple configurations are still valid, we need more specific parameters
-    if filtered_configs.len() > 1 {
-        return Err(anyhow::anyhow!(
-            "Ambiguous parameters, please provide more specific input."
-        ));
-    }
-
-    Ok((device.unwrap(), xcode_version.unwrap(), os_version.unwrap()))
+pub(crate) fn infer_parameters(
+    constraints: &DeviceConstraints,
+    device: Option<String>,
+    xcode_version: Option<String>,
+    os_version: Option<String>,
+) -> Result<(String, String, String)> {
+    let values = constraints::infer(
+        &constraints.ios,
+        &constraints::IOS_DIMENSIONS,
+        &[device, os_version, xcode_version],
+    )?
+    .complete(&constraints::IOS_DIMENSIONS)?;
+    let [device, os_version, xcode_version] =
+        <[String; 3]>::try_from(values).expect("a value for each dimension");
+    Ok((device, xcode_version, os_version))
 }
 
-fn get_allowed_permissions() -> HashSet<&'static str> {
-    HashSet::from([
-        "calendar",
-        "contacts-limited",
-        "contacts",
-        "location",
-        "location-always",
-        "photos-add",
-        "photos",
-        "media-library",
-        "microphone",
-        "motion",
-        "reminders",
-        "siri",
-    ])
-}
+pub(crate) async fn run(args: IosRunArgs) -> Result<bool> {
+    let IosRunArgs {
+        application,
+        test_application,
+        xctestrun,
+        xctestrun_target,
+        os_version,
+        device,
+        xcode_version,
+        common,
+        api_args,
+        retry_args,
+        analytics_args,
+        xctestrun_env,
+        xctestrun_test_env,
+        xctestplan_filter_file,
+        xctestplan_target_name,
+        xctestplan_configuration,
+        test_timeout_default,
+        test_timeout_max,
+        granted_permission,
+    } = args;
+
+    let (application, test_application, xctestrun) = match xctestrun {
+        Some(path) => {
+            let inputs = xctestrun::read(&path, xctestrun_target.as_deref()).await?;
+            (
+                inputs.application.clone(),
+                inputs.test_application.clone(),
+                Some(inputs),
+            )
+        }
+        None => (
+            application.expect("required without --xctestrun"),
+            test_application.expect("required without --xctestrun"),
+            None,
+        ),
+    };
 
-pub(crate) async fn run(
-    application: std::path::PathBuf,
-    test_application: std::path::PathBuf,
-    os_version: Option<OsVersion>,
-    device: Option<IosDevice>,
-    xcode_version: Option<XcodeVersion>,
-    common: super::CommonRunArgs,
-    api_args: super::ApiArgs,
-    xctestrun_env: Option<Vec<String>>,
-    xctestrun_test_env: Option<Vec<String>>,
-    xctestplan_filter_file: Option<std::path::PathBuf>,
-    xctestplan_target_name: Option<String>,
-    retry_args: super::RetryArgs,
-    analytics_args: super::AnalyticsArgs,
-    test_timeout_default: Option<u32>,
-    test_timeout_max: Option<u32>,
-    granted_permission: Option<Vec<String>>,
-) -> Result<bool> {
-    let (device, xcode_version, os_version) = if device.is_none()
-        && xcode_version.is_none()
-        && os_version.is_none()
+    let client = api_args.client()?;
+    let cells = matrix::expand(
+        &common.matrix,
+        &constraints::IOS_DIMENSIONS,
+        &[device, os_version, xcode_version],
+    )?;
+    let constraints = if cells
+        .iter()
+        .flat_map(|(_, values)| values)
+        .any(Option::is_some)
     {
-        (None, None, None)
+        Some(DeviceConstraints::load(&client).await)
     } else {
-        match infer_parameters(device, xcode_version, os_version).await {
-            Ok((dev, xcode, os)) => (Some(dev), Some(xcode), Some(os)),
-            Err(_) => {
-                return Err(ConfigurationError::UnsupportedRunConfiguration {
-                    message: "
-Please set --xcode-version, --os-version, and --device correctly.
-Supported iOS settings combinations are:
-    --xcode-version 15.4 --os-version 17.5 --device iPhone-15 => Default
-    --xcode-version 15.4 --os-version 17.5 --device iPhone-15-Pro
-    --xcode-version 15.4 --os-version 17.5 --device iPhone-15-Pro-Max
-    --xcode-version 15.4 --os-version 17.5 --device iPhone-11
-    --xcode-version 16.2 --os-version 18.2 --device iPhone-16
-    --xcode-version 16.2 --os-version 18.2 --device iPhone-16-Pro
-    --xcode-version 16.2 --os-version 18.2 --device iPhone-16-Pro-Max
-    --xcode-version 16.2 --os-version 18.2 --device iPhone-11
-First example: If you choose --xcode-version 15.4 --device iPhone-15-Pro then the --os-version will be inferred (17.5).
-Second example: If you choose --device iPhone-11 then you will receive an error because --os-version and --xcode-version params are ambiguous."
-                        .into(),
-                }.into());
+        None
+    };
+    let mut configurations = Vec::with_capacity(cells.len());
+    for (cell, values) in cells {
+        let [device, os_version, xcode_version] =
+            <[Option<String>; 3]>::try_from(values).expect("a value for each dimension");
+        let parameters = match &constraints {
+            Some(constraints) => {
+                let (device, xcode_version, os_version) =
+                    infer_parameters(constraints, device, xcode_version, os_version)?;
+                (Some(device), Some(xcode_version), Some(os_version))
             }
+            None => (None, None, None),
+        };
+        configurations.push((cell, parameters));
+    }
+
+    let xctestplan_options = match &xctestplan_filter_file {
+        Some(xctestplan_filter_file) => {
+            filtering::convert::xctestplan_run_options(
+                xctestplan_filter_file.clone(),
+                xctestplan_configuration,
+            )
+            .await?
         }
+        None => Default::default(),
     };
 
-    let filtering_configuration = if xctestplan_filter_file.is_some() {
+    let filtering_configuration = if let Some(xctestplan_filter_file) = xctestplan_filter_file {
         Some(
-            filtering::convert::convert_xctestplan(
-                xctestplan_filter_file.unwrap(),
-                xctestplan_target_name,
-            )
-            .await?,
+            filtering::convert::convert_xctestplan(xctestplan_filter_file, xctestplan_target_name)
+                .await?,
         )
     } else {
-        let filter_file = common.filter_file.map(filtering::convert::convert);
-        match filter_file {
-            Some(future) => Some(future.await?),
-            None => None,
+        if common.filter_file.is_empty() {
+            None
+        } else {
+            Some(filtering::convert::convert_all(common.filter_file).await?)
         }
     };
-    let application = ensure_format(application).await?;
-    let test_application = ensure_format(test_application).await?;
+    let filtering_configuration = filtering::convert::with_inline_filters(
+        filtering_configuration,
+        common.inline_filter_args.into(),
+    )
+    .await?;
+    let filtering_configuration =
+        filtering::convert::with_shard(filtering_configuration, common.shard);
+    let os_versions: Vec<_> = configurations
+        .iter()
+        .map(|(_, (_, _, os_version))| os_version.clone())
+        .collect();
+    // Holds the archives of bundle folders until they're uploaded
+    let workdir = tempfile::tempdir()?;
+    let application_archive = ensure_format(application.clone(), workdir.path()).await?;
+    let test_application_archive = ensure_format(test_application.clone(), workdir.path()).await?;
+    // Errors point at the supplied bundles rather than their archives
+    cli::validate::ios_binaries(&application, &test_application, &os_versions).await?;
+    let (application, test_application) = (application_archive, test_application_archive);
 
     let retry_args = cli::validate::retry_args(retry_args);
     cli::validate::result_file_args(&common.result_file_args)?;
 
-    if let Some(limit) = common.concurrency_limit {
-        if limit == 0 {
-            return Err(InputError::NonPositiveValue {
-                arg: "--concurrency-limit".to_owned(),
-            })?;
-        }
+    let mut ios = IosRunSpec {
+        application,
+        test_application,
+        granted_permission,
+        language: xctestplan_options.language,
+        country: xctestplan_options.region,
+        ..Default::default()
     }
-
-    if let Some(limit) = test_timeout_default {
-        if limit == 0 {
-            return Err(InputError::NonPositiveValue {
-                arg: "--test-timeout-default".to_owned(),
-            })?;
+    .with_xctestrun_env(xctestrun_env, xctestrun_test_env)?;
+    if let Some(xctestrun) = xctestrun {
+        // Variables given with --xctestrun-env and --xctestrun-test-env take precedence over the file
+        for (env, file_env) in [
+            (&mut ios.xctestrun_env, xctestrun.env),
+            (&mut ios.xctestrun_test_env, xctestrun.test_env),
+        ] {
+            if file_env.is_empty() {
+                continue;
+            }
+            let env = env.get_or_insert_with(Default::default);
+            for (key, value) in file_env {
+                env.entry(key).or_insert(value);
+            }
         }
     }
-
-    if let Some(limit) = test_timeout_max {
-        if limit == 0 {
-            return Err(InputError::NonPositiveValue {
-                arg: "--test-timeout-max".to_owned(),
-            })?;
+    if !xctestplan_options.env.is_empty() {
+        // Variables given with --xctestrun-env take precedence over the test plan
+        let env = ios.xctestrun_env.get_or_insert_with(Default::default);
+        for (key, value) in xctestplan_options.env {
+            env.entry(key).or_insert(value);
         }
     }
 
-    if let Some(granted_permission) = granted_permission.clone() {
-        let allowed_permissions = get_allowed_permissions();
-        let invalid_permissions: Vec<_> = granted_permission
-            .iter()
-            .filter(|perm| !allowed_permissions.contains(perm.as_str()))
-            .cloned()
-            .collect();
-
-        if !invalid_permissions.is_empty() {
-            return Err(InputError::IncorrectPermission {
-                permissions: invalid_permissions,
-            })?;
-        }
-    }
+    let spec = RunSpec::builder(ios)
+        .name(common.name)
+        .link(common.link)
+        .branch(common.branch)
+        .project(common.project)
+        .isolated(common.isolated)
+        .code_coverage(common.code_coverage.or(xctestplan_options.code_coverage))
+        .retry_quota_test_uncompleted(retry_args.retry_quota_test_uncompleted)
+        .retry_quota_test_preventive(retry_args.retry_quota_test_preventive)
+        .retry_quota_test_reactive(
+            retry_args
+                .retry_quota_test_reactive
+                .or(xctestplan_options.retry_quota_test_reactive),
+        )
+        .analytics_read_only(analytics_args.analytics_read_only)
+        .filtering_configuration(filtering_configuration)
+        .concurrency_limit(common.concurrency_limit)
+        .test_timeout_default(
+            test_timeout_default
+                .or(xctestplan_options.test_timeout_default)
+                .unwrap_or(DEFAULT_TEST_TIMEOUT),
+        )
+        .test_timeout_max(test_timeout_max.or(xctestplan_options.test_timeout_max))
+        .build()?;
 
     let present_wait: bool = match common.wait {
         None => true,
@@ -346,120 +252,150 @@ Second example: If you choose --device iPhone-11 then you will receive an error
         Some(false) => false,
     };
 
-    TriggerTestRunInteractor {}
+    if common.matrix.is_empty() {
+        let (_, (device, xcode_version, os_version)) = configurations.remove(0);
+        let spec = with_device(&spec, device, xcode_version, os_version);
+        return TriggerTestRunInteractor {}
+            .execute(
+                &client,
+                spec,
+                present_wait,
+                common.ignore_test_failures,
+                &common.output,
+                common.progress_args.no_progress_bars,
+                common.result_file_args.result_file,
+            )
+            .await;
+    }
+
+    let runs = configurations
+        .into_iter()
+        .map(|(cell, (device, xcode_version, os_version))| {
+            (cell, with_device(&spec, device, xcode_version, os_version))
+        })
+        .collect();
+    TriggerMatrixRunInteractor {}
         .execute(
-            &api_args.base_url,
-            &api_args.api_key,
-            common.name,
-            common.link,
-            common.branch,
+            &client,
+            runs,
             present_wait,
-            common.isolated,
             common.ignore_test_failures,
-            common.code_coverage,
-            retry_args.retry_quota_test_uncompleted,
-            retry_args.retry_quota_test_preventive,
-            retry_args.retry_quota_test_reactive,
-            analytics_args.analytics_read_only,
-            false,
-            false,
-            filtering_configuration,
             &common.output,
-            Some(application),
-            Some(test_application),
-            os_version.map(|x| x.to_string()),
-            None,
-            device.map(|x| x.to_string()),
-            xcode_version.map(|x| x.to_string()),
-            None,
-            "iOS".to_owned(),
             common.progress_args.no_progress_bars,
             common.result_file_args.result_file,
-            xctestrun_env,
-            xctestrun_test_env,
-            None,
-            common.concurrency_limit,
-            test_timeout_default,
-            test_timeout_max,
-            common.project,
-            None,
-            None,
-            granted_permission,
         )
         .await
 }
 
+fn with_device(
+    spec: &RunSpec,
+    device: Option<String>,
+    xcode_version: Option<String>,
+    os_version: Option<String>,
+) -> RunSpec {
+    let mut spec = spec.clone();
+    if let PlatformRunSpec::iOS(ios) = &mut spec.platform {
+        ios.os_version = os_version.map(|x| {
+            format!(
+                "com.apple.CoreSimulator.SimRuntime.iOS-{}",
+                x.replace('.', "-")
+            )
+        });
+        ios.device = device.map(|x| format!("com.apple.CoreSimulator.SimDeviceType.{}", x));
+        ios.xcode_version = xcode_version;
+    }
+    spec
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[tokio::test]
-    async fn test_infer_parameters_ambiguous_device_should_error() {
-        let provided_device = Some(IosDevice::IPhone11);
+    #[test]
+    fn test_infer_parameters_ambiguous_device_should_error() {
+        let provided_device = Some("iPhone-11".to_owned());
 
-        let result = infer_parameters(provided_device, None, None).await;
+        let result = infer_parameters(&DeviceConstraints::builtin(), provided_device, None, None);
         assert!(result.is_err());
     }
 
-    #[tokio::test]
-    async fn test_infer_parameters_device_and_xcode_version_provided() -> Result<()> {
-        let provided_device = Some(IosDevice::IPhone15);
-        let provided_xcode_version = Some(XcodeVersion::Xcode15_4);
-        let expected_os_version = OsVersion::Ios17_5;
+    #[test]
+    fn test_infer_parameters_device_and_xcode_version_provided() -> Result<()> {
+        let provided_device = Some("iPhone-15".to_owned());
+        let provided_xcode_version = Some("15.4".to_owned());
+        let expected_os_version = "17.5";
 
-        let (inferred_device, inferred_xcode_version, inferred_os_version) =
-            infer_parameters(provided_device, provided_xcode_version, None).await?;
+        let (inferred_device, inferred_xcode_version, inferred_os_version) = infer_parameters(
+            &DeviceConstraints::builtin(),
+            provided_device,
+            provided_xcode_version,
+            None,
+        )?;
 
-        assert_eq!(inferred_device, IosDevice::IPhone15);
-        assert_eq!(inferred_xcode_version, XcodeVersion::Xcode15_4);
+        assert_eq!(inferred_device, "iPhone-15");
+        assert_eq!(inferred_xcode_version, "15.4");
         assert_eq!(inferred_os_version, expected_os_version);
 
         Ok(())
     }
 
-    #[tokio::test]
-    async fn test_infer_parameters_ambiguous_xcode_version_should_error() {
-        let provided_xcode_version = Some(XcodeVersion::Xcode15_4);
+    #[test]
+    fn test_infer_parameters_ambiguous_xcode_version_should_error() {
+        let provided_xcode_version = Some("15.4".to_owned());
 
-        let result = infer_parameters(None, provided_xcode_version, None).await;
+        let result = infer_parameters(
+            &DeviceConstraints::builtin(),
+            None,
+            provided_xcode_version,
+            None,
+        );
         assert!(result.is_err());
     }
 
-    #[tokio::test]
-    async fn test_infer_parameters_complete_input_valid() -> Result<()> {
-        let provided_device = Some(IosDevice::IPhone15);
-        let provided_xcode_version = Some(XcodeVersion::Xcode15_4);
-        let provided_os_version = Some(OsVersion::Ios17_5);
+    #[test]
+    fn test_infer_parameters_complete_input_valid() -> Result<()> {
+        let provided_device = Some("iPhone-15".to_owned());
+        let provided_xcode_version = Some("15.4".to_owned());
+        let provided_os_version = Some("17.5".to_owned());
 
-        let (inferred_device, inferred_xcode_version, inferred_os_version) =
-            infer_parameters(provided_device, provided_xcode_version, provided_os_version).await?;
+        let (inferred_device, inferred_xcode_version, inferred_os_version) = infer_parameters(
+            &DeviceConstraints::builtin(),
+            provided_device,
+            provided_xcode_version,
+            provided_os_version,
+        )?;
 
-        assert_eq!(inferred_device, IosDevice::IPhone15);
-        assert_eq!(inferred_xcode_version, XcodeVersion::Xcode15_4);
-        assert_eq!(inferred_os_version, OsVersion::Ios17_5);
+        assert_eq!(inferred_device, "iPhone-15");
+        assert_eq!(inferred_xcode_version, "15.4");
+        assert_eq!(inferred_os_version, "17.5");
 
         Ok(())
     }
 
-    #[tokio::test]
-    async fn test_infer_parameters_invalid_device_and_xcode_combination_should_error() {
-        let provided_os_version = Some(OsVersion::Ios17_5);
-        let provided_xcode_version = Some(XcodeVersion::Xcode15_4);
+    #[test]
+    fn test_infer_parameters_invalid_device_and_xcode_combination_should_error() {
+        let provided_os_version = Some("17.5".to_owned());
+        let provided_xcode_version = Some("15.4".to_owned());
 
-        let result = infer_parameters(None, provided_xcode_version, provided_os_version).await;
+        let result = infer_parameters(
+            &DeviceConstraints::builtin(),
+            None,
+            provided_xcode_version,
+            provided_os_version,
+        );
         assert!(result.is_err());
     }
 
-    #[tokio::test]
-    async fn test_infer_parameters_valid_for_iphone_16() -> Result<()> {
-        let provided_device = Some(IosDevice::IPhone16);
+    #[test]
+    fn test_infer_parameters_valid_for_iphone_16() -> Result<()> {
+        let provided_device = Some("iPhone-16".to_owned());
 
         let (inferred_device, inferred_xcode_version, inferred_os_version) =
-            infer_parameters(provided_device, None, None).await?;
+            infer_parameters(&DeviceConstraints::builtin(), provided_device, None, None)?;
 
-        assert_eq!(inferred_device, IosDevice::IPhone16);
-        assert_eq!(inferred_xcode_version, XcodeVersion::Xcode16_2);
-        assert_eq!(inferred_os_version, OsVersion::Ios18_2);
+        assert_eq!(inferred_device, "iPhone-16");
+        assert_eq!(inferred_xcode_version, "16.2");
+        assert_eq!(inferred_os_version, "18.2");
 
         Ok(())
     }
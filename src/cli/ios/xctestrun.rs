@@ -0,0 +1,285 @@
+//! Inputs of `run ios --xctestrun`, the file `xcodebuild build-for-testing` writes next to the products.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{errors::InputError, formatter};
+
+const METADATA_KEY: &str = "__xctestrun_metadata__";
+const TEST_ROOT: &str = "__TESTROOT__";
+const TEST_HOST: &str = "__TESTHOST__";
+/// Placeholders xcodebuild expands to folders of the local machine
+const LOCAL_PLACEHOLDERS: [&str; 5] = [
+    TEST_ROOT,
+    TEST_HOST,
+    "__PLATFORMS__",
+    "__SHAREDFRAMEWORKS__",
+    "__DEVELOPERUSRLIB__",
+];
+
+#[derive(Deserialize)]
+struct Metadata {
+    #[serde(rename = "FormatVersion")]
+    format_version: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TestConfiguration {
+    name: Option<String>,
+    test_targets: Vec<TestTarget>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "PascalCase")]
+struct TestTarget {
+    blueprint_name: Option<String>,
+    test_host_path: Option<String>,
+    #[serde(rename = "UITargetAppPath")]
+    ui_target_app_path: Option<String>,
+    #[serde(rename = "IsUITestBundle", default)]
+    is_ui_test_bundle: bool,
+    #[serde(default)]
+    environment_variables: HashMap<String, String>,
+    #[serde(default)]
+    testing_environment_variables: HashMap<String, String>,
+    #[serde(default)]
+    command_line_arguments: Vec<String>,
+}
+
+/// Application, test runner and environment of a UI test target
+#[derive(Debug, PartialEq)]
+pub(crate) struct XctestrunInputs {
+    pub application: PathBuf,
+    pub test_application: PathBuf,
+    pub env: HashMap<String, String>,
+    pub test_env: HashMap<String, String>,
+}
+
+/// Reads the UI test target of the first test configuration.
+/// Without a target name the configuration has to contain a single UI test target.
+pub(crate) async fn read(path: &Path, target_name: Option<&str>) -> Result<XctestrunInputs> {
+    let invalid = |message: String| InputError::InvalidXctestrun {
+        path: path.to_path_buf(),
+        message,
+    };
+    let data = tokio::fs::read(path)
+        .await
+        .map_err(|error| InputError::OpenFileFailure {
+            path: path.to_path_buf(),
+            error,
+        })?;
+    let value: plist::Value = plist::from_bytes(&data).map_err(|e| invalid(e.to_string()))?;
+    let mut root = value
+        .into_dictionary()
+        .ok_or_else(|| invalid("expected a dictionary".into()))?;
+    let format_version = match root.remove(METADATA_KEY) {
+        Some(metadata) => {
+            plist::from_value::<Metadata>(&metadata)
+                .map_err(|e| invalid(e.to_string()))?
+                .format_version
+        }
+        None => 1,
+    };
+
+    let targets: Vec<TestTarget> = match format_version {
+        1 => {
+            let mut targets = vec![];
+            for (name, target) in root {
+                if name.starts_with("__") {
+                    continue;
+                }
+                let mut target: TestTarget =
+                    plist::from_value(&target).map_err(|e| invalid(e.to_string()))?;
+                target.blueprint_name.get_or_insert(name);
+                targets.push(target);
+            }
+            targets
+        }
+        2 => {
+            let configurations = root
+                .remove("TestConfigurations")
+                .ok_or_else(|| invalid("TestConfigurations are missing".into()))?;
+            let configurations: Vec<TestConfiguration> =
+                plist::from_value(&configurations).map_err(|e| invalid(e.to_string()))?;
+            let mut configurations = configurations.into_iter();
+            let configuration = configurations
+                .next()
+                .ok_or_else(|| invalid("TestConfigurations are empty".into()))?;
+            if configurations.len() > 0 {
+                formatter::warning(&format!(
+                    "xctestrun has several test configurations, using the first one ({})",
+                    configuration.name.as_deref().unwrap_or_default()
+                ));
+            }
+            configuration.test_targets
+        }
+        version => return Err(invalid(format!("unsupported FormatVersion {}", version)).into()),
+    };
+
+    let ui_targets: Vec<TestTarget> = targets
+        .into_iter()
+        .filter(|target| target.is_ui_test_bundle || target.ui_target_app_path.is_some())
+        .collect();
+    let available = || {
+        ui_targets
+            .iter()
+            .filter_map(|target| target.blueprint_name.clone())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let target = match target_name {
+        Some(name) => ui_targets
+            .iter()
+            .find(|target| target.blueprint_name.as_deref() == Some(name))
+            .ok_or_else(|| InputError::InvalidXctestrunTarget {
+                name: name.to_owned(),
+                available: available(),
+            })?,
+        None => match ui_targets.as_slice() {
+            [target] => target,
+            [] => return Err(invalid("it has no UI test targets".into()).into()),
+            _ => {
+                return Err(invalid(format!(
+                    "it has several UI test targets ({}), pick one with --xctestrun-target",
+                    available()
+                ))
+                .into())
+            }
+        },
+    };
+
+    let test_root = path.parent().unwrap_or(Path::new("."));
+    let test_host = target
+        .test_host_path
+        .as_deref()
+        .ok_or_else(|| invalid("TestHostPath of the UI test target is missing".into()))?
+        .replace(TEST_ROOT, &test_root.to_string_lossy());
+    let application = target
+        .ui_target_app_path
+        .as_deref()
+        .ok_or_else(|| invalid("UITargetAppPath of the UI test target is missing".into()))?
+        .replace(TEST_ROOT, &test_root.to_string_lossy())
+        .replace(TEST_HOST, &test_host);
+
+    // The API has no run parameter for launch arguments, dropping them would change what the tests do
+    if !target.command_line_arguments.is_empty() {
+        anyhow::bail!(InputError::UnsupportedXctestrunArguments {
+            path: path.to_path_buf(),
+            arguments: target.command_line_arguments.join(" "),
+        });
+    }
+    Ok(XctestrunInputs {
+        application: PathBuf::from(application),
+        test_application: PathBuf::from(test_host),
+        env: portable_env(&target.environment_variables),
+        test_env: portable_env(&target.testing_environment_variables),
+    })
+}
+
+/// Drops variables that point into the local build folder or Xcode, e.g. `DYLD_FRAMEWORK_PATH`
+fn portable_env(env: &HashMap<String, String>) -> HashMap<String, String> {
+    let (local, portable): (HashMap<_, _>, HashMap<_, _>) = env
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .partition(|(_, value)| {
+            LOCAL_PLACEHOLDERS
+                .iter()
+                .any(|placeholder| value.contains(placeholder))
+        });
+    if !local.is_empty() {
+        let mut keys: Vec<_> = local.keys().map(String::as_str).collect();
+        keys.sort();
+        formatter::warning(&format!(
+            "xctestrun environment variables with local paths are skipped: {}",
+            keys.join(", ")
+        ));
+    }
+    portable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        Path::new(&manifest_dir)
+            .join("fixture")
+            .join("xctestrun")
+            .join(name)
+    }
+
+    #[tokio::test]
+    async fn test_read_xctestrun() -> Result<()> {
+        for name in ["App_v1.xctestrun", "App_v2.xctestrun"] {
+            let inputs = read(&fixture(name), None).await?;
+
+            let root = fixture("");
+            assert_eq!(
+                inputs,
+                XctestrunInputs {
+                    application: root.join("../inventory/App.app"),
+                    test_application: root.join("../inventory/AppUITests-Runner.app"),
+                    env: HashMap::from([(
+                        "API_URL".to_owned(),
+                        "https://staging.example.com".to_owned()
+                    )]),
+                    test_env: HashMap::from([("SNAPSHOT_MODE".to_owned(), "record".to_owned())]),
+                },
+                "{}",
+                name
+            );
+            assert!(inputs.application.is_dir());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_xctestrun_target() -> Result<()> {
+        let path = fixture("App_v2.xctestrun");
+        assert!(read(&path, Some("AppUITests")).await.is_ok());
+
+        let error = read(&path, Some("AppTests")).await.unwrap_err();
+        assert!(
+            error.to_string().contains("available = AppUITests"),
+            "{}",
+            error
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_xctestrun_with_arguments() -> Result<()> {
+        let mut xctestrun = plist::Value::from_file(fixture("App_v2.xctestrun"))?;
+        let target = xctestrun
+            .as_dictionary_mut()
+            .and_then(|root| root.get_mut("TestConfigurations"))
+            .and_then(|configurations| configurations.as_array_mut()?.first_mut())
+            .and_then(|configuration| configuration.as_dictionary_mut()?.get_mut("TestTargets"))
+            .and_then(|targets| targets.as_array_mut()?.last_mut())
+            .and_then(plist::Value::as_dictionary_mut)
+            .unwrap();
+        target.insert(
+            "CommandLineArguments".into(),
+            plist::Value::Array(vec!["-AppleLanguages".into(), "(en)".into()]),
+        );
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("App.xctestrun");
+        xctestrun.to_file_xml(&path)?;
+
+        let error = read(&path, None).await.unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("arguments = -AppleLanguages (en)"),
+            "{}",
+            error
+        );
+        Ok(())
+    }
+}
@@ -1,15 +1,22 @@
 mod android;
+mod filter;
+mod inventory;
 mod ios;
 pub mod model;
 mod validate;
 
 use anyhow::Result;
 use clap::CommandFactory;
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgMatches, Args, FromArgMatches, Parser, Subcommand};
 use std::path::PathBuf;
+use std::time::Duration;
 
+use crate::api::{ClientConfig, RapiReqwestClient};
 use crate::errors::default_error_handler;
+use crate::filtering::convert::{InlineFilters, TestSelection};
+use crate::filtering::model::Shard;
 use crate::interactor::{DownloadArtifactsInteractor, GetDeviceCatalogInteractor};
+use crate::matrix::MatrixAxis;
 
 #[derive(Parser)]
 #[command(
@@ -41,119 +48,107 @@ impl Cli {
             Some(Commands::Run(args)) => {
                 let run_cmd = args.command;
                 match run_cmd {
-                    RunCommands::Android {
-                        application,
-                        test_application,
-                        os_version,
-                        system_image,
-                        device,
-                        common,
-                        api_args,
-                        flavor,
-                        instrumentation_arg,
-                        retry_args,
-                        analytics_args,
-                        pull_files,
-                        application_bundle,
-                        library_bundle,
-                        profiling_args,
-                        mock_location,
-                    } => {
-                        android::run(
-                            application,
-                            test_application,
-                            os_version,
-                            system_image,
-                            device,
-                            common,
-                            api_args,
-                            flavor,
-                            instrumentation_arg,
-                            retry_args,
-                            analytics_args,
-                            profiling_args,
-                            pull_files,
-                            application_bundle,
-                            library_bundle,
-                            mock_location,
-                        )
-                        .await
-                    }
-                    RunCommands::iOS {
-                        application,
-                        test_application,
-                        os_version,
-                        device,
-                        xcode_version,
-                        common,
-                        api_args,
-                        xctestrun_env,
-                        xctestrun_test_env,
-                        xctestplan_filter_file,
-                        xctestplan_target_name,
-                        retry_args,
-                        analytics_args,
-                        test_timeout_default,
-                        test_timeout_max,
-                        granted_permission,
-                    } => {
-                        ios::run(
-                            application,
-                            test_application,
-                            os_version,
-                            device,
-                            xcode_version,
-                            common,
-                            api_args,
-                            xctestrun_env,
-                            xctestrun_test_env,
-                            xctestplan_filter_file,
-                            xctestplan_target_name,
-                            retry_args,
-                            analytics_args,
-                            test_timeout_default,
-                            test_timeout_max,
-                            granted_permission,
-                        )
-                        .await
-                    }
+                    RunCommands::Android(args) => android::run(args).await,
+                    RunCommands::iOS(args) => ios::run(args).await,
                 }
             }
             Some(Commands::Download(args)) => {
                 let interactor = DownloadArtifactsInteractor {};
-                let _ = interactor
+                interactor
                     .execute(
-                        &args.api_args.base_url,
-                        &args.api_args.api_key,
+                        &args.api_args.client()?,
                         &args.id,
                         args.wait,
                         &args.output,
                         args.glob,
                         args.progress_args.no_progress_bars,
                     )
-                    .await;
-                Ok(true)
+                    .await
+                    .map(|_| true)
             }
             Some(Commands::Devices(args)) => {
                 let run_cmd = args.command;
                 let interactor = GetDeviceCatalogInteractor {};
                 match run_cmd {
                     DevicesCommands::Android {
+                        command:
+                            Some(AndroidDevicesCommands::Show {
+                                id,
+                                api_args,
+                                progress_args,
+                                format,
+                            }),
+                        ..
+                    } => interactor
+                        .execute(
+                            &api_args.client()?,
+                            model::DeviceQuery::AndroidDevice { id },
+                            progress_args.no_progress_bars,
+                            format,
+                        )
+                        .await
+                        .map(|_| true),
+                    DevicesCommands::Android {
+                        command: None,
                         api_args,
                         progress_args,
+                        format,
+                        manufacturer,
+                        min_width,
+                        form_factor,
                     } => {
-                        let _ = interactor
+                        // Only subcommands lift the requirement for the API args
+                        let api_args = api_args.0.expect("API args are required");
+                        let filter = model::AndroidDeviceFilter {
+                            manufacturer,
+                            min_width,
+                            form_factor,
+                        };
+                        interactor
                             .execute(
-                                &api_args.base_url,
-                                &api_args.api_key,
-                                &model::Platform::Android,
+                                &api_args.client()?,
+                                model::DeviceQuery::Android(filter),
                                 progress_args.no_progress_bars,
+                                format,
                             )
-                            .await;
+                            .await
+                            .map(|_| true)
                     }
+                    DevicesCommands::Ios {
+                        api_args,
+                        progress_args,
+                        format,
+                    } => interactor
+                        .execute(
+                            &api_args.client()?,
+                            model::DeviceQuery::Ios,
+                            progress_args.no_progress_bars,
+                            format,
+                        )
+                        .await
+                        .map(|_| true),
                 }
-                Ok(true)
             }
+            Some(Commands::Filter(args)) => match args.command {
+                FilterCommands::Evaluate {
+                    filter_file,
+                    tests,
+                    test_application,
+                } => filter::evaluate(filter_file, tests, test_application).await,
+                FilterCommands::Convert {
+                    from,
+                    to,
+                    input,
+                    output,
+                    xctestplan_target_name,
+                } => filter::convert(from, to, input, output, xctestplan_target_name).await,
+            },
+            Some(Commands::Tests(args)) => match args.command {
+                TestsCommands::List {
+                    test_application,
+                    library_bundle,
+                } => inventory::list(test_application, library_bundle).await,
+            },
             Some(Commands::Completions { shell }) => {
                 let mut app = Self::command();
                 let bin_name = app.get_name().to_string();
@@ -176,6 +171,7 @@ impl Cli {
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     #[clap(about = "Submit a test run")]
     Run(RunArgs),
@@ -183,6 +179,10 @@ enum Commands {
     Devices(DevicesArgs),
     #[clap(about = "Download artifacts from a previous test run")]
     Download(DownloadArgs),
+    #[clap(about = "Work with test filters locally")]
+    Filter(FilterArgs),
+    #[clap(about = "Inspect tests of a test application locally")]
+    Tests(TestsArgs),
     #[clap(about = "Output shell completion code for the specified shell (bash, zsh, fish)")]
     Completions { shell: clap_complete::Shell },
 }
@@ -207,9 +207,27 @@ struct CommonRunArgs {
         long,
         help = "Test filters supplied as a YAML file following the schema at https://docs.marathonlabs.io/runner/configuration/filtering/#filtering-logic. 
 For iOS see also https://docs.marathonlabs.io/runner/next/ios#test-plans.
-Please be aware that if you use the 'annotation' filter type on Android, you should add the 'com.malinskiy.adam:android-junit4-test-annotation-producer:<version>' test dependency to parse custom test annotations."
+Please be aware that if you use the 'annotation' filter type on Android, you should add the 'com.malinskiy.adam:android-junit4-test-annotation-producer:<version>' test dependency to parse custom test annotations.
+Can be repeated. Filter files can include other filter files with the top-level 'include' key, paths are relative to the including file. All allowlist filters of all files have to match and any blocklist filter excludes a test."
     )]
-    filter_file: Option<PathBuf>,
+    filter_file: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "INDEX/COUNT",
+        help = "Run only one of COUNT disjoint parts of the test suite, e.g. 0/4 for the first of four parallel CI pipelines. Indices start at 0. Adds a 'fragmentation' filter to the allowlist of --filter-file"
+    )]
+    shard: Option<Shard>,
+
+    #[arg(
+        long,
+        value_name = "DIMENSION=VALUES",
+        help = "Submit one run per combination of the values, e.g. --matrix os-version=11,13,15 --matrix device=pixel,tv. Dimensions are the device arguments of the platform. Binaries are uploaded once, each run downloads its --output into a subfolder named after its values"
+    )]
+    matrix: Vec<MatrixAxis>,
+
+    #[command(flatten)]
+    inline_filter_args: InlineFilterArgs,
 
     #[arg(
         long,
@@ -304,13 +322,131 @@ struct DevicesArgs {
 }
 
 #[derive(Debug, Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum DevicesCommands {
     #[clap(about = "Print supported Android devices")]
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Android {
+        #[command(subcommand)]
+        command: Option<AndroidDevicesCommands>,
+        #[command(flatten)]
+        api_args: ParentApiArgs,
+        #[command(flatten)]
+        progress_args: ProgressArgs,
+        #[arg(long, value_enum, default_value_t = model::OutputFormat::Table)]
+        format: model::OutputFormat,
+        #[arg(long, help = "Only devices of this manufacturer, case-insensitive")]
+        manufacturer: Option<String>,
+        #[arg(
+            long,
+            help = "Only devices with a screen width of at least this many pixels"
+        )]
+        min_width: Option<u32>,
+        #[arg(long, value_enum, help = "Only devices of this form factor")]
+        form_factor: Option<model::FormFactor>,
+    },
+    #[clap(about = "Print supported iOS device, runtime and Xcode combinations")]
+    #[command(name = "ios")]
+    Ios {
         #[command(flatten)]
         api_args: ApiArgs,
         #[command(flatten)]
         progress_args: ProgressArgs,
+        #[arg(long, value_enum, default_value_t = model::OutputFormat::Table)]
+        format: model::OutputFormat,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum AndroidDevicesCommands {
+    #[clap(about = "Print details of an Android device")]
+    Show {
+        #[arg(help = "Device type id, as listed by `marathon-cloud devices android`")]
+        id: String,
+        #[command(flatten)]
+        api_args: ApiArgs,
+        #[command(flatten)]
+        progress_args: ProgressArgs,
+        #[arg(long, value_enum, default_value_t = model::OutputFormat::Table)]
+        format: model::OutputFormat,
+    },
+}
+
+#[derive(Debug, clap::Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+struct FilterArgs {
+    #[command(subcommand)]
+    command: FilterCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum FilterCommands {
+    #[clap(about = "Print which tests are selected by a filter file without submitting a run")]
+    Evaluate {
+        #[arg(
+            long,
+            help = "Test filters supplied as a YAML file following the schema at https://docs.marathonlabs.io/runner/configuration/filtering/#filtering-logic"
+        )]
+        filter_file: PathBuf,
+        #[arg(
+            long,
+            required_unless_present = "test_application",
+            help = "Text file with one test per line, e.g. com.example.LoginTest#testLogin"
+        )]
+        tests: Option<PathBuf>,
+        #[arg(
+            long,
+            conflicts_with = "tests",
+            help = "Test application to read the tests from, e.g. app-debug-androidTest.apk or sample-appUITests-Runner.app"
+        )]
+        test_application: Option<PathBuf>,
+    },
+    #[clap(about = "Convert test selection between Marathon filter files and .xctestplan")]
+    Convert {
+        #[arg(long, value_enum, help = "Format of the input file")]
+        from: filter::FilterFormat,
+        #[arg(long, value_enum, help = "Format of the output file")]
+        to: filter::FilterFormat,
+        #[arg(long, help = "File to convert")]
+        input: PathBuf,
+        #[arg(
+            long,
+            required_if_eq("to", "xctestplan"),
+            help = "Output file, Marathon filters are printed to stdout if omitted. For xctestplan this is an existing test plan: only selectedTests and skippedTests of its targets are replaced"
+        )]
+        output: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Target of the .xctestplan to read or update. Can be repeated. Reading defaults to all enabled targets, updating to the only target of the plan"
+        )]
+        xctestplan_target_name: Vec<String>,
+    },
+}
+
+#[derive(Debug, clap::Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+struct TestsArgs {
+    #[command(subcommand)]
+    command: TestsCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum TestsCommands {
+    #[clap(about = "Print tests of a test application as JSON")]
+    List {
+        #[arg(
+            long,
+            required_unless_present = "library_bundle",
+            help = "Test application, e.g. app-debug-androidTest.apk or sample-appUITests-Runner.app"
+        )]
+        test_application: Option<PathBuf>,
+        #[arg(
+            long,
+            conflicts_with = "test_application",
+            help = "Library test apk, can be repeated.
+Example: '--library-bundle apks/library1-debug-androidTest.apk --library-bundle apks/library2-debug-androidTest.apk'"
+        )]
+        library_bundle: Option<Vec<PathBuf>>,
     },
 }
 
@@ -326,6 +462,98 @@ struct ApiArgs {
         help = "Base url for Marathon Cloud API"
     )]
     base_url: String,
+
+    #[command(flatten)]
+    network_args: NetworkArgs,
+}
+
+impl ApiArgs {
+    fn client(&self) -> Result<RapiReqwestClient> {
+        let network = &self.network_args;
+        let config = ClientConfig {
+            proxy: network.proxy.clone(),
+            ca_cert: network.ca_cert.clone(),
+            client_cert: network.client_cert.clone(),
+            client_key: network.client_key.clone(),
+            connect_timeout: network.connect_timeout.map(Duration::from_secs),
+            read_timeout: network.read_timeout.map(Duration::from_secs),
+        };
+        RapiReqwestClient::with_config(&self.base_url, &self.api_key, &config)
+    }
+}
+
+/// [`ApiArgs`] of a command whose subcommands take their own copy, missing when a subcommand is used.
+/// `Option<ApiArgs>` can't be flattened instead, clap leaves it empty because of the nested [`NetworkArgs`]
+#[derive(Debug)]
+struct ParentApiArgs(Option<ApiArgs>);
+
+impl FromArgMatches for ParentApiArgs {
+    fn from_arg_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        Ok(ParentApiArgs(ApiArgs::from_arg_matches(matches).ok()))
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        *self = Self::from_arg_matches(matches)?;
+        Ok(())
+    }
+}
+
+impl Args for ParentApiArgs {
+    fn augment_args(cmd: clap::Command) -> clap::Command {
+        ApiArgs::augment_args(cmd)
+    }
+
+    fn augment_args_for_update(cmd: clap::Command) -> clap::Command {
+        ApiArgs::augment_args_for_update(cmd)
+    }
+}
+
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true)]
+struct NetworkArgs {
+    #[arg(
+        long,
+        env("MARATHON_CLOUD_PROXY"),
+        help = "Proxy url for all requests, example: http://proxy.corp:3128. 
+By default HTTP_PROXY, HTTPS_PROXY and NO_PROXY environment variables are respected"
+    )]
+    proxy: Option<String>,
+
+    #[arg(
+        long,
+        env("MARATHON_CLOUD_CA_CERT"),
+        help = "PEM file with additional trusted root certificates, e.g. of a TLS-intercepting proxy"
+    )]
+    ca_cert: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env("MARATHON_CLOUD_CLIENT_CERT"),
+        help = "PEM file with the client certificate for mutual TLS. May also contain the private key"
+    )]
+    client_cert: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env("MARATHON_CLOUD_CLIENT_KEY"),
+        requires = "client_cert",
+        help = "PEM file with the private key for --client-cert"
+    )]
+    client_key: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env("MARATHON_CLOUD_CONNECT_TIMEOUT"),
+        help = "Connection timeout in seconds"
+    )]
+    connect_timeout: Option<u64>,
+
+    #[arg(
+        long,
+        env("MARATHON_CLOUD_READ_TIMEOUT"),
+        help = "Read timeout in seconds for each network read, including uploads and downloads"
+    )]
+    read_timeout: Option<u64>,
 }
 
 #[derive(Debug, Args)]
@@ -393,6 +621,93 @@ struct ProgressArgs {
     no_progress_bars: bool,
 }
 
+#[derive(Debug, Args, Clone)]
+#[command(args_conflicts_with_subcommands = true)]
+struct InlineFilterArgs {
+    #[arg(
+        long,
+        value_name = "CLASS",
+        help = "Run tests of the class, given as a simple or fully-qualified name. Includes are combined with each other and with the --filter-file allowlist as a union"
+    )]
+    include_class: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "TEST",
+        help = "Run the test, given as ClassName#method or com.example.ClassName#method"
+    )]
+    include_test: Vec<String>,
+
+    #[arg(long, value_name = "PACKAGE", help = "Run tests of the package")]
+    include_package: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "ANNOTATION",
+        help = "Run tests with the annotation, given as a fully-qualified name"
+    )]
+    include_annotation: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Run tests whose fully-qualified name, e.g. com.example.ClassName#method, matches the regex"
+    )]
+    include_regex: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "CLASS",
+        help = "Skip tests of the class, given as a simple or fully-qualified name. Excludes are added to the --filter-file blocklist"
+    )]
+    exclude_class: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "TEST",
+        help = "Skip the test, given as ClassName#method or com.example.ClassName#method"
+    )]
+    exclude_test: Vec<String>,
+
+    #[arg(long, value_name = "PACKAGE", help = "Skip tests of the package")]
+    exclude_package: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "ANNOTATION",
+        help = "Skip tests with the annotation, given as a fully-qualified name"
+    )]
+    exclude_annotation: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Skip tests whose fully-qualified name matches the regex"
+    )]
+    exclude_regex: Vec<String>,
+}
+
+impl From<InlineFilterArgs> for InlineFilters {
+    fn from(args: InlineFilterArgs) -> Self {
+        InlineFilters {
+            include: TestSelection {
+                classes: args.include_class,
+                tests: args.include_test,
+                packages: args.include_package,
+                annotations: args.include_annotation,
+                regexes: args.include_regex,
+            },
+            exclude: TestSelection {
+                classes: args.exclude_class,
+                tests: args.exclude_test,
+                packages: args.exclude_package,
+                annotations: args.exclude_annotation,
+                regexes: args.exclude_regex,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Args, Clone)]
 #[command(args_conflicts_with_subcommands = true)]
 struct ResultFileArgs {
@@ -406,165 +721,206 @@ struct ResultFileArgs {
 #[derive(Debug, Subcommand)]
 enum RunCommands {
     #[clap(about = "Run tests for Android")]
-    Android {
-        #[arg(
-            short,
-            long,
-            help = "application filepath, example: /home/user/workspace/sample.apk"
-        )]
-        application: Option<PathBuf>,
+    Android(AndroidRunArgs),
+    #[allow(non_camel_case_types)]
+    #[command(name = "ios")]
+    #[clap(about = "Run tests for iOS")]
+    iOS(IosRunArgs),
+}
 
-        #[arg(
-            short,
-            long,
-            help = "test application filepath, example: /home/user/workspace/testSample.apk"
-        )]
-        test_application: Option<PathBuf>,
+#[derive(Debug, Args)]
+struct AndroidRunArgs {
+    #[arg(
+        short,
+        long,
+        help = "application filepath, example: /home/user/workspace/sample.apk. An APK set (.apks) is reduced to its universal APK, or to its x86 standalone APK when --os-version is below 5.0. Sets with only split APKs are rejected since splits can't be uploaded as a single APK. An Android App Bundle (.aab) is converted into a universal APK for every device with bundletool from PATH or the BUNDLETOOL environment variable"
+    )]
+    application: Option<PathBuf>,
 
-        #[arg(value_enum, long, help = "OS version")]
-        os_version: Option<android::OsVersion>,
+    #[arg(
+        short,
+        long,
+        help = "test application filepath, example: /home/user/workspace/testSample.apk"
+    )]
+    test_application: Option<PathBuf>,
 
-        #[arg(value_enum, long, help = "Runtime system image")]
-        system_image: Option<android::SystemImage>,
+    #[arg(long, help = "OS version, example: 14")]
+    os_version: Option<String>,
 
-        #[arg(
-            value_enum,
-            long,
-            help = "Device type id. Use `marathon-cloud devices android` to get a list of supported devices"
-        )]
-        device: Option<String>,
+    #[arg(value_enum, long, help = "Runtime system image")]
+    system_image: Option<android::SystemImage>,
 
-        #[arg(value_enum, long, help = "Test flavor")]
-        flavor: Option<android::Flavor>,
+    #[arg(
+        value_enum,
+        long,
+        help = "Device type id. Use `marathon-cloud devices android` to get a list of supported devices"
+    )]
+    device: Option<String>,
 
-        #[command(flatten)]
-        common: CommonRunArgs,
+    #[arg(value_enum, long, help = "Test flavor")]
+    flavor: Option<android::Flavor>,
 
-        #[command(flatten)]
-        api_args: ApiArgs,
+    #[command(flatten)]
+    common: CommonRunArgs,
 
-        #[command(flatten)]
-        retry_args: RetryArgs,
+    #[command(flatten)]
+    api_args: ApiArgs,
 
-        #[command(flatten)]
-        analytics_args: AnalyticsArgs,
+    #[command(flatten)]
+    retry_args: RetryArgs,
 
-        #[command(flatten)]
-        profiling_args: ProfilingArgs,
+    #[command(flatten)]
+    analytics_args: AnalyticsArgs,
+
+    #[command(flatten)]
+    profiling_args: ProfilingArgs,
 
-        #[arg(long, help = "Instrumentation arguments, example: FOO=BAR")]
-        instrumentation_arg: Option<Vec<String>>,
+    #[arg(long, help = "Instrumentation arguments, example: FOO=BAR")]
+    instrumentation_arg: Option<Vec<String>>,
 
-        #[arg(
-            long,
-            help = "Pull files from devices after the test run. 
+    #[arg(
+        long,
+        help = "Pull files from devices after the test run. 
 The format is 'ROOT:PATH' where ROOT is one of [EXTERNAL_STORAGE, APP_DATA] and PATH is a relative path to the target file or directory. 
 Example: 'EXTERNAL_STORAGE:Documents/some-results', 'APP_DATA:files/my_folder/some_file.txt'. 
 Note: Files with the same name and path from different devices may overwrite each other."
-        )]
-        pull_files: Option<Vec<String>>,
+    )]
+    pull_files: Option<Vec<String>>,
 
-        #[arg(
-            long,
-            conflicts_with_all = &["application", "test_application"],
-            help = "Application bundle containing the application apk and test application apk.
+    #[arg(
+        long,
+        conflicts_with_all = &["application", "test_application"],
+        help = "Application bundle containing the application apk and test application apk.
 The format is '<app_apk_path>,<test_apk_path>'. The delimeter is a comma.
 Example: '--application-bundle apks/feature1-app-debug.apk,apks/feature1-app-debug-androidTest.apk --application-bundle apks/feature2-app-debug.apk,apks/feature2-app-debug-androidTest.apk'"
-        )]
-        application_bundle: Option<Vec<String>>,
+    )]
+    application_bundle: Option<Vec<String>>,
 
-        #[arg(
-            long,
-            conflicts_with_all = &["application", "test_application"],
-            help = "Library bundle containing the library test apk. Library testing requires only Test APK.
+    #[arg(
+        long,
+        conflicts_with_all = &["application", "test_application"],
+        help = "Library bundle containing the library test apk. Library testing requires only Test APK.
 The format is '<test_apk_path>'.
 Example: '--library-bundle apks/library1-debug-androidTest.apk --library-bundle apks/library2-debug-androidTest.apk'"
-        )]
-        library_bundle: Option<Vec<PathBuf>>,
+    )]
+    library_bundle: Option<Vec<PathBuf>>,
 
-        #[arg(
-            long,
-            default_value_t = false,
-            help = "Allow mock location access for application"
-        )]
-        mock_location: bool,
-    },
-    #[allow(non_camel_case_types)]
-    #[command(name = "ios")]
-    #[clap(about = "Run tests for iOS")]
-    iOS {
-        #[arg(
-            short,
-            long,
-            help = "application filepath, example: /home/user/workspace/sample.zip"
-        )]
-        application: PathBuf,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Allow mock location access for application"
+    )]
+    mock_location: bool,
+}
 
-        #[arg(
-            short,
-            long,
-            help = "test application filepath, example: /home/user/workspace/sampleUITests-Runner.zip"
-        )]
-        test_application: PathBuf,
+#[derive(Debug, Args)]
+struct IosRunArgs {
+    #[arg(
+        short,
+        long,
+        required_unless_present = "xctestrun",
+        help = "application filepath, example: /home/user/workspace/sample.zip"
+    )]
+    application: Option<PathBuf>,
 
-        #[arg(value_enum, long, help = "iOS runtime version")]
-        os_version: Option<ios::OsVersion>,
+    #[arg(
+        short,
+        long,
+        required_unless_present = "xctestrun",
+        help = "test application filepath, example: /home/user/workspace/sampleUITests-Runner.zip. A warning is printed when its test bundle ids don't extend the application bundle id, which is only a heuristic since built bundles don't record the application under test"
+    )]
+    test_application: Option<PathBuf>,
 
-        #[arg(value_enum, long, help = "Device type")]
-        device: Option<ios::IosDevice>,
+    #[arg(
+        long,
+        conflicts_with_all = &["application", "test_application"],
+        help = "The .xctestrun file of `xcodebuild build-for-testing`. The application, the test runner and the environment variables of its UI test target are used, --xctestrun-env and --xctestrun-test-env take precedence"
+    )]
+    xctestrun: Option<PathBuf>,
 
-        #[arg(value_enum, long, help = "Xcode version")]
-        xcode_version: Option<ios::XcodeVersion>,
+    #[arg(
+        long,
+        requires = "xctestrun",
+        conflicts_with_all = &["application", "test_application"],
+        help = "UI test target of the .xctestrun to run. Can be omitted when there is a single UI test target"
+    )]
+    xctestrun_target: Option<String>,
 
-        #[command(flatten)]
-        common: CommonRunArgs,
+    #[arg(
+        long,
+        help = "iOS runtime version, example: 18.2. Use `marathon-cloud devices ios` to get a list of supported combinations"
+    )]
+    os_version: Option<String>,
 
-        #[command(flatten)]
-        api_args: ApiArgs,
+    #[arg(
+        long,
+        help = "Device type, example: iPhone-16. Use `marathon-cloud devices ios` to get a list of supported combinations"
+    )]
+    device: Option<String>,
 
-        #[command(flatten)]
-        retry_args: RetryArgs,
+    #[arg(
+        long,
+        help = "Xcode version, example: 16.2. Use `marathon-cloud devices ios` to get a list of supported combinations"
+    )]
+    xcode_version: Option<String>,
 
-        #[command(flatten)]
-        analytics_args: AnalyticsArgs,
+    #[command(flatten)]
+    common: CommonRunArgs,
 
-        #[arg(
-            long,
-            help = "xctestrun environment variable (EnvironmentVariables item), example FOO=BAR"
-        )]
-        xctestrun_env: Option<Vec<String>>,
+    #[command(flatten)]
+    api_args: ApiArgs,
 
-        #[arg(
-            long,
-            help = "xctestrun testing environment variable (TestingEnvironmentVariables item), example FOO=BAR"
-        )]
-        xctestrun_test_env: Option<Vec<String>>,
+    #[command(flatten)]
+    retry_args: RetryArgs,
 
-        #[arg(long, help = "Test filters supplied as .xctestplan file")]
-        xctestplan_filter_file: Option<PathBuf>,
+    #[command(flatten)]
+    analytics_args: AnalyticsArgs,
 
-        #[arg(long, help = "Target name to use for test filtering in .xctestplan")]
-        xctestplan_target_name: Option<String>,
+    #[arg(
+        long,
+        help = "xctestrun environment variable (EnvironmentVariables item), example FOO=BAR"
+    )]
+    xctestrun_env: Option<Vec<String>>,
 
-        #[arg(
-            long,
-            default_value = "300",
-            help = "Default timeout for each test in seconds"
-        )]
-        test_timeout_default: Option<u32>,
+    #[arg(
+        long,
+        help = "xctestrun testing environment variable (TestingEnvironmentVariables item), example FOO=BAR"
+    )]
+    xctestrun_test_env: Option<Vec<String>>,
 
-        #[arg(
-            long,
-            help = "Maximum test timeout in seconds, overriding all other test timeout settings"
-        )]
-        test_timeout_max: Option<u32>,
+    #[arg(long, help = "Test filters supplied as .xctestplan file")]
+    xctestplan_filter_file: Option<PathBuf>,
 
-        #[arg(
-            long,
-            help = "Grant permission to application.
+    #[arg(
+        long,
+        help = "Target name to use for test filtering in .xctestplan. Can be repeated. By default all enabled targets are used"
+    )]
+    xctestplan_target_name: Vec<String>,
+
+    #[arg(
+        long,
+        requires = "xctestplan_filter_file",
+        help = "Configuration of the .xctestplan whose environment variables, language, region, test timeouts, repetitions and code coverage are applied to the run. Explicit flags take precedence. Can be omitted when the test plan has a single configuration"
+    )]
+    xctestplan_configuration: Option<String>,
+
+    #[arg(
+        long,
+        help = "Default timeout for each test in seconds [default: 300, or the test plan's execution time allowance]"
+    )]
+    test_timeout_default: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Maximum test timeout in seconds, overriding all other test timeout settings"
+    )]
+    test_timeout_max: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Grant permission to application.
 Important: Granting is conducted before each test batch (not each test). If you need to grant before each test, please use --isolated mode.
 Available permissions: calendar, contacts-limited, contacts, location, location-always, photos-add, photos, media-library, microphone, motion, reminders, siri."
-        )]
-        granted_permission: Option<Vec<String>>,
-    },
+    )]
+    granted_permission: Option<Vec<String>>,
 }
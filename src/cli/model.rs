@@ -1,5 +1,14 @@
 use std::fmt::Display;
 
+use crate::api::AndroidDevice;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Yaml,
+}
+
 #[derive(Debug)]
 pub enum Platform {
     Android,
@@ -15,3 +24,121 @@ impl Display for Platform {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FormFactor {
+    Phone,
+    Tv,
+    Watch,
+    Wear,
+}
+
+impl FormFactor {
+    /// The catalog doesn't report form factors, they follow from the device type ids
+    pub fn of(device: &AndroidDevice) -> FormFactor {
+        let id = device.id.to_lowercase();
+        if id.starts_with("tv") || id.contains("_tv") {
+            FormFactor::Tv
+        } else if id.contains("wear") {
+            FormFactor::Wear
+        } else if id.contains("watch") {
+            FormFactor::Watch
+        } else {
+            FormFactor::Phone
+        }
+    }
+}
+
+impl Display for FormFactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormFactor::Phone => f.write_str("phone"),
+            FormFactor::Tv => f.write_str("tv"),
+            FormFactor::Watch => f.write_str("watch"),
+            FormFactor::Wear => f.write_str("wear"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct AndroidDeviceFilter {
+    /// Case-insensitive
+    pub manufacturer: Option<String>,
+    pub min_width: Option<u32>,
+    pub form_factor: Option<FormFactor>,
+}
+
+impl AndroidDeviceFilter {
+    pub fn matches(&self, device: &AndroidDevice) -> bool {
+        self.manufacturer
+            .as_ref()
+            .map_or(true, |m| m.eq_ignore_ascii_case(&device.manufacturer))
+            && self.min_width.map_or(true, |w| device.width >= w)
+            && self
+                .form_factor
+                .map_or(true, |f| f == FormFactor::of(device))
+    }
+}
+
+#[derive(Debug)]
+pub enum DeviceQuery {
+    Ios,
+    Android(AndroidDeviceFilter),
+    AndroidDevice { id: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(id: &str, manufacturer: &str, width: u32) -> AndroidDevice {
+        AndroidDevice {
+            name: id.to_owned(),
+            id: id.to_owned(),
+            manufacturer: manufacturer.to_owned(),
+            width,
+            height: 1080,
+            dpi: 320,
+        }
+    }
+
+    #[test]
+    fn test_android_device_filter() {
+        let devices = [
+            device("pixel_6", "Google", 1080),
+            device("tv_1080p", "Google", 1920),
+            device("wearos_small_round", "Google", 384),
+            device("Galaxy Nexus", "Samsung", 720),
+        ];
+        let ids = |filter: AndroidDeviceFilter| {
+            devices
+                .iter()
+                .filter(|d| filter.matches(d))
+                .map(|d| d.id.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ids(AndroidDeviceFilter {
+                manufacturer: Some("google".into()),
+                min_width: Some(1000),
+                ..Default::default()
+            }),
+            vec!["pixel_6", "tv_1080p"]
+        );
+        assert_eq!(
+            ids(AndroidDeviceFilter {
+                form_factor: Some(FormFactor::Phone),
+                ..Default::default()
+            }),
+            vec!["pixel_6", "Galaxy Nexus"]
+        );
+        assert_eq!(
+            ids(AndroidDeviceFilter {
+                form_factor: Some(FormFactor::Wear),
+                ..Default::default()
+            }),
+            vec!["wearos_small_round"]
+        );
+    }
+}
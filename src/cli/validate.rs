@@ -1,4 +1,12 @@
-use crate::{cli::RetryArgs, errors::InputError};
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    cli::RetryArgs,
+    errors::{ConfigurationError, InputError},
+    formatter,
+    inventory::{self, AndroidManifest, BundleInfo, IosBundle},
+    spec::AndroidRunSpec,
+};
 use anyhow::Result;
 
 pub(crate) fn retry_args(retry_args: RetryArgs) -> RetryArgs {
@@ -29,3 +37,359 @@ pub(crate) fn result_file_args(result_file_args: &super::ResultFileArgs) -> Resu
         Ok(())
     }
 }
+
+/// API level of an Android version like `14` or `8.1`
+pub(crate) fn api_level(os_version: &str) -> Option<u32> {
+    let level = match os_version {
+        "5" | "5.0" => 21,
+        "5.1" => 22,
+        "6" | "6.0" => 23,
+        "7" | "7.0" => 24,
+        "7.1" => 25,
+        "8" | "8.0" => 26,
+        "8.1" => 27,
+        version => {
+            let major: u32 = version.strip_suffix(".0").unwrap_or(version).parse().ok()?;
+            // Android 12L is API level 32, every later major version adds one level
+            match major {
+                9..=12 => major + 19,
+                13.. => major + 20,
+                _ => return None,
+            }
+        }
+    };
+    Some(level)
+}
+
+/// Checks the manifests of the APKs against each other and against the Android versions of the runs.
+/// APKs with a manifest that can't be read locally are left to the server
+pub(crate) async fn android_binaries(
+    android: &AndroidRunSpec,
+    os_versions: &[Option<String>],
+) -> Result<()> {
+    let mut pairs: Vec<(Option<&Path>, &Path)> = vec![];
+    if let Some(test_application) = &android.test_application {
+        pairs.push((android.application.as_deref(), test_application));
+    }
+    for bundle in android.application_bundle.iter().flatten() {
+        pairs.push((Some(&bundle.app_path), &bundle.test_app_path));
+    }
+    for test_application in android.library_bundle.iter().flatten() {
+        pairs.push((None, test_application));
+    }
+
+    let mut manifests: HashMap<&Path, Option<AndroidManifest>> = HashMap::new();
+    let paths = pairs
+        .iter()
+        .flat_map(|(application, test_application)| [*application, Some(*test_application)])
+        .chain([android.application.as_deref()])
+        .flatten();
+    for path in paths {
+        if manifests.contains_key(path) {
+            continue;
+        }
+        let manifest = match inventory::android_manifest(path).await {
+            Ok(manifest) => Some(manifest),
+            Err(error) => {
+                formatter::warning(&format!(
+                    "Skipping local checks of {}: {}",
+                    path.display(),
+                    error
+                ));
+                None
+            }
+        };
+        manifests.insert(path, manifest);
+    }
+
+    for (application, test_application) in pairs {
+        let Some(test_manifest) = &manifests[test_application] else {
+            continue;
+        };
+        if test_manifest.instrumentations.is_empty() {
+            anyhow::bail!(ConfigurationError::MissingInstrumentation {
+                path: test_application.to_path_buf(),
+            });
+        }
+        let Some(application) = application else {
+            continue;
+        };
+        let Some(app_manifest) = &manifests[application] else {
+            continue;
+        };
+        if !test_manifest
+            .instrumentations
+            .iter()
+            .any(|instrumentation| instrumentation.target_package == app_manifest.package)
+        {
+            anyhow::bail!(ConfigurationError::TargetPackageMismatch {
+                test_application: test_application.to_path_buf(),
+                target_package: test_manifest.instrumentations[0].target_package.clone(),
+                application: application.to_path_buf(),
+                package: app_manifest.package.clone(),
+            });
+        }
+    }
+
+    for os_version in os_versions.iter().flatten() {
+        let Some(api_level) = api_level(os_version) else {
+            continue;
+        };
+        for (path, manifest) in &manifests {
+            let Some(min_sdk_version) = manifest.as_ref().and_then(|m| m.min_sdk_version) else {
+                continue;
+            };
+            if min_sdk_version > api_level {
+                anyhow::bail!(ConfigurationError::MinSdkVersionNotSupported {
+                    path: path.to_path_buf(),
+                    min_sdk_version,
+                    os_version: os_version.clone(),
+                    api_level,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+const SIMULATOR_PLATFORM: &str = "iPhoneSimulator";
+
+/// Checks the Info.plist files of the application and the test runner against the iOS versions of the runs.
+/// Bundles that can't be read locally are left to the server.
+pub(crate) async fn ios_binaries(
+    application: &Path,
+    test_application: &Path,
+    os_versions: &[Option<String>],
+) -> Result<()> {
+    let app = read_ios_bundle(application).await;
+    let runner = read_ios_bundle(test_application).await;
+    if runner
+        .as_ref()
+        .is_some_and(|runner| runner.test_bundles.is_empty())
+    {
+        anyhow::bail!(ConfigurationError::MissingXctestBundle {
+            path: test_application.to_path_buf(),
+        });
+    }
+
+    let app_bundles = app.iter().map(|app| (application, &app.info));
+    let runner_bundles = runner.iter().flat_map(|runner| {
+        [&runner.info]
+            .into_iter()
+            .chain(&runner.test_bundles)
+            .map(|info| (test_application, info))
+    });
+    let bundles: Vec<(&Path, &BundleInfo)> = app_bundles.chain(runner_bundles).collect();
+    for (path, info) in &bundles {
+        if !info.supported_platforms.is_empty()
+            && !info
+                .supported_platforms
+                .iter()
+                .any(|platform| platform == SIMULATOR_PLATFORM)
+        {
+            anyhow::bail!(ConfigurationError::NotBuiltForSimulator {
+                path: path.to_path_buf(),
+                platforms: info.supported_platforms.join(","),
+            });
+        }
+    }
+
+    for os_version in os_versions.iter().flatten() {
+        for (path, info) in &bundles {
+            let Some(minimum_os_version) = &info.minimum_os_version else {
+                continue;
+            };
+            if version_parts(minimum_os_version) > version_parts(os_version) {
+                anyhow::bail!(ConfigurationError::MinimumOsVersionNotSupported {
+                    path: path.to_path_buf(),
+                    minimum_os_version: minimum_os_version.clone(),
+                    os_version: os_version.clone(),
+                });
+            }
+        }
+    }
+
+    let (Some(app), Some(runner)) = (app, runner) else {
+        return Ok(());
+    };
+    // Built bundles don't record the target application of UI tests, Xcode's
+    // default is a test bundle id that extends the application bundle id
+    if let Some(app_identifier) = &app.info.identifier {
+        let test_identifiers: Vec<&str> = runner
+            .test_bundles
+            .iter()
+            .filter_map(|info| info.identifier.as_deref())
+            .collect();
+        if !test_identifiers.is_empty()
+            && !test_identifiers
+                .iter()
+                .any(|identifier| identifier.starts_with(app_identifier.as_str()))
+        {
+            formatter::warning(&format!(
+                "Test bundles {} don't look like tests of {}, double check --application matches --test-application",
+                test_identifiers.join(","),
+                app_identifier
+            ));
+        }
+    }
+    Ok(())
+}
+
+async fn read_ios_bundle(path: &Path) -> Option<IosBundle> {
+    match inventory::ios_bundle(path).await {
+        Ok(bundle) => Some(bundle),
+        Err(error) => {
+            formatter::warning(&format!(
+                "Skipping local checks of {}: {}",
+                path.display(),
+                error
+            ));
+            None
+        }
+    }
+}
+
+/// `17.5` as `[17, 5, 0]`, so that `17.5` equals `17.5.0`
+fn version_parts(version: &str) -> Vec<u32> {
+    let mut parts: Vec<u32> = version
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect();
+    parts.resize(parts.len().max(3), 0);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        Path::new(&manifest_dir)
+            .join("fixture")
+            .join("inventory")
+            .join(name)
+    }
+
+    fn spec(application: &str, test_application: &str) -> AndroidRunSpec {
+        AndroidRunSpec {
+            application: Some(fixture(application)),
+            test_application: Some(fixture(test_application)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_api_level() {
+        assert_eq!(api_level("8.1"), Some(27));
+        assert_eq!(api_level("12"), Some(31));
+        assert_eq!(api_level("14"), Some(34));
+        assert_eq!(api_level("15.0"), Some(35));
+        assert_eq!(api_level("4.4"), None);
+    }
+
+    #[tokio::test]
+    async fn test_android_binaries() -> Result<()> {
+        let spec = spec("app.apk", "app-androidTest.apk");
+        android_binaries(&spec, &[None, Some("14".into())]).await?;
+
+        let error = android_binaries(&spec, &[Some("7".into())])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("API level 24"), "{}", error);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_android_binaries_mismatch() {
+        let error = android_binaries(&spec("app-androidTest.apk", "app-androidTest.apk"), &[])
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("target package = com.example\n"),
+            "{}",
+            error
+        );
+
+        let error = android_binaries(&spec("app.apk", "app.apk"), &[])
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("doesn't declare an instrumentation"));
+    }
+
+    #[test]
+    fn test_version_parts() {
+        assert!(version_parts("17.5") == version_parts("17.5.0"));
+        assert!(version_parts("16.4") < version_parts("17"));
+        assert!(version_parts("18.2") > version_parts("18.1.1"));
+    }
+
+    #[tokio::test]
+    async fn test_ios_binaries() -> Result<()> {
+        let app = fixture("App.app");
+        let runner = fixture("AppUITests-Runner.app");
+        ios_binaries(&app, &runner, &[None, Some("17.5".into())]).await?;
+
+        let error = ios_binaries(&app, &runner, &[Some("15.5".into())])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("MinimumOSVersion = 16.0"));
+
+        let error = ios_binaries(&app, &app, &[]).await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("doesn't contain any xctest bundles"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ios_binaries_device_build() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let app = dir.path().join("App.app");
+        std::fs::create_dir(&app)?;
+        let mut info = plist::Dictionary::new();
+        info.insert("CFBundleIdentifier".into(), "com.example.App".into());
+        info.insert(
+            "CFBundleSupportedPlatforms".into(),
+            plist::Value::Array(vec!["iPhoneOS".into()]),
+        );
+        plist::to_file_binary(app.join("Info.plist"), &info)?;
+
+        let error = ios_binaries(&app, &fixture("AppUITests-Runner.app"), &[])
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("platforms = iPhoneOS"),
+            "{}",
+            error
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ios_binaries_unreadable_bundle() -> Result<()> {
+        // The bundle isn't at the top level of the archive, so it's left to the server
+        let dir = tempfile::tempdir()?;
+        let build = dir.path().join("build");
+        std::fs::create_dir(&build)?;
+        let runner = build.join("AppUITests-Runner.app");
+        std::fs::create_dir(&runner)?;
+        std::fs::copy(
+            fixture("AppUITests-Runner.app/Info.plist"),
+            runner.join("Info.plist"),
+        )?;
+        let archive = dir.path().join("runner.zip");
+        crate::compression::zip_dir(&build, tokio::fs::File::create(&archive).await?).await?;
+
+        ios_binaries(&fixture("App.app"), &archive, &[Some("17.5".into())]).await
+    }
+
+    #[tokio::test]
+    async fn test_android_binaries_unreadable_manifest() -> Result<()> {
+        android_binaries(&spec("../tls/client.crt", "../tls/client.crt"), &[]).await
+    }
+}
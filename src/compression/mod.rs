@@ -1,45 +1,181 @@
-use std::path::Path;
+use std::{fs::Metadata, path::Path};
 
 use anyhow::Context;
-use async_zip::{tokio::write::ZipFileWriter, Compression, ZipEntryBuilder};
+use async_zip::{
+    tokio::write::ZipFileWriter, Compression, ZipDateTime, ZipDateTimeBuilder, ZipEntryBuilder,
+};
 use log::debug;
-use tokio::{fs::File, io::AsyncReadExt};
-use walkdir::DirEntry;
-
-pub async fn zip_dir<T>(
-    it: &mut dyn Iterator<Item = DirEntry>,
-    prefix: &str,
-    mut writer: T,
-) -> anyhow::Result<()>
+use tokio::{
+    fs::{self, File},
+    io::AsyncWriteExt,
+};
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use walkdir::WalkDir;
+
+const S_IFDIR: u16 = 0o040000;
+const S_IFREG: u16 = 0o100000;
+const S_IFLNK: u16 = 0o120000;
+/// Used where the file system has no unix permissions
+#[cfg(not(unix))]
+const DEFAULT_PERMISSIONS: u16 = 0o755;
+
+/// Streams `dir` into a zip archive with entry names relative to the parent of `dir`, e.g. `App.app/Info.plist`.
+/// Directories get entries of their own and symlinks are stored as links, e.g. `Versions/Current` of frameworks.
+/// Entries are walked in file-name order and carry a fixed timestamp, so identical folders produce identical archives.
+pub async fn zip_dir<T>(dir: &Path, writer: T) -> anyhow::Result<()>
 where
     T: tokio::io::AsyncWrite + Unpin,
 {
-    let unix_permissions = 0o755;
-    let compression_method = Compression::Deflate;
-    let mut zip = ZipFileWriter::with_tokio(&mut writer);
+    let prefix = dir.parent().unwrap_or(dir);
+    let mut zip = ZipFileWriter::with_tokio(writer);
 
-    let prefix = Path::new(prefix);
-    let mut buffer = Vec::new();
-    for entry in it {
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
         let path = entry.path();
         let name = path.strip_prefix(prefix)?;
-        let path_as_string = name
+        let name = name
             .to_str()
-            .map(str::to_owned)
-            .with_context(|| format!("{name:?} Is a Non UTF-8 Path"))?;
+            .with_context(|| format!("{name:?} Is a Non UTF-8 Path"))?
+            .replace(std::path::MAIN_SEPARATOR, "/");
+        // Doesn't follow symlinks
+        let metadata = entry.metadata()?;
+        let permissions = permissions(&metadata);
 
-        if path.is_file() {
+        if metadata.is_dir() {
+            let builder = ZipEntryBuilder::new(format!("{name}/").into(), Compression::Stored)
+                .unix_permissions(S_IFDIR | permissions)
+                .last_modification_date(timestamp());
+            zip.write_entry_whole(builder, &[]).await?;
+        } else if metadata.is_symlink() {
+            let target = fs::read_link(path).await?;
+            let target = target
+                .to_str()
+                .with_context(|| format!("{target:?} Is a Non UTF-8 Path"))?
+                .replace(std::path::MAIN_SEPARATOR, "/");
+            debug!("adding symlink {path:?} as {name:?} -> {target:?} ...");
+            let builder = ZipEntryBuilder::new(name.into(), Compression::Stored)
+                .unix_permissions(S_IFLNK | permissions)
+                .last_modification_date(timestamp());
+            zip.write_entry_whole(builder, target.as_bytes()).await?;
+        } else {
             debug!("adding file {path:?} as {name:?} ...");
-            let mut f = File::open(path).await?;
-            f.read_to_end(&mut buffer).await?;
+            let builder = ZipEntryBuilder::new(name.into(), Compression::Deflate)
+                .unix_permissions(S_IFREG | permissions)
+                .last_modification_date(timestamp());
+            let file = File::open(path).await?;
+            let mut entry_writer = zip.write_entry_stream(builder).await?;
+            futures::io::copy(file.compat(), &mut entry_writer).await?;
+            entry_writer.close().await?;
+        }
+    }
+    let mut writer = zip.close().await?.into_inner();
+    writer.flush().await?;
+    Ok(())
+}
 
-            let builder = ZipEntryBuilder::new(path_as_string.into(), compression_method)
-                .unix_permissions(unix_permissions);
-            zip.write_entry_whole(builder, &buffer).await?;
+/// The earliest time zip archives can represent
+fn timestamp() -> ZipDateTime {
+    ZipDateTimeBuilder::new().year(1980).month(1).day(1).build()
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &Metadata) -> u16 {
+    use std::os::unix::fs::PermissionsExt;
+    (metadata.permissions().mode() & 0o7777) as u16
+}
+
+#[cfg(not(unix))]
+fn permissions(_metadata: &Metadata) -> u16 {
+    DEFAULT_PERMISSIONS
+}
 
-            buffer.clear();
+#[cfg(test)]
+mod tests {
+    use async_zip::tokio::read::fs::ZipFileReader;
+
+    use super::*;
+
+    async fn zip(dir: &Path, archive: &Path) -> anyhow::Result<Vec<u8>> {
+        zip_dir(dir, File::create(archive).await?).await?;
+        Ok(fs::read(archive).await?)
+    }
+
+    #[tokio::test]
+    async fn test_zip_dir() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let app = tmp.path().join("App.app");
+        let framework = app.join("Frameworks/Kit.framework");
+        std::fs::create_dir_all(framework.join("Versions/A"))?;
+        std::fs::create_dir_all(app.join("PlugIns"))?;
+        std::fs::write(app.join("Info.plist"), b"plist")?;
+        std::fs::write(app.join("App"), b"binary")?;
+        // Sorted globally it would come before `Frameworks/`, since '.' < '/'
+        std::fs::write(app.join("Frameworks.txt"), b"text")?;
+        std::fs::write(framework.join("Versions/A/Kit"), b"kit")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(app.join("App"), std::fs::Permissions::from_mode(0o755))?;
+            std::fs::set_permissions(
+                app.join("Info.plist"),
+                std::fs::Permissions::from_mode(0o644),
+            )?;
+            std::os::unix::fs::symlink("A", framework.join("Versions/Current"))?;
+        }
+
+        let first = zip(&app, &tmp.path().join("first.zip")).await?;
+        // Modification times don't change the archive
+        std::fs::write(app.join("Info.plist"), b"plist")?;
+        let second = zip(&app, &tmp.path().join("second.zip")).await?;
+        assert_eq!(first, second);
+
+        let reader = ZipFileReader::new(tmp.path().join("first.zip")).await?;
+        let entries: Vec<(String, Option<u16>)> = reader
+            .file()
+            .entries()
+            .iter()
+            .map(|entry| {
+                (
+                    entry.filename().as_str().unwrap().to_owned(),
+                    entry.unix_permissions(),
+                )
+            })
+            .collect();
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        let mut expected = vec![
+            "App.app/",
+            "App.app/App",
+            "App.app/Frameworks/",
+            "App.app/Frameworks/Kit.framework/",
+            "App.app/Frameworks/Kit.framework/Versions/",
+            "App.app/Frameworks/Kit.framework/Versions/A/",
+            "App.app/Frameworks/Kit.framework/Versions/A/Kit",
+            "App.app/Frameworks/Kit.framework/Versions/Current",
+            "App.app/Frameworks.txt",
+            "App.app/Info.plist",
+            "App.app/PlugIns/",
+        ];
+        if cfg!(not(unix)) {
+            expected.retain(|name| !name.ends_with("/Current"));
         }
+        assert_eq!(names, expected);
+
+        #[cfg(unix)]
+        {
+            let mode = |name: &str| entries.iter().find(|(n, _)| n == name).unwrap().1;
+            assert_eq!(mode("App.app/App"), Some(S_IFREG | 0o755));
+            assert_eq!(mode("App.app/Info.plist"), Some(S_IFREG | 0o644));
+            let link = "App.app/Frameworks/Kit.framework/Versions/Current";
+            assert_eq!(mode(link).map(|m| m & 0o170000), Some(S_IFLNK));
+            let index = names.iter().position(|name| *name == link).unwrap();
+            let mut target = String::new();
+            reader
+                .reader_with_entry(index)
+                .await?
+                .read_to_string_checked(&mut target)
+                .await?;
+            assert_eq!(target, "A");
+        }
+        Ok(())
     }
-    zip.close().await?;
-    Ok(())
 }
@@ -0,0 +1,424 @@
+//! Supported device configurations and inference of the dimensions a user left out.
+//!
+//! The server publishes the catalog as lists of [`Combination`]s per platform. It is cached
+//! locally for [`CACHE_TTL`] and falls back to the tables below when the API can't be reached.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{RapiClient, RapiReqwestClient},
+    errors::ConfigurationError,
+    formatter,
+};
+
+pub const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub const IOS_DIMENSIONS: [&str; 3] = ["device", "os_version", "xcode_version"];
+pub const ANDROID_DIMENSIONS: [&str; 4] = ["device", "flavor", "system_image", "os_version"];
+
+/// Supported values per dimension. A dimension that is absent allows any value
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Combination(pub BTreeMap<String, Vec<String>>);
+
+impl Combination {
+    fn new(dimensions: &[(&str, &[&str])]) -> Self {
+        Combination(
+            dimensions
+                .iter()
+                .map(|(dimension, values)| {
+                    (
+                        dimension.to_string(),
+                        values.iter().map(|v| v.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Renders the combination as command-line flags, e.g. `--device iPhone-15 --os-version 17.5`
+    fn describe(&self, dimensions: &[&str]) -> String {
+        dimensions
+            .iter()
+            .filter_map(|dimension| {
+                self.0
+                    .get(*dimension)
+                    .map(|values| format!("{} {}", flag(dimension), values.join("|")))
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceConstraints {
+    #[serde(rename = "ios")]
+    pub ios: Vec<Combination>,
+    #[serde(rename = "android")]
+    pub android: Vec<Combination>,
+}
+
+impl DeviceConstraints {
+    /// Catalog shipped with the CLI, used when neither the API nor the cache is available
+    pub fn builtin() -> Self {
+        let ios = [
+            ("iPhone-15", "17.5", "15.4"),
+            ("iPhone-15-Pro", "17.5", "15.4"),
+            ("iPhone-15-Pro-Max", "17.5", "15.4"),
+            ("iPhone-11", "17.5", "15.4"),
+            ("iPhone-16", "18.2", "16.2"),
+            ("iPhone-16-Pro", "18.2", "16.2"),
+            ("iPhone-16-Pro-Max", "18.2", "16.2"),
+            ("iPhone-11", "18.2", "16.2"),
+        ]
+        .iter()
+        .map(|(device, os_version, xcode_version)| {
+            Combination::new(&[
+                ("device", &[device]),
+                ("os_version", &[os_version]),
+                ("xcode_version", &[xcode_version]),
+            ])
+        })
+        .collect();
+
+        let images: &[&str] = &["default", "google_apis"];
+        let up_to_14: &[&str] = &["10", "11", "12", "13", "14"];
+        let android = vec![
+            Combination::new(&[
+                ("device", &["watch"]),
+                ("flavor", &["native"]),
+                ("system_image", &["google_apis"]),
+                ("os_version", &["11", "13"]),
+            ]),
+            Combination::new(&[
+                ("device", &["tv"]),
+                ("flavor", &["native"]),
+                ("system_image", &["google_apis"]),
+                ("os_version", up_to_14),
+            ]),
+            Combination::new(&[
+                ("device", &["wear"]),
+                ("system_image", images),
+                ("os_version", up_to_14),
+            ]),
+            Combination::new(&[("system_image", images), ("os_version", up_to_14)]),
+            Combination::new(&[("system_image", &["google_apis"]), ("os_version", &["15"])]),
+        ];
+
+        DeviceConstraints { ios, android }
+    }
+
+    /// Returns the cached catalog while it's fresh, otherwise fetches it from the API.
+    /// Never fails: an expired cache and then the builtin catalog are used as fallbacks
+    pub async fn load(client: &RapiReqwestClient) -> Self {
+        Self::load_with_cache(client, cache_path().as_deref()).await
+    }
+
+    pub async fn load_with_cache(client: &RapiReqwestClient, cache: Option<&Path>) -> Self {
+        let cached = cache.and_then(|path| read_cache(path, client.base_url()));
+        if let Some(cached) = &cached {
+            if cached.age() < CACHE_TTL {
+                return cached.constraints.clone();
+            }
+        }
+
+        match client.get_device_constraints().await {
+            Ok(constraints) => {
+                if let Some(path) = cache {
+                    if let Err(error) = write_cache(path, client.base_url(), &constraints) {
+                        debug!("Failed to cache device constraints: {}", error);
+                    }
+                }
+                constraints
+            }
+            Err(error) => {
+                formatter::warning(&format!(
+                    "Failed to fetch supported device configurations, using {} list: {}",
+                    if cached.is_some() {
+                        "an expired"
+                    } else {
+                        "the builtin"
+                    },
+                    error
+                ));
+                cached
+                    .map(|cached| cached.constraints)
+                    .unwrap_or_else(Self::builtin)
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedConstraints {
+    base_url: String,
+    /// Seconds since the unix epoch
+    fetched_at: u64,
+    constraints: DeviceConstraints,
+}
+
+impl CachedConstraints {
+    fn age(&self) -> Duration {
+        let fetched_at = UNIX_EPOCH + Duration::from_secs(self.fetched_at);
+        SystemTime::now()
+            .duration_since(fetched_at)
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+fn read_cache(path: &Path, base_url: &str) -> Option<CachedConstraints> {
+    let content = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str::<CachedConstraints>(&content)
+        .ok()
+        .filter(|cached| cached.base_url == base_url)
+}
+
+fn write_cache(path: &Path, base_url: &str, constraints: &DeviceConstraints) -> Result<()> {
+    let fetched_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let cached = CachedConstraints {
+        base_url: base_url.to_owned(),
+        fetched_at,
+        constraints: constraints.clone(),
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string(&cached)?)?;
+    Ok(())
+}
+
+/// Outcome of [`infer`]: the provided values plus every dimension that only one value fits
+#[derive(Debug, PartialEq, Eq)]
+pub struct Inference {
+    pub values: Vec<Option<String>>,
+    /// Combinations compatible with the provided values
+    pub candidates: Vec<Combination>,
+}
+
+impl Inference {
+    /// Fails if any dimension is still open, listing the combinations to pick from
+    pub fn complete(self, dimensions: &[&str]) -> Result<Vec<String>> {
+        let open: Vec<String> = dimensions
+            .iter()
+            .zip(&self.values)
+            .filter(|(_, value)| value.is_none())
+            .map(|(dimension, _)| flag(dimension))
+            .collect();
+        if open.is_empty() {
+            return Ok(self.values.into_iter().flatten().collect());
+        }
+        anyhow::bail!(ConfigurationError::UnsupportedRunConfiguration {
+            message: format!(
+                "{} can't be inferred, several configurations match:\n{}",
+                open.join(", "),
+                list(&self.candidates, dimensions)
+            ),
+        })
+    }
+}
+
+/// Matches `provided` values, ordered as `dimensions`, against the supported combinations
+/// and fills every unspecified dimension that has a single possible value.
+///
+/// A combination that doesn't restrict a dimension matches any value of it, unless another
+/// combination names that value explicitly: `--device tv` only matches the TV specific rules
+pub fn infer(
+    combinations: &[Combination],
+    dimensions: &[&str],
+    provided: &[Option<String>],
+) -> Result<Inference> {
+    let explicit = |dimension: &str, value: &String| {
+        combinations.iter().any(|c| {
+            c.0.get(dimension)
+                .is_some_and(|values| values.contains(value))
+        })
+    };
+    let candidates: Vec<Combination> = combinations
+        .iter()
+        .filter(|combination| {
+            dimensions.iter().zip(provided).all(|(dimension, value)| {
+                match (value, combination.0.get(*dimension)) {
+                    (None, _) => true,
+                    (Some(value), Some(values)) => values.contains(value),
+                    (Some(value), None) => !explicit(dimension, value),
+                }
+            })
+        })
+        .cloned()
+        .collect();
+
+    if candidates.is_empty() {
+        let requested = dimensions
+            .iter()
+            .zip(provided)
+            .filter_map(|(dimension, value)| {
+                value
+                    .as_ref()
+                    .map(|value| format!("{} {}", flag(dimension), value))
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        anyhow::bail!(ConfigurationError::UnsupportedRunConfiguration {
+            message: format!(
+                "{} is not supported. Supported configurations are:\n{}",
+                requested,
+                list(combinations, dimensions)
+            ),
+        });
+    }
+
+    let values = dimensions
+        .iter()
+        .zip(provided)
+        .map(|(dimension, value)| {
+            value.clone().or_else(|| {
+                let mut possible: Vec<&String> = vec![];
+                for candidate in &candidates {
+                    for value in candidate.0.get(*dimension)? {
+                        if !possible.contains(&value) {
+                            possible.push(value);
+                        }
+                    }
+                }
+                match possible.as_slice() {
+                    [value] => Some(value.to_string()),
+                    _ => None,
+                }
+            })
+        })
+        .collect();
+
+    Ok(Inference { values, candidates })
+}
+
+fn flag(dimension: &str) -> String {
+    format!("--{}", dimension.replace('_', "-"))
+}
+
+fn list(combinations: &[Combination], dimensions: &[&str]) -> String {
+    combinations
+        .iter()
+        .map(|combination| {
+            let description = combination.describe(dimensions);
+            if description.is_empty() {
+                "    any".to_owned()
+            } else {
+                format!("    {}", description)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("marathon-cloud").join("constraints.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provided(values: &[Option<&str>]) -> Vec<Option<String>> {
+        values.iter().map(|v| v.map(str::to_owned)).collect()
+    }
+
+    fn infer_ios(values: &[Option<&str>]) -> Result<Inference> {
+        infer(
+            &DeviceConstraints::builtin().ios,
+            &IOS_DIMENSIONS,
+            &provided(values),
+        )
+    }
+
+    fn infer_android(values: &[Option<&str>]) -> Result<Inference> {
+        infer(
+            &DeviceConstraints::builtin().android,
+            &ANDROID_DIMENSIONS,
+            &provided(values),
+        )
+    }
+
+    #[test]
+    fn test_ios_device_infers_runtime_and_xcode() -> Result<()> {
+        let values = infer_ios(&[Some("iPhone-16"), None, None])?.complete(&IOS_DIMENSIONS)?;
+        assert_eq!(values, vec!["iPhone-16", "18.2", "16.2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ios_ambiguity_lists_candidates() {
+        let error = infer_ios(&[Some("iPhone-11"), None, None])
+            .and_then(|inference| inference.complete(&IOS_DIMENSIONS))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("--os-version, --xcode-version"), "{}", error);
+        assert!(
+            error.contains("--device iPhone-11 --os-version 17.5 --xcode-version 15.4"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("--device iPhone-11 --os-version 18.2 --xcode-version 16.2"),
+            "{}",
+            error
+        );
+        assert!(!error.contains("iPhone-15"), "{}", error);
+    }
+
+    #[test]
+    fn test_ios_unsupported_combination() {
+        let error = infer_ios(&[Some("iPhone-16"), Some("17.5"), None])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("--device iPhone-16 --os-version 17.5 is not supported"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_android_explicit_device_shadows_generic_rules() -> Result<()> {
+        assert!(infer_android(&[Some("tv"), None, None, Some("15")]).is_err());
+        assert!(infer_android(&[Some("watch"), None, None, Some("12")]).is_err());
+        assert!(infer_android(&[Some("tv"), Some("js-jest-appium"), None, None]).is_err());
+
+        let inference = infer_android(&[Some("watch"), None, None, None])?;
+        assert_eq!(
+            inference.values,
+            provided(&[Some("watch"), Some("native"), Some("google_apis"), None])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_android_unknown_device_uses_generic_rules() -> Result<()> {
+        let inference = infer_android(&[Some("pixel_6"), None, None, Some("15")])?;
+        assert_eq!(
+            inference.values,
+            provided(&[Some("pixel_6"), None, Some("google_apis"), Some("15")])
+        );
+        assert!(infer_android(&[Some("pixel_6"), None, Some("default"), Some("15")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_is_scoped_to_base_url() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("nested/constraints.json");
+        write_cache(&path, "https://a", &DeviceConstraints::builtin())?;
+
+        let cached = read_cache(&path, "https://a").unwrap();
+        assert_eq!(cached.constraints, DeviceConstraints::builtin());
+        assert!(cached.age() < CACHE_TTL);
+        assert!(read_cache(&path, "https://b").is_none());
+        Ok(())
+    }
+}
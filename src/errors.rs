@@ -7,6 +7,7 @@ use tokio::{io, task::JoinError};
 use url::ParseError;
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum ApiError {
     #[error("Invalid parameters for url")]
     InvalidParameters { error: ParseError },
@@ -25,6 +26,7 @@ pub enum ApiError {
 }
 
 #[derive(Error, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum EnvArgError {
     #[error("Invalid environment or testing environment variable. Double check you've supplied correct value\nvalue = {env_arg}")]
     InvalidKeyValue { env_arg: String },
@@ -34,6 +36,7 @@ pub enum EnvArgError {
 }
 
 #[derive(Error, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum PullArgError {
     #[error(
         "Invalid format for --pull-files argument. Expected format: ROOT:PATH. Your format: {arg}"
@@ -45,15 +48,27 @@ pub enum PullArgError {
 }
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum ArtifactError {
     #[error("Failed to retrieve artifact list.\nerror = {error}")]
     ListFailed { error: JoinError },
 
     #[error("Failed to download artifacts.\nerror = {error}")]
     DownloadFailed { error: JoinError },
+
+    #[error("Failed to download {id}. All {retries} retries failed.\nerror = {message}")]
+    RetriesExhausted {
+        id: String,
+        retries: u32,
+        message: String,
+    },
+
+    #[error("Failed to patch allure results\npath = {path:?}, error = {error}")]
+    PatchFailed { path: PathBuf, error: io::Error },
 }
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum InputError {
     #[error("Invalid input file. Double check you've supplied correct path\npath = {path}")]
     InvalidFileName { path: PathBuf },
@@ -67,6 +82,27 @@ pub enum InputError {
     #[error("Invalid xctestplan file: no test targets specified. Double check you've supplied correct path")]
     XctestplanMissingTargets,
 
+    #[error("Can't convert test filters from {from} to {to}. Double check you've supplied different --from and --to formats")]
+    InvalidConversion { from: String, to: String },
+
+    #[error("Invalid xctestplan target. Double check you've supplied correct target name\ntarget = {name}, available = {available}")]
+    XctestplanMissingTarget { name: String, available: String },
+
+    #[error("Invalid xctestplan configuration. Double check you've supplied correct configuration name\nconfiguration = {name}, available = {available}")]
+    XctestplanMissingConfiguration { name: String, available: String },
+
+    #[error("Unsupported xctestplan commandLineArgumentEntries, runs can't pass launch arguments to the tests. Double check you've disabled them in the test plan\npath = {path}, arguments = {arguments}")]
+    UnsupportedXctestplanArguments { path: PathBuf, arguments: String },
+
+    #[error("Invalid xctestrun file: {message}. Double check you've supplied the .xctestrun of build-for-testing\npath = {path}")]
+    InvalidXctestrun { path: PathBuf, message: String },
+
+    #[error("Invalid xctestrun target. Double check you've supplied correct UI test target name\ntarget = {name}, available = {available}")]
+    InvalidXctestrunTarget { name: String, available: String },
+
+    #[error("Unsupported xctestrun CommandLineArguments, runs can't pass launch arguments to the tests. Double check you've removed them from the scheme or test plan\npath = {path}, arguments = {arguments}")]
+    UnsupportedXctestrunArguments { path: PathBuf, arguments: String },
+
     #[error("Invalid input file. All file paths should be valid UTF8\npath = {path}")]
     NonUTF8Path { path: PathBuf },
 
@@ -85,6 +121,20 @@ pub enum InputError {
         supported: String,
     },
 
+    #[error(
+        "Invalid test identifier. Tests should be specified as package.ClassName#method\nid = {id}"
+    )]
+    InvalidTestIdentifier { id: String },
+
+    #[error("Unsupported APK set: {message}\npath = {path}")]
+    InvalidApkSet { path: PathBuf, message: String },
+
+    #[error("Unknown device id. Use `marathon-cloud devices android` to get a list of supported devices\nid = {id}")]
+    UnknownDevice { id: String },
+
+    #[error("Invalid --matrix argument. Expected format: DIMENSION=VALUE,VALUE, e.g. os-version=11,13: {message}\nmatrix = {axis}")]
+    InvalidMatrix { axis: String, message: String },
+
     #[error("{arg} arg should be a positive number")]
     NonPositiveValue { arg: String },
 
@@ -94,21 +144,142 @@ Available permissions: calendar, contacts-limited, contacts, location, location-
 }
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum ConfigurationError {
     #[error("Unsupported run configuration: {message}")]
     UnsupportedRunConfiguration { message: String },
+
+    #[error("Test application is built for a different application. Double check you've supplied correct APKs\ntest application = {test_application}, target package = {target_package}\napplication = {application}, package = {package}")]
+    TargetPackageMismatch {
+        test_application: PathBuf,
+        target_package: String,
+        application: PathBuf,
+        package: String,
+    },
+
+    #[error("Test application doesn't declare an instrumentation runner. Double check you've supplied correct test APK\npath = {path}")]
+    MissingInstrumentation { path: PathBuf },
+
+    #[error("APK requires a newer Android version. Double check you've supplied correct --os-version\npath = {path}, minSdkVersion = {min_sdk_version}, os_version = {os_version} (API level {api_level})")]
+    MinSdkVersionNotSupported {
+        path: PathBuf,
+        min_sdk_version: u32,
+        os_version: String,
+        api_level: u32,
+    },
+
+    #[error("iOS bundle isn't built for the simulator. Double check you've built it with `-sdk iphonesimulator`\npath = {path}, platforms = {platforms}")]
+    NotBuiltForSimulator { path: PathBuf, platforms: String },
+
+    #[error("Test application doesn't contain any xctest bundles in PlugIns. Double check you've supplied the *-Runner.app of your UI tests\npath = {path}")]
+    MissingXctestBundle { path: PathBuf },
+
+    #[error("iOS bundle requires a newer iOS version. Double check you've supplied correct --os-version\npath = {path}, MinimumOSVersion = {minimum_os_version}, os_version = {os_version}")]
+    MinimumOsVersionNotSupported {
+        path: PathBuf,
+        minimum_os_version: String,
+        os_version: String,
+    },
+
+    #[error("Android App Bundles are converted into APKs with bundletool, which wasn't found. Double check bundletool is on PATH or BUNDLETOOL points to bundletool or its jar\npath = {path}")]
+    BundletoolNotFound { path: PathBuf },
+
+    #[error("bundletool can't convert the Android App Bundle into an APK. Double check you've supplied correct bundle\npath = {path}, error = {message}")]
+    BundletoolFailed { path: PathBuf, message: String },
+
+    #[error("Invalid proxy url. Double check you've supplied correct value\nproxy = {proxy}, error = {error}")]
+    InvalidProxy { proxy: String, error: ReqwestError },
+
+    #[error("Invalid PEM certificate. Double check you've supplied correct path\npath = {path}, error = {error}")]
+    InvalidCertificate { path: PathBuf, error: ReqwestError },
+}
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum InventoryError {
+    #[error("Can't read archive. Double check you've supplied correct path\npath = {path}, error = {error}")]
+    InvalidArchive {
+        path: PathBuf,
+        error: async_zip::error::ZipError,
+    },
+
+    #[error("Test application doesn't contain any dex files. Double check you've supplied correct test application\npath = {path}")]
+    MissingDex { path: PathBuf },
+
+    #[error("Test application doesn't contain any xctest bundles. Double check you've supplied correct test application\npath = {path}")]
+    MissingXctest { path: PathBuf },
+
+    #[error("Invalid xctest binary in test application\npath = {path}, entry = {entry}, error = {message}")]
+    InvalidMachO {
+        path: PathBuf,
+        entry: String,
+        message: String,
+    },
+
+    #[error("iOS bundle doesn't contain an Info.plist. Double check you've supplied correct bundle\npath = {path}")]
+    MissingInfoPlist { path: PathBuf },
+
+    #[error("Invalid Info.plist in iOS bundle\npath = {path}, entry = {entry}, error = {message}")]
+    InvalidInfoPlist {
+        path: PathBuf,
+        entry: String,
+        message: String,
+    },
+
+    #[error("APK doesn't contain an AndroidManifest.xml. Double check you've supplied correct APK\npath = {path}")]
+    MissingManifest { path: PathBuf },
+
+    #[error("Invalid AndroidManifest.xml in APK\npath = {path}, error = {message}")]
+    InvalidManifest { path: PathBuf, message: String },
+
+    #[error(
+        "Invalid dex file in test application\npath = {path}, entry = {entry}, error = {message}"
+    )]
+    InvalidDex {
+        path: PathBuf,
+        entry: String,
+        message: String,
+    },
 }
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum FilteringConfigurationError {
-    #[error("Filter type {mtype} is not supported by Marathon Cloud")]
-    UnsupportedFilterType { mtype: String },
     #[error("Filter type {mtype} is invalid")]
     InvalidFilterType { mtype: String },
     #[error("Invalid configuration for filter {mtype}: {message}")]
     InvalidFilterConfiguration { mtype: String, message: String },
     #[error("The following mandatory fields in --filter-file were missed: {fields}")]
     MissedMandatoryFields { fields: String },
+    #[error("Invalid filter file. Double check you've supplied correct filters\npath = {path:?}, error = {error}")]
+    InvalidFilterFile {
+        path: PathBuf,
+        error: serde_yaml::Error,
+    },
+    #[error("Invalid include in filter file {file:?}: {message}\ninclude = {include:?}")]
+    InvalidInclude {
+        file: PathBuf,
+        include: PathBuf,
+        message: String,
+    },
+    #[error("Filter files include each other: {chain}")]
+    IncludeCycle { chain: String },
+    #[error("Filter at {path} can't be converted: {message}")]
+    NotConvertible { path: String, message: String },
+    #[error("Invalid regex for filter {mtype} at {path}: {message}\nregex = {regex}")]
+    InvalidRegex {
+        path: String,
+        mtype: String,
+        regex: String,
+        message: String,
+    },
+    #[error("Invalid values file for filter {mtype} at {path}: {message}\nfile = {file:?}")]
+    InvalidValuesFile {
+        path: String,
+        mtype: String,
+        file: PathBuf,
+        message: String,
+    },
 }
 
 //Dumps the error to output recursively by looking at the source()
@@ -1,20 +1,264 @@
 use anyhow::Result;
 use regex::Regex;
 use shellexpand;
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 use tokio::{
     fs::{self, File},
     io::AsyncReadExt,
 };
 
-use crate::errors::{FilteringConfigurationError, InputError};
+use crate::{
+    errors::{FilteringConfigurationError, InputError},
+    formatter,
+};
 
 use super::{
-    model::{Filter, FilteringConfiguration, SparseMarathonfile},
+    java_regex::{self, DialectIssue},
+    model::{
+        Filter, FilterFile, FilteringConfiguration, Matcher, Shard, SparseMarathonfile, UnionOp,
+    },
     xctestplan,
 };
 
+/// Tests selected or excluded by command-line flags such as `--include-class`
+#[derive(Debug, Default, Clone)]
+pub struct TestSelection {
+    /// Simple or fully-qualified class names
+    pub classes: Vec<String>,
+    /// `ClassName#method`, optionally with a package
+    pub tests: Vec<String>,
+    pub packages: Vec<String>,
+    pub annotations: Vec<String>,
+    /// Regexes matched against fully-qualified test names
+    pub regexes: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct InlineFilters {
+    pub include: TestSelection,
+    pub exclude: TestSelection,
+}
+
+impl TestSelection {
+    async fn into_filters(self, prefix: &str, workdir: &Path) -> Result<Vec<Filter>> {
+        let (qualified_classes, simple_classes): (Vec<_>, Vec<_>) = self
+            .classes
+            .into_iter()
+            .partition(|class| class.contains('.'));
+        let (qualified_tests, simple_tests): (Vec<_>, Vec<_>) =
+            self.tests.into_iter().partition(|test| {
+                test.split_once('#')
+                    .is_some_and(|(class, _)| class.contains('.'))
+            });
+
+        let mut filters = vec![];
+        for (flag, filter, values) in [
+            (
+                "class",
+                Filter::FullyQualifiedClassName as fn(Matcher) -> Filter,
+                qualified_classes,
+            ),
+            ("class", Filter::SimpleClassName, simple_classes),
+            ("test", Filter::FullyQualifiedTestName, qualified_tests),
+            ("test", Filter::SimpleTestName, simple_tests),
+            ("package", Filter::Package, self.packages),
+            ("annotation", Filter::Annotation, self.annotations),
+        ] {
+            if !values.is_empty() {
+                filters.push((flag, filter(Matcher::Values(values))));
+            }
+        }
+        for regex in self.regexes {
+            filters.push((
+                "regex",
+                Filter::FullyQualifiedTestName(Matcher::Regex(regex)),
+            ));
+        }
+
+        let mut validated = Vec::with_capacity(filters.len());
+        for (flag, mut filter) in filters {
+            validate_filter(&mut filter, &format!("{}-{}", prefix, flag), workdir).await?;
+            validated.push(filter);
+        }
+        Ok(validated)
+    }
+}
+
+/// Merges filters given as flags into the filtering configuration:
+/// a test runs if it's selected by the configuration or by any `--include-*` flag,
+/// and it's skipped if it matches the blocklist or any `--exclude-*` flag
+pub async fn with_inline_filters(
+    cnf: Option<SparseMarathonfile>,
+    inline: InlineFilters,
+) -> Result<Option<SparseMarathonfile>> {
+    let workdir = std::env::current_dir()?;
+    let mut includes = inline.include.into_filters("--include", &workdir).await?;
+    let excludes = inline.exclude.into_filters("--exclude", &workdir).await?;
+    if includes.is_empty() && excludes.is_empty() {
+        return Ok(cnf);
+    }
+
+    let mut cnf = cnf.unwrap_or_else(|| SparseMarathonfile {
+        filtering_configuration: FilteringConfiguration::default(),
+    });
+    let filtering_configuration = &mut cnf.filtering_configuration;
+    if !includes.is_empty() {
+        // Marathon intersects allowlist filters, so the union has to be spelled out as a composition
+        match filtering_configuration.allowlist.take() {
+            Some(mut allowlist) if allowlist.len() == 1 => includes.push(allowlist.remove(0)),
+            Some(allowlist) if !allowlist.is_empty() => includes.push(Filter::Composition {
+                filters: allowlist,
+                op: UnionOp::Intersection,
+            }),
+            _ => {}
+        }
+        let allowed = if includes.len() == 1 {
+            includes.remove(0)
+        } else {
+            Filter::Composition {
+                filters: includes,
+                op: UnionOp::Union,
+            }
+        };
+        filtering_configuration.allowlist = Some(vec![allowed]);
+    }
+    if !excludes.is_empty() {
+        filtering_configuration
+            .blocklist
+            .get_or_insert_with(Vec::new)
+            .extend(excludes);
+    }
+    Ok(Some(cnf))
+}
+
+/// Narrows the filtering configuration down to a single shard of the suite
+pub fn with_shard(
+    cnf: Option<SparseMarathonfile>,
+    shard: Option<Shard>,
+) -> Option<SparseMarathonfile> {
+    let Some(shard) = shard else {
+        return cnf;
+    };
+    let mut cnf = cnf.unwrap_or_else(|| SparseMarathonfile {
+        filtering_configuration: FilteringConfiguration::default(),
+    });
+    cnf.filtering_configuration
+        .allowlist
+        .get_or_insert_with(Vec::new)
+        .push(shard.into());
+    Some(cnf)
+}
+
 pub async fn convert(cnf: PathBuf) -> Result<SparseMarathonfile> {
+    convert_all(vec![cnf]).await
+}
+
+/// Reads filter files together with the files they include and merges them
+/// as if all filters were written in a single file: a test has to match every allowlist filter
+/// of every file and is excluded by any blocklist filter of any file.
+/// A file included several times is applied once.
+pub async fn convert_all(files: Vec<PathBuf>) -> Result<SparseMarathonfile> {
+    let mut merged = FilteringConfiguration::default();
+    let mut loaded = HashSet::new();
+    for cnf in files {
+        let path = cnf.to_str().ok_or(InputError::NonUTF8Path {
+            path: cnf.to_owned(),
+        })?;
+        let expanded_path = PathBuf::from(shellexpand::tilde(&path).into_owned());
+        load(expanded_path, &mut vec![], &mut loaded, &mut merged).await?;
+    }
+    Ok(SparseMarathonfile {
+        filtering_configuration: merged,
+    })
+}
+
+async fn load(
+    path: PathBuf,
+    chain: &mut Vec<PathBuf>,
+    loaded: &mut HashSet<PathBuf>,
+    merged: &mut FilteringConfiguration,
+) -> Result<()> {
+    let content = fs::read_to_string(&path)
+        .await
+        .map_err(|error| InputError::OpenFileFailure {
+            path: path.clone(),
+            error,
+        })?;
+    let absolute_path = fs::canonicalize(&path).await?;
+    if let Some(start) = chain.iter().position(|p| *p == absolute_path) {
+        let chain = chain[start..]
+            .iter()
+            .chain([&absolute_path])
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        anyhow::bail!(FilteringConfigurationError::IncludeCycle { chain });
+    }
+    if !loaded.insert(absolute_path.clone()) {
+        return Ok(());
+    }
+
+    let filter_file: FilterFile = serde_yaml::from_str(&content).map_err(|error| {
+        FilteringConfigurationError::InvalidFilterFile {
+            path: path.clone(),
+            error,
+        }
+    })?;
+    let workdir = absolute_path.parent().unwrap_or(Path::new(""));
+    match filter_file.filtering_configuration {
+        Some(mut cnf) => {
+            validate(&mut cnf, workdir).await?;
+            if let Some(allowlist) = cnf.allowlist {
+                merged
+                    .allowlist
+                    .get_or_insert_with(Vec::new)
+                    .extend(allowlist);
+            }
+            if let Some(blocklist) = cnf.blocklist {
+                merged
+                    .blocklist
+                    .get_or_insert_with(Vec::new)
+                    .extend(blocklist);
+            }
+        }
+        None if filter_file.include.is_empty() => {
+            anyhow::bail!(FilteringConfigurationError::MissedMandatoryFields {
+                fields: "filteringConfiguration".to_string()
+            });
+        }
+        None => {}
+    }
+
+    chain.push(absolute_path.clone());
+    for include in filter_file.include {
+        let invalid = |message: &str| FilteringConfigurationError::InvalidInclude {
+            file: path.clone(),
+            include: include.clone(),
+            message: message.to_owned(),
+        };
+        if !include.is_relative() {
+            anyhow::bail!(invalid(
+                "File should be specified relative to the including filter file"
+            ));
+        } else if !workdir.join(&include).is_file() {
+            anyhow::bail!(invalid("File does not exist or is not a regular file"));
+        }
+        Box::pin(load(workdir.join(&include), chain, loaded, merged)).await?;
+    }
+    chain.pop();
+    Ok(())
+}
+
+/// Converts test selections of xctestplan targets into filters.
+/// Without target names all enabled targets are used.
+/// Selections of several targets are combined as a union, each scoped to the tests of its own bundle.
+pub async fn convert_xctestplan(
+    cnf: PathBuf,
+    target_names: Vec<String>,
+) -> Result<SparseMarathonfile> {
     let path = cnf.to_str().ok_or(InputError::NonUTF8Path {
         path: cnf.to_owned(),
     })?;
@@ -27,24 +271,85 @@ pub async fn convert(cnf: PathBuf) -> Result<SparseMarathonfile> {
                 error,
             })?;
 
-    let mut filtering_configuration: SparseMarathonfile = serde_yaml::from_str(&content)?;
+    let xctestplan: xctestplan::SparseTestPlan = serde_json::from_str(&content)?;
+    let targets = xctestplan.test_targets;
+    let targets: Vec<_> = if target_names.is_empty() {
+        targets
+            .iter()
+            .filter(|x| x.enabled != Some(false))
+            .collect()
+    } else {
+        let mut selected = vec![];
+        for target_name in target_names {
+            let target = targets
+                .iter()
+                .find(|x| x.target.name == target_name)
+                .ok_or_else(|| InputError::XctestplanMissingTarget {
+                    name: target_name.clone(),
+                    available: targets
+                        .iter()
+                        .map(|x| x.target.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                })?;
+            selected.push(target);
+        }
+        selected
+    };
+
+    let filtering_configuration = match targets.as_slice() {
+        [] => anyhow::bail!(InputError::XctestplanMissingTargets),
+        [target] => FilteringConfiguration {
+            allowlist: target
+                .selected_tests
+                .as_ref()
+                .map(|x| vec![xctestplan_ids_to_filter(x)]),
+            blocklist: target
+                .skipped_tests
+                .as_ref()
+                .map(|x| vec![xctestplan_ids_to_filter(x)]),
+        },
+        targets => FilteringConfiguration {
+            allowlist: Some(vec![Filter::Composition {
+                filters: targets
+                    .iter()
+                    .map(|x| xctestplan_target_filter(x))
+                    .collect(),
+                op: UnionOp::Union,
+            }]),
+            blocklist: None,
+        },
+    };
+    let marathonfile = SparseMarathonfile {
+        filtering_configuration,
+    };
 
-    let absolute_path = fs::canonicalize(&expanded_path).await?;
-    let workdir = absolute_path.parent().unwrap_or(Path::new(""));
-    validate(
-        &content,
-        &mut filtering_configuration.filtering_configuration,
-        workdir,
-    )
-    .await?;
+    Ok(marathonfile)
+}
 
-    Ok(filtering_configuration)
+/// Run settings taken from the options of an xctestplan configuration
+#[derive(Debug, Default, PartialEq)]
+pub struct XctestplanRunOptions {
+    pub env: HashMap<String, String>,
+    pub language: Option<String>,
+    pub region: Option<String>,
+    pub test_timeout_default: Option<u32>,
+    pub test_timeout_max: Option<u32>,
+    pub code_coverage: Option<bool>,
+    pub retry_quota_test_reactive: Option<u32>,
 }
 
-pub async fn convert_xctestplan(
+// Xcode's defaults when the test plan enables the option without a value
+const XCTEST_DEFAULT_EXECUTION_TIME_ALLOWANCE: u32 = 600;
+const XCTEST_DEFAULT_MAXIMUM_REPETITIONS: u32 = 3;
+
+/// Reads the options of the configuration, falling back to the default options of the test plan.
+/// Without a configuration name the only configuration of the plan is used.
+/// Options Marathon Cloud can't apply are reported as warnings.
+pub async fn xctestplan_run_options(
     cnf: PathBuf,
-    target_name: Option<String>,
-) -> Result<SparseMarathonfile> {
+    configuration: Option<String>,
+) -> Result<XctestplanRunOptions> {
     let path = cnf.to_str().ok_or(InputError::NonUTF8Path {
         path: cnf.to_owned(),
     })?;
@@ -56,37 +361,199 @@ pub async fn convert_xctestplan(
                 path: PathBuf::from(&expanded_path),
                 error,
             })?;
-
     let xctestplan: xctestplan::SparseTestPlan = serde_json::from_str(&content)?;
-    let targets = xctestplan.test_targets;
-    let target = match target_name {
-        Some(target_name) => targets
+
+    let available = || {
+        xctestplan
+            .configurations
             .iter()
-            .find(|x| x.target.name == target_name)
-            .ok_or(InputError::XctestplanMissingTargets)?,
-        None => targets
-            .first()
-            .ok_or(InputError::XctestplanMissingTargets)?,
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
     };
-
-    let allowlist = target
-        .selected_tests
-        .as_ref()
-        .map(|x| xctestplan_ids_to_filter(x));
-    let blocklist = target
-        .skipped_tests
-        .as_ref()
-        .map(|x| xctestplan_ids_to_filter(x));
-
-    let filtering_configuration = FilteringConfiguration {
-        allowlist: allowlist.map(|x| vec![x]),
-        blocklist: blocklist.map(|x| vec![x]),
+    let options = match configuration {
+        Some(name) => {
+            let configuration = xctestplan
+                .configurations
+                .iter()
+                .find(|c| c.name == name)
+                .ok_or_else(|| InputError::XctestplanMissingConfiguration {
+                    name: name.clone(),
+                    available: available(),
+                })?;
+            configuration
+                .options
+                .clone()
+                .or(xctestplan.default_options.clone())
+        }
+        None => match xctestplan.configurations.as_slice() {
+            [configuration] => configuration
+                .options
+                .clone()
+                .or(xctestplan.default_options.clone()),
+            [] => xctestplan.default_options.clone(),
+            _ => {
+                formatter::warning(&format!(
+                    "xctestplan has several configurations ({}), using its default options. Pick one with --xctestplan-configuration",
+                    available()
+                ));
+                xctestplan.default_options.clone()
+            }
+        },
     };
-    let marathonfile = SparseMarathonfile {
-        filtering_configuration,
+
+    run_options(Path::new(&expanded_path), options)
+}
+
+fn run_options(path: &Path, options: xctestplan::Options) -> Result<XctestplanRunOptions> {
+    use xctestplan::TestRepetitionMode;
+
+    let mut run_options = XctestplanRunOptions {
+        env: options
+            .environmnent_variables
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|entry| entry.enabled != Some(false))
+            .map(|entry| (entry.key, entry.value))
+            .collect(),
+        language: options.language,
+        region: options.region,
+        code_coverage: options.code_coverage,
+        ..Default::default()
     };
+    if run_options.env.values().any(|value| value.contains("$(")) {
+        formatter::warning("xctestplan environment variables are passed as is, build setting references like $(VAR) are not expanded");
+    }
+    if options.test_timeouts_enabled == Some(true) {
+        run_options.test_timeout_default = Some(
+            options
+                .default_test_execution_time_allowance
+                .unwrap_or(XCTEST_DEFAULT_EXECUTION_TIME_ALLOWANCE),
+        );
+        run_options.test_timeout_max = options.maximum_test_execution_time_allowance;
+    }
 
-    Ok(marathonfile)
+    let arguments: Vec<String> = options
+        .command_line_arguments
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|entry| entry.enabled != Some(false))
+        .map(|entry| entry.argument)
+        .collect();
+    if !arguments.is_empty() {
+        anyhow::bail!(InputError::UnsupportedXctestplanArguments {
+            path: path.to_path_buf(),
+            arguments: arguments.join(" "),
+        });
+    }
+
+    let mut unsupported = vec![];
+    match options.test_repetition_mode {
+        Some(TestRepetitionMode::RetryOnFailure) => {
+            let repetitions = options
+                .maximum_test_repetitions
+                .unwrap_or(XCTEST_DEFAULT_MAXIMUM_REPETITIONS);
+            run_options.retry_quota_test_reactive = Some(repetitions.saturating_sub(1));
+        }
+        Some(TestRepetitionMode::UntilFailure)
+        | Some(TestRepetitionMode::UpUntilMaximumRepetitions) => {
+            unsupported.push("testRepetitionMode")
+        }
+        Some(TestRepetitionMode::None) | None => {}
+    }
+    if options
+        .address_sanitizer
+        .is_some_and(|sanitizer| sanitizer.enabled == Some(true))
+    {
+        unsupported.push("addressSanitizer");
+    }
+    for (name, enabled) in [
+        ("threadSanitizerEnabled", options.thread_sanitizer_enabled),
+        (
+            "undefinedBehaviorSanitizerEnabled",
+            options.undefined_behavior_sanitizer_enabled,
+        ),
+        ("guardMallocEnabled", options.guard_malloc_enabled),
+        (
+            "mallocGuardEdgesEnabled",
+            options.malloc_guard_edges_enabled,
+        ),
+        ("mallocScribbleEnabled", options.malloc_scribble_enabled),
+        ("nsZombieEnabled", options.nszombie_enabled),
+    ] {
+        if enabled == Some(true) {
+            unsupported.push(name);
+        }
+    }
+    if options
+        .malloc_stack_logging
+        .is_some_and(|logging| logging.logging_type.is_some())
+    {
+        unsupported.push("mallocStackLoggingOptions");
+    }
+    if options.location_scenario.is_some() {
+        unsupported.push("locationScenario");
+    }
+    if matches!(
+        options.test_execution_ordering,
+        Some(xctestplan::TestExecutionOrdering::Random)
+    ) {
+        unsupported.push("testExecutionOrdering");
+    }
+    if !unsupported.is_empty() {
+        formatter::warning(&format!(
+            "xctestplan options not supported by Marathon Cloud are ignored: {}",
+            unsupported.join(", ")
+        ));
+    }
+    Ok(run_options)
+}
+
+/// Writes the allowlist and blocklist of filter files into `selectedTests` and `skippedTests`
+/// of the test plan targets, leaving the rest of the plan as is
+pub async fn update_xctestplan(
+    filter_files: Vec<PathBuf>,
+    xctestplan: PathBuf,
+    target_names: Vec<String>,
+) -> Result<()> {
+    let marathonfile = convert_all(filter_files).await?;
+    let path = xctestplan.to_str().ok_or(InputError::NonUTF8Path {
+        path: xctestplan.to_owned(),
+    })?;
+    let expanded_path = shellexpand::tilde(&path).into_owned();
+    let content =
+        fs::read_to_string(&expanded_path)
+            .await
+            .map_err(|error| InputError::OpenFileFailure {
+                path: PathBuf::from(&expanded_path),
+                error,
+            })?;
+    let updated = xctestplan::update::update(
+        &content,
+        &marathonfile.filtering_configuration,
+        &target_names,
+    )?;
+    fs::write(&expanded_path, updated).await?;
+    Ok(())
+}
+
+// Marathon uses the test bundle name as the package of iOS tests
+fn xctestplan_target_filter(target: &xctestplan::TestTarget) -> Filter {
+    let bundle = Filter::Package(Matcher::Values(vec![target.target.name.clone()]));
+    let selected = match &target.selected_tests {
+        Some(ids) => Filter::Composition {
+            filters: vec![bundle, xctestplan_ids_to_filter(ids)],
+            op: UnionOp::Intersection,
+        },
+        None => bundle,
+    };
+    match &target.skipped_tests {
+        Some(ids) => Filter::Composition {
+            filters: vec![selected, xctestplan_ids_to_filter(ids)],
+            op: UnionOp::Subtract,
+        },
+        None => selected,
+    }
 }
 
 //Identifiers contain a mix of class names and class name with method signature
@@ -107,213 +574,381 @@ fn xctestplan_ids_to_filter(ids: &[String]) -> Filter {
 
     if !class_names.is_empty() && !simple_test_names.is_empty() {
         //Need to use composition since filtering is done via class names and also using methods
-        let class_name_filter = Filter {
-            mtype: "simple-class-name".into(),
-            values: Some(class_names),
-            op: None,
-            file: None,
-            regex: None,
-            filters: None,
-        };
-
-        let simple_qualified_test_name_filter = Filter {
-            mtype: "simple-test-name".into(),
-            values: Some(simple_test_names),
-            op: None,
-            file: None,
-            regex: None,
-            filters: None,
-        };
-        Filter {
-            mtype: "composition".into(),
-            values: None,
-            op: Some("UNION".into()),
-            filters: Some(vec![class_name_filter, simple_qualified_test_name_filter]),
-            regex: None,
-            file: None,
+        Filter::Composition {
+            filters: vec![
+                Filter::SimpleClassName(Matcher::Values(class_names)),
+                Filter::SimpleTestName(Matcher::Values(simple_test_names)),
+            ],
+            op: UnionOp::Union,
         }
     } else if !class_names.is_empty() {
-        Filter {
-            mtype: "simple-class-name".into(),
-            values: Some(class_names),
-            op: None,
-            file: None,
-            regex: None,
-            filters: None,
-        }
+        Filter::SimpleClassName(Matcher::Values(class_names))
     } else {
-        Filter {
-            mtype: "simple-test-name".into(),
-            values: Some(simple_test_names),
-            op: None,
-            file: None,
-            regex: None,
-            filters: None,
-        }
+        Filter::SimpleTestName(Matcher::Values(simple_test_names))
     }
 }
 
-pub async fn validate(
-    original_content: &String,
-    cnf: &mut FilteringConfiguration,
-    workdir: &Path,
-) -> Result<()> {
-    if !original_content.contains("filteringConfiguration") {
-        anyhow::bail!(FilteringConfigurationError::MissedMandatoryFields {
-            fields: "filteringConfiguration".to_string()
-        });
-    }
-
-    if !original_content.contains("allowlist") && !original_content.contains("blocklist") {
+/// Checks what the schema can't express and replaces `file` matchers with the values they point to
+pub async fn validate(cnf: &mut FilteringConfiguration, workdir: &Path) -> Result<()> {
+    if cnf.allowlist.is_none() && cnf.blocklist.is_none() {
         anyhow::bail!(FilteringConfigurationError::MissedMandatoryFields {
             fields: "At least one of 'allowlist' and 'blocklist' should be presented".to_string()
         });
     }
 
-    let supported_types = vec![
-        "fully-qualified-class-name",
-        "fully-qualified-test-name",
-        "simple-class-name",
-        "simple-test-name",
-        "package",
-        "method",
-        "annotation",
-    ];
-    let unsupported_types = vec!["allure", "fragmentation", "annotationData"];
-
-    for list in [&mut cnf.allowlist, &mut cnf.blocklist] {
-        match list {
-            Some(filters) => {
-                validate_filters(filters, &supported_types, &unsupported_types, workdir).await?
-            }
-            None => continue,
+    for (name, list) in [
+        ("allowlist", &mut cnf.allowlist),
+        ("blocklist", &mut cnf.blocklist),
+    ] {
+        if let Some(filters) = list {
+            let path = format!("filteringConfiguration.{}", name);
+            validate_filters(filters, &path, workdir).await?;
         }
     }
 
     Ok(())
 }
 
-async fn validate_filters(
-    filters: &mut [Filter],
-    supported_types: &[&str],
-    unsupported_types: &[&str],
-    workdir: &Path,
-) -> Result<()> {
-    for filter in filters.iter_mut() {
-        if filter.mtype == "composition" {
-            if filter.op.is_none() {
-                anyhow::bail!(FilteringConfigurationError::InvalidFilterConfiguration {
-                    mtype: filter.mtype.clone(),
-                    message: "missing 'op' field".to_owned()
-                });
-            } else if filter.op.as_ref().is_some_and(|op| op.is_empty()) {
-                anyhow::bail!(FilteringConfigurationError::InvalidFilterConfiguration {
-                    mtype: filter.mtype.clone(),
-                    message: "empty 'op' field".to_owned()
-                });
-            } else {
-                match filter.filters.as_mut() {
-                    Some(filters) => {
-                        for filter in filters.iter_mut() {
-                            validate_filter(filter, supported_types, unsupported_types, workdir)
-                                .await?;
-                        }
-                    }
-                    None => {
-                        anyhow::bail!(FilteringConfigurationError::InvalidFilterConfiguration {
-                            mtype: filter.mtype.clone(),
-                            message: "missing composition filters".to_owned()
-                        });
-                    }
+async fn validate_filters(filters: &mut [Filter], path: &str, workdir: &Path) -> Result<()> {
+    for (index, filter) in filters.iter_mut().enumerate() {
+        let path = format!("{}[{}]", path, index);
+        if let Filter::Composition { filters, .. } = filter {
+            Box::pin(validate_filters(
+                filters,
+                &format!("{}.filters", path),
+                workdir,
+            ))
+            .await?;
+        } else {
+            validate_filter(filter, &path, workdir).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn validate_filter(filter: &mut Filter, path: &str, workdir: &Path) -> Result<()> {
+    resolve_values_file(filter, path, workdir).await?;
+    let mtype = filter.mtype();
+
+    if let Filter::AnnotationData {
+        name_regex,
+        value_regex,
+    } = filter
+    {
+        validate_regex(mtype, name_regex, path)?;
+        validate_regex(mtype, value_regex, path)?;
+    }
+    match filter.matcher() {
+        Some(Matcher::Regex(regex)) => validate_regex(mtype, regex, path)?,
+        Some(Matcher::Values(values)) => {
+            if values.is_empty() {
+                formatter::warning(&format!(
+                    "{}: filter {} has no values and can never match",
+                    path, mtype
+                ));
+            }
+            for value in values {
+                if let Some(reason) = unmatchable_value(filter, value) {
+                    formatter::warning(&format!(
+                        "{}: value {:?} of filter {} can never match, {}",
+                        path, value, mtype, reason
+                    ));
+                }
+                if matches!(filter, Filter::Annotation(_)) && !value.contains('.') {
+                    formatter::warning(&format!(
+                        "{}: annotation {:?} is not a fully-qualified name, Marathon compares annotations by their fully-qualified names, e.g. com.example.Smoke",
+                        path, value
+                    ));
                 }
             }
-        } else {
-            validate_filter(filter, supported_types, unsupported_types, workdir).await?;
         }
+        _ => {}
     }
     Ok(())
 }
 
-async fn validate_filter(
-    filter: &mut Filter,
-    supported_types: &[&str],
-    unsupported_types: &[&str],
-    workdir: &Path,
-) -> Result<()> {
-    if unsupported_types.iter().any(|&t| t == filter.mtype) {
-        anyhow::bail!(FilteringConfigurationError::UnsupportedFilterType {
-            mtype: filter.mtype.clone(),
-        });
-    } else if !supported_types.iter().any(|&t| t == filter.mtype) {
-        anyhow::bail!(FilteringConfigurationError::InvalidFilterType {
-            mtype: filter.mtype.clone(),
-        });
+fn validate_regex(mtype: &str, regex: &str, path: &str) -> Result<()> {
+    let invalid = |message: String| FilteringConfigurationError::InvalidRegex {
+        path: path.to_owned(),
+        mtype: mtype.to_owned(),
+        regex: regex.to_owned(),
+        message,
+    };
+
+    let scan = java_regex::scan(regex);
+    for issue in scan.issues {
+        match issue {
+            DialectIssue::Incompatible(message) => anyhow::bail!(invalid(message)),
+            DialectIssue::JavaOnly(message) => formatter::warning(&format!(
+                "{}: regex {:?} of filter {}: {}",
+                path, regex, mtype, message
+            )),
+        }
     }
+    Regex::new(&scan.checkable).map_err(|error| invalid(error.to_string()))?;
+    Ok(())
+}
 
-    match (&filter.regex, &filter.values, &filter.file) {
-        (None, None, None) => {
-            anyhow::bail!(FilteringConfigurationError::InvalidFilterConfiguration {
-                mtype: filter.mtype.clone(),
-                message: "At least one of regex, values or file should be specified".into()
-            })
+/// Explains why a value can't match any test identifier of the filter's kind
+fn unmatchable_value(filter: &Filter, value: &str) -> Option<&'static str> {
+    match filter {
+        Filter::Package(_) if value.contains('#') => {
+            Some("package names can't contain '#', use a test name filter instead")
+        }
+        Filter::FullyQualifiedClassName(_) | Filter::SimpleClassName(_) if value.contains('#') => {
+            Some("class names can't contain '#', use a test name filter instead")
+        }
+        Filter::SimpleClassName(_) if value.contains('.') => {
+            Some("simple class names can't contain '.', use fully-qualified-class-name instead")
+        }
+        Filter::FullyQualifiedTestName(_) | Filter::SimpleTestName(_) if !value.contains('#') => {
+            Some("test names have the form ClassName#method")
+        }
+        Filter::SimpleTestName(_)
+            if value
+                .split_once('#')
+                .is_some_and(|(class, _)| class.contains('.')) =>
+        {
+            Some("simple test names can't contain a package, use fully-qualified-test-name instead")
+        }
+        Filter::Method(_) if value.contains('#') || value.contains('.') => {
+            Some("method names can't contain '#' or '.', use a test name filter instead")
         }
+        Filter::Annotation(_) if value.contains('#') => Some("annotation names can't contain '#'"),
+        _ => None,
+    }
+}
 
-        (None, None, Some(path)) => {
-            if !path.is_relative() {
-                anyhow::bail!(FilteringConfigurationError::InvalidFilterConfiguration {
-                    mtype: filter.mtype.clone(),
-                    message: "File should be specified relative to the filter file".into()
-                })
-            } else if !workdir.join(path).is_file() {
-                anyhow::bail!(FilteringConfigurationError::InvalidFilterConfiguration {
-                    mtype: filter.mtype.clone(),
-                    message: "File does not exist or is not a regular file".into()
-                })
-            } else {
-                let mut values_file = File::open(workdir.join(path)).await?;
-                let size = values_file.metadata().await?.len();
-                if size == 0 {
-                    anyhow::bail!(FilteringConfigurationError::InvalidFilterConfiguration {
-                        mtype: filter.mtype.clone(),
-                        message: "File does not exist or is not a regular file".into()
-                    })
-                }
+async fn resolve_values_file(filter: &mut Filter, path: &str, workdir: &Path) -> Result<()> {
+    let mtype = filter.mtype();
+    let Some(matcher) = filter.matcher_mut() else {
+        return Ok(());
+    };
+    let Matcher::File(file) = matcher else {
+        return Ok(());
+    };
+    let invalid = |message: &str| FilteringConfigurationError::InvalidValuesFile {
+        path: path.to_owned(),
+        mtype: mtype.to_owned(),
+        file: file.clone(),
+        message: message.to_owned(),
+    };
 
-                let mut buffer = String::new();
-                values_file.read_to_string(&mut buffer).await?;
+    if !file.is_relative() {
+        anyhow::bail!(invalid(
+            "File should be specified relative to the filter file"
+        ));
+    } else if !workdir.join(&file).is_file() {
+        anyhow::bail!(invalid("File does not exist or is not a regular file"));
+    }
 
-                let mut values = Vec::new();
+    let mut values_file = File::open(workdir.join(&file)).await?;
+    let size = values_file.metadata().await?.len();
+    if size == 0 {
+        anyhow::bail!(invalid("File does not exist or is not a regular file"));
+    }
 
-                let comment_regex = Regex::new(r"\s+#.*$")?;
-                for value in buffer.lines() {
-                    let value = value.trim();
-                    if value.is_empty() || value.starts_with('#') {
-                        continue;
-                    }
-                    let value = comment_regex.replace_all(value, "");
-                    values.push(value.as_ref().to_owned());
-                }
-                filter.values = Some(values);
-                filter.file = None;
-            }
-            Ok(())
-        }
-        (None, Some(_), None) => Ok(()),
-        (Some(_), None, None) => Ok(()),
+    let mut buffer = String::new();
+    values_file.read_to_string(&mut buffer).await?;
+
+    let mut values = Vec::new();
 
-        _ => anyhow::bail!(FilteringConfigurationError::InvalidFilterConfiguration {
-            mtype: filter.mtype.clone(),
-            message: "only one of [regex, values, file] can be specified".into()
-        }),
+    let comment_regex = Regex::new(r"\s+#.*$")?;
+    for value in buffer.lines() {
+        let value = value.trim();
+        if value.is_empty() || value.starts_with('#') {
+            continue;
+        }
+        let value = comment_regex.replace_all(value, "");
+        values.push(value.as_ref().to_owned());
     }
+    *matcher = Matcher::Values(values);
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
-    use std::path::{self, Path};
+    use std::path::{self, Path, PathBuf};
+
+    use crate::errors::InputError;
+    use crate::filtering::{
+        convert::{
+            convert, convert_all, convert_xctestplan, unmatchable_value, validate_regex,
+            with_inline_filters, xctestplan_run_options, InlineFilters, TestSelection,
+        },
+        model::{Filter, Matcher, UnionOp},
+    };
+
+    fn include_fixture(name: &str) -> PathBuf {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        Path::new(&manifest_dir)
+            .join("fixture")
+            .join("filtering")
+            .join("include")
+            .join(name)
+    }
+
+    #[tokio::test]
+    async fn test_include() -> Result<()> {
+        let result = convert(include_fixture("main.yaml")).await?;
+        let result = serde_json::to_string(&result)?;
+
+        // quarantine.yaml is included twice but applied once
+        assert_eq!(
+            result,
+            r#"{"filteringConfiguration":{"allowlist":[{"type":"package","values":["com.example"]}],"blocklist":[{"type":"fully-qualified-test-name","values":["com.example.LoginTest#testLogout"]},{"type":"simple-class-name","values":["UploadTest"]}]}}"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_include_cycle() {
+        let error = convert(include_fixture("cycle.yaml")).await.unwrap_err();
+        let message = error.to_string();
+
+        assert!(
+            message.starts_with("Filter files include each other:"),
+            "{}",
+            message
+        );
+        assert_eq!(message.matches("cycle.yaml").count(), 3, "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_misspelled_key_is_rejected() {
+        let error = convert(include_fixture("typo.yaml")).await.unwrap_err();
+        let message = error.to_string();
+
+        assert!(message.starts_with("Invalid filter file."), "{}", message);
+        assert!(message.contains("unknown field `includes`"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_multiple_filter_files() -> Result<()> {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let valid = Path::new(&manifest_dir)
+            .join("fixture")
+            .join("filtering")
+            .join("valid.yaml");
+        let result = convert_all(vec![valid, include_fixture("quarantine.yaml")]).await?;
+        let result = serde_json::to_string(&result)?;
+
+        assert_eq!(
+            result,
+            r#"{"filteringConfiguration":{"allowlist":[{"type":"fully-qualified-test-name","regex":".*Test"}],"blocklist":[{"type":"fully-qualified-test-name","values":["com.example.LoginTest#testLogout"]}]}}"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_inline_filters_without_file() -> Result<()> {
+        let inline = InlineFilters {
+            include: TestSelection {
+                classes: vec!["LoginTest".into(), "com.example.UploadTest".into()],
+                ..Default::default()
+            },
+            exclude: TestSelection {
+                annotations: vec!["com.example.Flaky".into()],
+                ..Default::default()
+            },
+        };
+        let result = with_inline_filters(None, inline).await?;
+
+        assert_eq!(
+            serde_json::to_string(&result)?,
+            r#"{"filteringConfiguration":{"allowlist":[{"type":"composition","filters":[{"type":"fully-qualified-class-name","values":["com.example.UploadTest"]},{"type":"simple-class-name","values":["LoginTest"]}],"op":"UNION"}],"blocklist":[{"type":"annotation","values":["com.example.Flaky"]}]}}"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_inline_filters_merged_with_file() -> Result<()> {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let fixture = Path::new(&manifest_dir)
+            .join("fixture")
+            .join("filtering")
+            .join("valid.yaml");
+        let cnf = convert(fixture).await?;
+        let file_allowlist = cnf.filtering_configuration.allowlist.clone().unwrap();
+
+        let inline = InlineFilters {
+            include: TestSelection {
+                tests: vec!["com.example.LoginTest#testLogin".into()],
+                ..Default::default()
+            },
+            exclude: TestSelection {
+                regexes: vec![".*Flaky.*".into()],
+                ..Default::default()
+            },
+        };
+        let result = with_inline_filters(Some(cnf), inline).await?.unwrap();
+
+        let allowlist = result.filtering_configuration.allowlist.unwrap();
+        let Filter::Composition { filters, .. } = &allowlist[0] else {
+            panic!("expected a union, got {:?}", allowlist);
+        };
+        assert_eq!(
+            filters[0],
+            Filter::FullyQualifiedTestName(Matcher::Values(vec![
+                "com.example.LoginTest#testLogin".into()
+            ]))
+        );
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[1], file_allowlist[0]);
+        assert_eq!(
+            result.filtering_configuration.blocklist,
+            Some(vec![Filter::FullyQualifiedTestName(Matcher::Regex(
+                ".*Flaky.*".into()
+            ))])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_inline_regex_is_validated() {
+        let inline = InlineFilters {
+            include: TestSelection {
+                regexes: vec![".*Test(".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let error = with_inline_filters(None, inline).await.unwrap_err();
+        assert!(error.to_string().contains("--include-regex"), "{}", error);
+    }
+
+    #[test]
+    fn test_validate_regex() {
+        assert!(validate_regex("package", "com\\.example\\..*", "allowlist[0]").is_ok());
+        assert!(validate_regex("package", "(?<!internal)\\.tests", "allowlist[0]").is_ok());
+
+        let error = validate_regex("simple-class-name", ".*Test(", "allowlist[0]").unwrap_err();
+        assert!(error.to_string().contains("unclosed group"), "{}", error);
+        // Java only constructs don't hide syntax errors in the rest of the regex
+        let error = validate_regex("simple-class-name", "(?<x>a\\1", "allowlist[0]").unwrap_err();
+        assert!(error.to_string().contains("unclosed group"), "{}", error);
+        let error =
+            validate_regex("simple-class-name", "[[:upper:]]+", "allowlist[0]").unwrap_err();
+        assert!(error.to_string().contains("POSIX"), "{}", error);
+    }
 
-    use crate::filtering::convert::{convert, convert_xctestplan};
+    #[test]
+    fn test_unmatchable_value() {
+        let values = Matcher::Values(vec![]);
+        assert!(unmatchable_value(&Filter::Package(values.clone()), "com.example#test").is_some());
+        assert!(unmatchable_value(&Filter::Package(values.clone()), "com.example").is_none());
+        assert!(unmatchable_value(&Filter::SimpleTestName(values.clone()), "LoginTest").is_some());
+        assert!(
+            unmatchable_value(&Filter::SimpleTestName(values.clone()), "a.LoginTest#test")
+                .is_some()
+        );
+        assert!(
+            unmatchable_value(&Filter::SimpleTestName(values.clone()), "LoginTest#test").is_none()
+        );
+        assert!(unmatchable_value(&Filter::Method(values.clone()), "LoginTest#test").is_some());
+        assert!(unmatchable_value(&Filter::SimpleClassName(values), "a.LoginTest").is_some());
+    }
 
     #[tokio::test]
     async fn test_valid() -> Result<()> {
@@ -426,9 +1061,13 @@ mod tests {
             .join("fixture")
             .join("filtering")
             .join("fragmentation.yaml");
-        let result = convert(fixture).await;
+        let result = convert(fixture).await?;
+        let result = serde_json::to_string(&result)?;
 
-        assert!(result.is_err());
+        assert_eq!(
+            result,
+            r#"{"filteringConfiguration":{"allowlist":[{"type":"fragmentation","index":1,"count":5}]}}"#
+        );
         Ok(())
     }
 
@@ -514,6 +1153,19 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_lists_only_in_comments_error() -> Result<()> {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let fixture = Path::new(&manifest_dir)
+            .join("fixture")
+            .join("filtering")
+            .join("listsOnlyInComments.yaml");
+        let result = convert(fixture).await;
+
+        assert!(result.is_err());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_wrong_allow_list_error() -> Result<()> {
         let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
@@ -548,7 +1200,7 @@ mod tests {
             .join("filtering")
             .join("xctestplan")
             .join("1.json");
-        let result = convert_xctestplan(fixture, None).await?;
+        let result = convert_xctestplan(fixture, vec![]).await?;
         let result = serde_json::to_string(&result)?;
         assert_eq!(
             result,
@@ -568,7 +1220,7 @@ mod tests {
             .join("filtering")
             .join("xctestplan")
             .join("test plan with spaces.xctestplan");
-        let result = convert_xctestplan(fixture, None).await?;
+        let result = convert_xctestplan(fixture, vec![]).await?;
         let result = serde_json::to_string(&result)?;
         assert_eq!(
             result,
@@ -576,4 +1228,110 @@ mod tests {
         );
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_xctestplan_run_options() -> Result<()> {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let fixture = Path::new(&manifest_dir)
+            .join("fixture")
+            .join("filtering")
+            .join("xctestplan")
+            .join("1.json");
+        let result = xctestplan_run_options(fixture.clone(), None).await?;
+
+        assert_eq!(result.env.get("A").map(String::as_str), Some("B"));
+        assert_eq!(result.language.as_deref(), Some("en-AU"));
+        assert_eq!(result.region.as_deref(), Some("AU"));
+        assert_eq!(result.code_coverage, Some(false));
+        assert_eq!(result.test_timeout_default, Some(600));
+        assert_eq!(result.test_timeout_max, None);
+        assert_eq!(result.retry_quota_test_reactive, Some(2));
+
+        let result =
+            xctestplan_run_options(fixture.clone(), Some("Configuration 1".into())).await?;
+        assert_eq!(result.language.as_deref(), Some("en-AU"));
+
+        let error = xctestplan_run_options(fixture, Some("Release".into()))
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("available = Configuration 1"),
+            "{}",
+            error
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_xctestplan_run_options_with_arguments() {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let fixture = Path::new(&manifest_dir)
+            .join("fixture")
+            .join("filtering")
+            .join("xctestplan")
+            .join("test plan with spaces.xctestplan");
+        let error = xctestplan_run_options(fixture, None).await.unwrap_err();
+
+        assert!(
+            matches!(
+                error.downcast_ref(),
+                Some(InputError::UnsupportedXctestplanArguments { arguments, .. }) if arguments == "-test"
+            ),
+            "{}",
+            error
+        );
+    }
+
+    fn multiple_targets_fixture() -> PathBuf {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        Path::new(&manifest_dir)
+            .join("fixture")
+            .join("filtering")
+            .join("xctestplan")
+            .join("multiple-targets.xctestplan")
+    }
+
+    #[tokio::test]
+    async fn test_xctestplan_all_enabled_targets() -> Result<()> {
+        let result = convert_xctestplan(multiple_targets_fixture(), vec![]).await?;
+        let result = serde_json::to_string(&result)?;
+        assert_eq!(
+            result,
+            r#"{"filteringConfiguration":{"allowlist":[{"type":"composition","filters":[{"type":"composition","filters":[{"type":"package","values":["AppTests"]},{"type":"composition","filters":[{"type":"simple-class-name","values":["LoginTests"]},{"type":"simple-test-name","values":["UploadTest#testUpload"]}],"op":"UNION"}],"op":"INTERSECTION"},{"type":"composition","filters":[{"type":"package","values":["AppUITests"]},{"type":"simple-test-name","values":["OnboardingTests#testSkip"]}],"op":"SUBTRACT"}],"op":"UNION"}]}}"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_xctestplan_target_names() -> Result<()> {
+        let result =
+            convert_xctestplan(multiple_targets_fixture(), vec!["AppUITests".into()]).await?;
+        let result = serde_json::to_string(&result)?;
+        assert_eq!(
+            result,
+            r#"{"filteringConfiguration":{"blocklist":[{"type":"simple-test-name","values":["OnboardingTests#testSkip"]}]}}"#
+        );
+
+        let result = convert_xctestplan(
+            multiple_targets_fixture(),
+            vec!["AppUITests".into(), "LegacyTests".into()],
+        )
+        .await?;
+        let allowlist = result.filtering_configuration.allowlist.unwrap();
+        assert!(
+            matches!(&allowlist[..], [Filter::Composition { filters, op: UnionOp::Union }] if filters.len() == 2)
+        );
+
+        let error = convert_xctestplan(multiple_targets_fixture(), vec!["Missing".into()])
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("available = AppTests, AppUITests, LegacyTests"),
+            "{}",
+            error
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,430 @@
+use std::{fmt::Display, path::PathBuf};
+
+use anyhow::Result;
+use regex::Regex;
+use tokio::fs;
+
+use crate::errors::{FilteringConfigurationError, InputError};
+
+use super::model::{Filter, FilteringConfiguration, Matcher, UnionOp};
+
+/// Test identity as seen by Marathon's filters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    pub package: String,
+    pub class: String,
+    pub method: String,
+    pub annotations: Vec<String>,
+}
+
+impl TestCase {
+    /// Parses `com.example.ClassName#method`, the package may be empty
+    pub fn parse(id: &str) -> Option<TestCase> {
+        let (class_name, method) = id.split_once('#')?;
+        let (package, class) = class_name.rsplit_once('.').unwrap_or(("", class_name));
+        if class.is_empty() || method.is_empty() {
+            return None;
+        }
+        Some(TestCase {
+            package: package.to_owned(),
+            class: class.to_owned(),
+            method: method.to_owned(),
+            annotations: vec![],
+        })
+    }
+
+    pub fn fully_qualified_class_name(&self) -> String {
+        if self.package.is_empty() {
+            self.class.clone()
+        } else {
+            format!("{}.{}", self.package, self.class)
+        }
+    }
+
+    pub fn fully_qualified_test_name(&self) -> String {
+        format!("{}#{}", self.fully_qualified_class_name(), self.method)
+    }
+
+    pub fn simple_test_name(&self) -> String {
+        format!("{}#{}", self.class, self.method)
+    }
+
+    /// Identifier used by xctestplan selections, e.g. `LoginTests/testLogin`
+    pub fn xctest_identifier(&self) -> String {
+        format!("{}/{}", self.class, self.method)
+    }
+}
+
+impl Display for TestCase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.fully_qualified_test_name())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Selected,
+    Excluded { reason: String },
+}
+
+#[derive(Debug)]
+pub struct Evaluation {
+    pub test: TestCase,
+    pub verdict: Verdict,
+}
+
+/// Reads test identifiers, one per line. Comments follow the same rules as filter value files
+pub async fn read_tests(path: PathBuf) -> Result<Vec<TestCase>> {
+    let content = fs::read_to_string(&path)
+        .await
+        .map_err(|error| InputError::OpenFileFailure {
+            path: path.clone(),
+            error,
+        })?;
+
+    let comment_regex = Regex::new(r"\s+#.*$")?;
+    let mut tests = vec![];
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = comment_regex.replace_all(line, "");
+        let test = TestCase::parse(&line).ok_or(InputError::InvalidTestIdentifier {
+            id: line.clone().into_owned(),
+        })?;
+        tests.push(test);
+    }
+    Ok(tests)
+}
+
+/// Applies the filtering configuration the same way Marathon does:
+/// a test has to match every allowlist filter and none of the blocklist filters
+pub fn evaluate(cnf: &FilteringConfiguration, tests: Vec<TestCase>) -> Result<Vec<Evaluation>> {
+    let allowlist = cnf.allowlist.as_deref().unwrap_or_default();
+    let blocklist = cnf.blocklist.as_deref().unwrap_or_default();
+
+    let mut evaluations = Vec::with_capacity(tests.len());
+    for test in tests {
+        let mut verdict = Verdict::Selected;
+        for (index, filter) in allowlist.iter().enumerate() {
+            if !matches(filter, &test)? {
+                verdict = Verdict::Excluded {
+                    reason: format!(
+                        "not matched by allowlist filter #{} ({})",
+                        index + 1,
+                        describe(filter)
+                    ),
+                };
+                break;
+            }
+        }
+        if verdict == Verdict::Selected {
+            for (index, filter) in blocklist.iter().enumerate() {
+                if matches(filter, &test)? {
+                    verdict = Verdict::Excluded {
+                        reason: format!(
+                            "matched by blocklist filter #{} ({})",
+                            index + 1,
+                            describe(filter)
+                        ),
+                    };
+                    break;
+                }
+            }
+        }
+        evaluations.push(Evaluation { test, verdict });
+    }
+    Ok(evaluations)
+}
+
+fn matches(filter: &Filter, test: &TestCase) -> Result<bool> {
+    let matcher = match filter {
+        Filter::Composition { filters, op } => {
+            return match op {
+                UnionOp::Union => {
+                    for filter in filters {
+                        if matches(filter, test)? {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                }
+                UnionOp::Intersection => {
+                    for filter in filters {
+                        if !matches(filter, test)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                }
+                UnionOp::Subtract => match filters.split_first() {
+                    Some((first, rest)) => {
+                        if !matches(first, test)? {
+                            return Ok(false);
+                        }
+                        for filter in rest {
+                            if matches(filter, test)? {
+                                return Ok(false);
+                            }
+                        }
+                        Ok(true)
+                    }
+                    None => Ok(false),
+                },
+            };
+        }
+        Filter::FullyQualifiedClassName(matcher)
+        | Filter::FullyQualifiedTestName(matcher)
+        | Filter::SimpleClassName(matcher)
+        | Filter::SimpleTestName(matcher)
+        | Filter::Package(matcher)
+        | Filter::Method(matcher)
+        | Filter::Annotation(matcher) => matcher,
+        // Marathon decides these using test metadata collected on the device
+        Filter::Fragmentation { .. } | Filter::Allure | Filter::AnnotationData { .. } => {
+            anyhow::bail!(FilteringConfigurationError::InvalidFilterConfiguration {
+                mtype: filter.mtype().to_owned(),
+                message: "can't be evaluated locally, it depends on test metadata available only during the run".to_owned(),
+            })
+        }
+    };
+
+    let candidates = match filter {
+        Filter::FullyQualifiedClassName(_) => vec![test.fully_qualified_class_name()],
+        Filter::FullyQualifiedTestName(_) => vec![test.fully_qualified_test_name()],
+        Filter::SimpleClassName(_) => vec![test.class.clone()],
+        Filter::SimpleTestName(_) => vec![test.simple_test_name()],
+        Filter::Package(_) => vec![test.package.clone()],
+        Filter::Method(_) => vec![test.method.clone()],
+        Filter::Annotation(_) => test.annotations.clone(),
+        _ => unreachable!(),
+    };
+
+    match matcher {
+        Matcher::Regex(regex) => {
+            // Marathon matches the whole value, not a substring
+            let regex = Regex::new(&format!("^(?:{})$", regex)).map_err(|error| {
+                FilteringConfigurationError::InvalidFilterConfiguration {
+                    mtype: filter.mtype().to_owned(),
+                    message: format!("invalid regex: {}", error),
+                }
+            })?;
+            Ok(candidates.iter().any(|value| regex.is_match(value)))
+        }
+        Matcher::Values(values) => Ok(candidates.iter().any(|value| values.contains(value))),
+        Matcher::File(_) => {
+            anyhow::bail!(FilteringConfigurationError::InvalidFilterConfiguration {
+                mtype: filter.mtype().to_owned(),
+                message: "values file has to be resolved with filtering::convert first".to_owned(),
+            })
+        }
+    }
+}
+
+fn describe(filter: &Filter) -> String {
+    match filter {
+        Filter::Composition { filters, op } => {
+            format!("composition {} of {} filters", op, filters.len())
+        }
+        Filter::Fragmentation { index, count } => format!("fragmentation {}/{}", index, count),
+        Filter::AnnotationData {
+            name_regex,
+            value_regex,
+        } => format!(
+            "annotationData name regex {}, value regex {}",
+            name_regex, value_regex
+        ),
+        _ => match filter.matcher() {
+            Some(Matcher::Regex(regex)) => format!("{} regex {}", filter.mtype(), regex),
+            Some(Matcher::Values(values)) => {
+                format!("{} values [{}]", filter.mtype(), values.join(", "))
+            }
+            Some(Matcher::File(file)) => format!("{} file {}", filter.mtype(), file.display()),
+            None => filter.mtype().to_owned(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tests() -> Vec<TestCase> {
+        [
+            "com.example.LoginTest#testLogin",
+            "com.example.LoginTest#testLogout",
+            "com.example.slow.UploadTest#testUpload",
+            "SimpleTest#testSimple",
+        ]
+        .iter()
+        .map(|id| TestCase::parse(id).unwrap())
+        .collect()
+    }
+
+    fn evaluate_yaml(yaml: &str) -> Result<Vec<(String, Verdict)>> {
+        let cnf: FilteringConfiguration = serde_yaml::from_str(yaml)?;
+        Ok(evaluate(&cnf, tests())?
+            .into_iter()
+            .map(|e| (e.test.to_string(), e.verdict))
+            .collect())
+    }
+
+    fn selected(evaluations: &[(String, Verdict)]) -> Vec<&str> {
+        evaluations
+            .iter()
+            .filter(|(_, v)| *v == Verdict::Selected)
+            .map(|(id, _)| id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let test = TestCase::parse("com.example.LoginTest#testLogin").unwrap();
+        assert_eq!(test.package, "com.example");
+        assert_eq!(test.class, "LoginTest");
+        assert_eq!(test.method, "testLogin");
+        assert!(TestCase::parse("com.example.LoginTest").is_none());
+    }
+
+    #[test]
+    fn test_no_filters_selects_everything() -> Result<()> {
+        let result = evaluate_yaml("{}")?;
+        assert_eq!(selected(&result).len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_allowlist_filters_are_intersected() -> Result<()> {
+        let result = evaluate_yaml(
+            r#"
+allowlist:
+  - type: package
+    regex: "com\\.example.*"
+  - type: method
+    values: ["testLogin", "testUpload"]
+"#,
+        )?;
+        assert_eq!(
+            selected(&result),
+            vec![
+                "com.example.LoginTest#testLogin",
+                "com.example.slow.UploadTest#testUpload"
+            ]
+        );
+        assert_eq!(
+            result[1].1,
+            Verdict::Excluded {
+                reason:
+                    "not matched by allowlist filter #2 (method values [testLogin, testUpload])"
+                        .into()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_regex_matches_whole_value() -> Result<()> {
+        let result = evaluate_yaml(
+            r#"
+allowlist:
+  - type: simple-class-name
+    regex: "Login"
+"#,
+        )?;
+        assert!(selected(&result).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocklist_reason() -> Result<()> {
+        let result = evaluate_yaml(
+            r#"
+blocklist:
+  - type: package
+    values: ["com.example.slow"]
+"#,
+        )?;
+        assert_eq!(
+            result[2].1,
+            Verdict::Excluded {
+                reason: "matched by blocklist filter #1 (package values [com.example.slow])".into()
+            }
+        );
+        assert_eq!(selected(&result).len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_composition_union() -> Result<()> {
+        let result = evaluate_yaml(
+            r#"
+allowlist:
+  - type: composition
+    op: UNION
+    filters:
+      - type: simple-class-name
+        values: ["SimpleTest"]
+      - type: simple-test-name
+        values: ["LoginTest#testLogout"]
+"#,
+        )?;
+        assert_eq!(
+            selected(&result),
+            vec!["com.example.LoginTest#testLogout", "SimpleTest#testSimple"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_composition_intersection() -> Result<()> {
+        let result = evaluate_yaml(
+            r#"
+allowlist:
+  - type: composition
+    op: INTERSECTION
+    filters:
+      - type: simple-class-name
+        values: ["LoginTest"]
+      - type: method
+        regex: ".*Logout"
+"#,
+        )?;
+        assert_eq!(selected(&result), vec!["com.example.LoginTest#testLogout"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_composition_subtract() -> Result<()> {
+        let result = evaluate_yaml(
+            r#"
+allowlist:
+  - type: composition
+    op: SUBTRACT
+    filters:
+      - type: package
+        regex: "com\\.example.*"
+      - type: simple-class-name
+        values: ["LoginTest"]
+"#,
+        )?;
+        assert_eq!(
+            selected(&result),
+            vec!["com.example.slow.UploadTest#testUpload"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_annotation_without_metadata_matches_nothing() -> Result<()> {
+        let result = evaluate_yaml(
+            r#"
+allowlist:
+  - type: annotation
+    values: ["com.example.Smoke"]
+"#,
+        )?;
+        assert!(selected(&result).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+//! Marathon applies filter regexes with java.util.regex, while local tooling uses the regex crate.
+//! This scanner flags the constructs where the two dialects disagree.
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DialectIssue {
+    /// Valid only in Rust or means something else in Java, Marathon would reject or misapply it
+    Incompatible(String),
+    /// Valid in Java but not supported by the regex crate, so it can't be checked or evaluated locally
+    JavaOnly(String),
+}
+
+/// Outcome of scanning a filter regex
+pub struct Scan {
+    pub issues: Vec<DialectIssue>,
+    /// The regex with Java only constructs replaced by Rust ones of the same shape,
+    /// e.g. lookarounds by non-capturing groups, so its syntax can still be checked locally
+    pub checkable: String,
+}
+
+pub fn scan(regex: &str) -> Scan {
+    let chars: Vec<char> = regex.chars().collect();
+    let mut issues = vec![];
+    let mut checkable = String::with_capacity(regex.len());
+    let mut class_depth = 0;
+    let mut i = 0;
+    // Whether the previous token can be followed by a possessive `+`
+    let mut after_quantifier = false;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let quantifier = after_quantifier;
+        after_quantifier = false;
+
+        match c {
+            '\\' => {
+                match next {
+                    Some('Q') => {
+                        issues.push(java_only("\\Q...\\E quoting"));
+                        // Everything up to \E is literal
+                        let rest = &chars[i + 2..];
+                        let end = find(rest, &['\\', 'E']);
+                        let literal: String = rest[..end.unwrap_or(rest.len())].iter().collect();
+                        checkable.push_str(&regex::escape(&literal));
+                        i += 2 + end.map(|e| e + 2).unwrap_or(rest.len());
+                        continue;
+                    }
+                    Some('1'..='9') if class_depth == 0 => {
+                        issues.push(java_only("backreferences"));
+                        checkable.push_str("(?:)");
+                        i += 2;
+                        continue;
+                    }
+                    Some('k') if chars.get(i + 2) == Some(&'<') => {
+                        issues.push(java_only("named backreferences"));
+                        checkable.push_str("(?:)");
+                        i += find(&chars[i..], &['>']).map(|e| e + 1).unwrap_or(2);
+                        continue;
+                    }
+                    Some('<') | Some('>') => issues.push(incompatible(
+                        "\\< and \\> are word boundaries in Rust but literal characters in Java",
+                    )),
+                    Some('b') if chars.get(i + 2) == Some(&'{') => issues.push(incompatible(
+                        "\\b{...} word boundaries are not supported by Java",
+                    )),
+                    _ => {}
+                }
+                checkable.extend(&chars[i..(i + 2).min(chars.len())]);
+                i += 2;
+                continue;
+            }
+            '[' => {
+                if class_depth > 0 && next == Some(':') {
+                    if let Some(end) = find(&chars[i..], &[':', ']']) {
+                        let class: String = chars[i..i + end + 2].iter().collect();
+                        issues.push(incompatible(&format!(
+                            "POSIX class {} is not supported by Java, use \\p{{...}} instead",
+                            class
+                        )));
+                        checkable.push_str(&class);
+                        i += end + 2;
+                        continue;
+                    }
+                }
+                class_depth += 1;
+                checkable.push(c);
+                // A leading `]` or `^]` is a literal inside the class
+                if next == Some('^') {
+                    checkable.push('^');
+                    i += 1;
+                }
+                if chars.get(i + 1) == Some(&']') {
+                    checkable.push(']');
+                    i += 1;
+                }
+                i += 1;
+                continue;
+            }
+            ']' if class_depth > 0 => {
+                class_depth -= 1;
+                after_quantifier = false;
+            }
+            '-' | '~' if class_depth > 0 && next == Some(c) => {
+                issues.push(incompatible(&format!(
+                    "{}{} is a set operation in Rust but literal characters in Java",
+                    c, c
+                )));
+                checkable.extend([c, c]);
+                i += 2;
+                continue;
+            }
+            '(' if class_depth == 0 && next == Some('?') => {
+                let rest = &chars[i + 2..];
+                let starts_with = |prefix: &[char]| rest.starts_with(prefix);
+                let lookaround = if starts_with(&['P', '<'])
+                    || starts_with(&['P', '='])
+                    || starts_with(&['P', '>'])
+                {
+                    issues.push(incompatible(
+                        "(?P<name>...) groups are not supported by Java, use (?<name>...)",
+                    ));
+                    None
+                } else if starts_with(&['<', '=']) || starts_with(&['<', '!']) {
+                    issues.push(java_only("lookbehind"));
+                    Some(2)
+                } else if starts_with(&['=']) || starts_with(&['!']) {
+                    issues.push(java_only("lookahead"));
+                    Some(1)
+                } else if starts_with(&['>']) {
+                    issues.push(java_only("atomic groups"));
+                    Some(1)
+                } else if !starts_with(&['<']) && !starts_with(&[':']) {
+                    let flags: Vec<char> = rest
+                        .iter()
+                        .copied()
+                        .take_while(|c| *c != ')' && *c != ':')
+                        .collect();
+                    let mut supported = String::new();
+                    for flag in flags.iter().copied() {
+                        match flag {
+                            'U' => issues.push(incompatible("flag U swaps greediness in Rust but enables Unicode classes in Java")),
+                            'R' => issues.push(incompatible("flag R is not supported by Java")),
+                            'd' | 'c' => issues.push(java_only(&format!("flag {}", flag))),
+                            _ => supported.push(flag),
+                        }
+                    }
+                    if supported.len() < flags.len() {
+                        let supported = supported.trim_end_matches('-');
+                        i += 2 + flags.len();
+                        if supported.is_empty() {
+                            checkable.push_str("(?:");
+                            // `(?d:...)` keeps its group, `(?d)` becomes an empty one
+                            if chars.get(i) == Some(&':') {
+                                i += 1;
+                            }
+                        } else {
+                            checkable.push_str("(?");
+                            checkable.push_str(supported);
+                        }
+                        continue;
+                    }
+                    None
+                } else {
+                    None
+                };
+                if let Some(length) = lookaround {
+                    checkable.push_str("(?:");
+                    i += 2 + length;
+                    continue;
+                }
+            }
+            '*' | '+' | '?' | '}' if class_depth == 0 => {
+                if quantifier && c == '+' {
+                    issues.push(java_only("possessive quantifiers"));
+                    i += 1;
+                    continue;
+                } else {
+                    after_quantifier = !(quantifier && c == '?');
+                }
+            }
+            _ => {}
+        }
+        checkable.push(c);
+        i += 1;
+    }
+    Scan { issues, checkable }
+}
+
+/// Char index of the first occurrence of `pattern` in `chars`
+fn find(chars: &[char], pattern: &[char]) -> Option<usize> {
+    chars
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+}
+
+fn incompatible(message: &str) -> DialectIssue {
+    DialectIssue::Incompatible(message.to_owned())
+}
+
+fn java_only(construct: &str) -> DialectIssue {
+    DialectIssue::JavaOnly(format!(
+        "{} are supported by Marathon but can't be checked locally",
+        construct
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn incompatible_count(regex: &str) -> usize {
+        scan(regex)
+            .issues
+            .iter()
+            .filter(|issue| matches!(issue, DialectIssue::Incompatible(_)))
+            .count()
+    }
+
+    fn java_only_count(regex: &str) -> usize {
+        scan(regex)
+            .issues
+            .iter()
+            .filter(|issue| matches!(issue, DialectIssue::JavaOnly(_)))
+            .count()
+    }
+
+    #[test]
+    fn test_common_regexes_are_portable() {
+        for regex in [
+            ".*Test",
+            "com\\.example\\..*",
+            "test[A-Z]\\w+",
+            "[^#]+#test.*",
+            "(?i)login.*",
+            "(?<name>Foo|Bar)Test",
+            "a{2,3}b+?",
+            "[\\[\\]]+",
+            "[]a]",
+        ] {
+            assert_eq!(scan(regex).issues, vec![], "{}", regex);
+        }
+    }
+
+    #[test]
+    fn test_incompatible_constructs() {
+        assert_eq!(incompatible_count("(?P<name>Test)"), 1);
+        assert_eq!(incompatible_count("[[:alpha:]]+Test"), 1);
+        assert_eq!(incompatible_count("\\<Test\\>"), 2);
+        assert_eq!(incompatible_count("[a-z--aeiou]"), 1);
+        assert_eq!(incompatible_count("(?U)a+"), 1);
+    }
+
+    #[test]
+    fn test_java_only_constructs() {
+        assert_eq!(java_only_count("(?<!Base)Test"), 1);
+        assert_eq!(java_only_count("(?=.*Login).*"), 1);
+        assert_eq!(java_only_count("a++b"), 1);
+        assert_eq!(java_only_count("(\\w)\\1"), 1);
+        assert_eq!(java_only_count("\\Q[literal]\\E.*"), 1);
+        assert_eq!(java_only_count("a+?b"), 0);
+    }
+
+    #[test]
+    fn test_non_ascii_literals_keep_position() {
+        assert_eq!(incompatible_count("\\Qäöü\\E\\<Test"), 1);
+        assert_eq!(incompatible_count("[[:äöü:]]\\<Test"), 2);
+    }
+
+    #[test]
+    fn test_checkable_replaces_java_only_constructs() {
+        for (regex, checkable) in [
+            ("(?<!Base)Test", "(?:Base)Test"),
+            ("(?=.*Login).*", "(?:.*Login).*"),
+            ("(?>a|b)c", "(?:a|b)c"),
+            ("a++b", "a+b"),
+            ("(\\w)\\1", "(\\w)(?:)"),
+            ("(?<x>a)\\k<x>", "(?<x>a)(?:)"),
+            ("\\Q[é]\\E.*", "\\[é\\].*"),
+            ("(?d)a", "(?:)a"),
+            ("(?id:a)", "(?i:a)"),
+            ("[a-z]+Test", "[a-z]+Test"),
+        ] {
+            assert_eq!(scan(regex).checkable, checkable, "{}", regex);
+        }
+    }
+}
@@ -1,3 +1,5 @@
 pub mod convert;
+pub mod evaluate;
+mod java_regex;
 pub mod model;
 mod xctestplan;
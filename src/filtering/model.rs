@@ -1,17 +1,34 @@
-use std::path::PathBuf;
+use std::{fmt, path::PathBuf, str::FromStr};
 
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{self, IgnoredAny, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
 use serde_with::skip_serializing_none;
 
+use crate::errors::FilteringConfigurationError;
+
 #[skip_serializing_none]
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct SparseMarathonfile {
     #[serde(rename = "filteringConfiguration")]
     pub filtering_configuration: FilteringConfiguration,
 }
 
+/// Filter file as written by users: the filtering configuration and other filter files to merge into it
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FilterFile {
+    /// Paths relative to this file
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
+    #[serde(rename = "filteringConfiguration")]
+    pub filtering_configuration: Option<FilteringConfiguration>,
+}
+
 #[skip_serializing_none]
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
 pub struct FilteringConfiguration {
     #[serde(rename = "allowlist")]
     pub allowlist: Option<Vec<Filter>>,
@@ -19,22 +36,459 @@ pub struct FilteringConfiguration {
     pub blocklist: Option<Vec<Filter>>,
 }
 
-// Very simplstic and flattened representation of https://github.com/MarathonLabs/marathon/blob/0.9.1/configuration/src/main/kotlin/com/malinskiy/marathon/config/FilteringConfiguration.kt
-#[skip_serializing_none]
-#[derive(Deserialize, Serialize)]
-pub struct Filter {
-    #[serde(rename = "type")]
-    pub mtype: String,
-
-    #[serde[rename = "regex"]]
-    pub regex: Option<String>,
-    #[serde[rename = "values"]]
-    pub values: Option<Vec<String>>,
-    #[serde[rename = "file"]]
-    pub file: Option<PathBuf>,
-
-    #[serde[rename = "filters"]]
-    pub filters: Option<Vec<Filter>>,
-    #[serde[rename = "op"]]
-    pub op: Option<String>,
+// Mirrors https://github.com/MarathonLabs/marathon/blob/0.9.1/configuration/src/main/kotlin/com/malinskiy/marathon/config/FilteringConfiguration.kt
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Filter {
+    FullyQualifiedClassName(Matcher),
+    FullyQualifiedTestName(Matcher),
+    SimpleClassName(Matcher),
+    SimpleTestName(Matcher),
+    Package(Matcher),
+    Method(Matcher),
+    Annotation(Matcher),
+    Composition {
+        filters: Vec<Filter>,
+        op: UnionOp,
+    },
+    /// Selects the `index`-th of `count` disjoint parts of the suite
+    Fragmentation {
+        index: u32,
+        count: u32,
+    },
+    /// Applies the filters from the Allure test management configuration of the app
+    Allure,
+    #[serde(rename = "annotationData", rename_all = "camelCase")]
+    AnnotationData {
+        name_regex: String,
+        value_regex: String,
+    },
+}
+
+/// Exactly one of the ways to specify what a single-value filter matches
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Matcher {
+    Regex(String),
+    Values(Vec<String>),
+    /// Path to a file with values, relative to the filter file. Resolved into values by `convert`
+    File(PathBuf),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum UnionOp {
+    Union,
+    Intersection,
+    Subtract,
+}
+
+impl fmt::Display for UnionOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnionOp::Union => f.write_str("UNION"),
+            UnionOp::Intersection => f.write_str("INTERSECTION"),
+            UnionOp::Subtract => f.write_str("SUBTRACT"),
+        }
+    }
+}
+
+const VALUE_TYPES: &[&str] = &[
+    "fully-qualified-class-name",
+    "fully-qualified-test-name",
+    "simple-class-name",
+    "simple-test-name",
+    "package",
+    "method",
+    "annotation",
+];
+const VALUE_FIELDS: &[&str] = &["type", "regex", "values", "file"];
+const COMPOSITION_FIELDS: &[&str] = &["type", "filters", "op"];
+const FRAGMENTATION_FIELDS: &[&str] = &["type", "index", "count"];
+const ALLURE_FIELDS: &[&str] = &["type"];
+const ANNOTATION_DATA_FIELDS: &[&str] = &["type", "nameRegex", "valueRegex"];
+
+impl Filter {
+    pub fn mtype(&self) -> &'static str {
+        match self {
+            Filter::FullyQualifiedClassName(_) => "fully-qualified-class-name",
+            Filter::FullyQualifiedTestName(_) => "fully-qualified-test-name",
+            Filter::SimpleClassName(_) => "simple-class-name",
+            Filter::SimpleTestName(_) => "simple-test-name",
+            Filter::Package(_) => "package",
+            Filter::Method(_) => "method",
+            Filter::Annotation(_) => "annotation",
+            Filter::Composition { .. } => "composition",
+            Filter::Fragmentation { .. } => "fragmentation",
+            Filter::Allure => "allure",
+            Filter::AnnotationData { .. } => "annotationData",
+        }
+    }
+
+    pub fn matcher(&self) -> Option<&Matcher> {
+        match self {
+            Filter::FullyQualifiedClassName(matcher)
+            | Filter::FullyQualifiedTestName(matcher)
+            | Filter::SimpleClassName(matcher)
+            | Filter::SimpleTestName(matcher)
+            | Filter::Package(matcher)
+            | Filter::Method(matcher)
+            | Filter::Annotation(matcher) => Some(matcher),
+            _ => None,
+        }
+    }
+
+    pub fn matcher_mut(&mut self) -> Option<&mut Matcher> {
+        match self {
+            Filter::FullyQualifiedClassName(matcher)
+            | Filter::FullyQualifiedTestName(matcher)
+            | Filter::SimpleClassName(matcher)
+            | Filter::SimpleTestName(matcher)
+            | Filter::Package(matcher)
+            | Filter::Method(matcher)
+            | Filter::Annotation(matcher) => Some(matcher),
+            _ => None,
+        }
+    }
+
+    fn with_matcher(mtype: &str, matcher: Matcher) -> Option<Filter> {
+        Some(match mtype {
+            "fully-qualified-class-name" => Filter::FullyQualifiedClassName(matcher),
+            "fully-qualified-test-name" => Filter::FullyQualifiedTestName(matcher),
+            "simple-class-name" => Filter::SimpleClassName(matcher),
+            "simple-test-name" => Filter::SimpleTestName(matcher),
+            "package" => Filter::Package(matcher),
+            "method" => Filter::Method(matcher),
+            "annotation" => Filter::Annotation(matcher),
+            _ => return None,
+        })
+    }
+}
+
+/// Part of the suite selected by `--shard <index>/<count>`, indices start at 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl From<Shard> for Filter {
+    fn from(shard: Shard) -> Self {
+        Filter::Fragmentation {
+            index: shard.index,
+            count: shard.count,
+        }
+    }
+}
+
+impl FromStr for Shard {
+    type Err = FilteringConfigurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: String| FilteringConfigurationError::InvalidFilterConfiguration {
+            mtype: "fragmentation".to_owned(),
+            message,
+        };
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| invalid(format!("shard [{}] should be <index>/<count>", s)))?;
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<i64>()
+                .map_err(|_| invalid(format!("shard [{}] should be <index>/<count>", s)))
+        };
+        let (index, count) = fragment(parse(index)?, parse(count)?).map_err(invalid)?;
+        Ok(Shard { index, count })
+    }
+}
+
+// Same checks as Marathon's FragmentationFilterConfiguration.validate()
+fn fragment(index: i64, count: i64) -> Result<(u32, u32), String> {
+    if index < 0 {
+        return Err(format!("Fragment index [{}] should be >= 0", index));
+    }
+    if count < 0 {
+        return Err(format!("Fragment count [{}] should be >= 0", count));
+    }
+    if index >= count {
+        return Err(format!(
+            "Fragment index [{}] is out of bounds (max {})",
+            index,
+            count - 1
+        ));
+    }
+    let index =
+        u32::try_from(index).map_err(|_| format!("Fragment index [{}] is too large", index))?;
+    let count =
+        u32::try_from(count).map_err(|_| format!("Fragment count [{}] is too large", count))?;
+    Ok((index, count))
+}
+
+// Hand-written so that every error is raised while serde_yaml is positioned at the offending filter:
+// the message then carries the path (e.g. `filteringConfiguration.allowlist[1].filters[0]`) and line
+impl<'de> Deserialize<'de> for Filter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(FilterVisitor)
+    }
+}
+
+struct FilterVisitor;
+
+impl<'de> Visitor<'de> for FilterVisitor {
+    type Value = Filter;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a filter with a type")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Filter, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut mtype: Option<String> = None;
+        let mut regex: Option<String> = None;
+        let mut values: Option<Vec<String>> = None;
+        let mut file: Option<PathBuf> = None;
+        let mut filters: Option<Vec<Filter>> = None;
+        let mut op: Option<UnionOp> = None;
+        let mut index: Option<i64> = None;
+        let mut count: Option<i64> = None;
+        let mut name_regex: Option<String> = None;
+        let mut value_regex: Option<String> = None;
+        let mut fields: Vec<String> = vec![];
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "type" => mtype = Some(map.next_value()?),
+                "regex" => regex = Some(map.next_value()?),
+                "values" => values = Some(map.next_value()?),
+                "file" => file = Some(map.next_value()?),
+                "filters" => filters = Some(map.next_value()?),
+                "op" => op = Some(map.next_value()?),
+                "index" => index = Some(map.next_value()?),
+                "count" => count = Some(map.next_value()?),
+                "nameRegex" => name_regex = Some(map.next_value()?),
+                "valueRegex" => value_regex = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+            fields.push(key);
+        }
+
+        let mtype = mtype.ok_or_else(|| de::Error::missing_field("type"))?;
+        let invalid = |message: &str| {
+            de::Error::custom(FilteringConfigurationError::InvalidFilterConfiguration {
+                mtype: mtype.clone(),
+                message: message.to_owned(),
+            })
+        };
+
+        let expected = match mtype.as_str() {
+            "composition" => COMPOSITION_FIELDS,
+            "fragmentation" => FRAGMENTATION_FIELDS,
+            "allure" => ALLURE_FIELDS,
+            "annotationData" => ANNOTATION_DATA_FIELDS,
+            mtype if VALUE_TYPES.contains(&mtype) => VALUE_FIELDS,
+            _ => {
+                return Err(de::Error::custom(
+                    FilteringConfigurationError::InvalidFilterType { mtype },
+                ))
+            }
+        };
+        if let Some(field) = fields.iter().find(|f| !expected.contains(&f.as_str())) {
+            return Err(de::Error::unknown_field(field, expected));
+        }
+
+        match mtype.as_str() {
+            "composition" => {
+                let op = op.ok_or_else(|| invalid("missing 'op' field"))?;
+                let filters = filters.ok_or_else(|| invalid("missing composition filters"))?;
+                return Ok(Filter::Composition { filters, op });
+            }
+            "fragmentation" => {
+                let index = index.ok_or_else(|| de::Error::missing_field("index"))?;
+                let count = count.ok_or_else(|| de::Error::missing_field("count"))?;
+                let (index, count) = fragment(index, count).map_err(|message| invalid(&message))?;
+                return Ok(Filter::Fragmentation { index, count });
+            }
+            "allure" => return Ok(Filter::Allure),
+            "annotationData" => {
+                return Ok(Filter::AnnotationData {
+                    name_regex: name_regex.ok_or_else(|| de::Error::missing_field("nameRegex"))?,
+                    value_regex: value_regex
+                        .ok_or_else(|| de::Error::missing_field("valueRegex"))?,
+                });
+            }
+            _ => {}
+        }
+
+        let matcher = match (regex, values, file) {
+            (Some(regex), None, None) => Matcher::Regex(regex),
+            (None, Some(values), None) => Matcher::Values(values),
+            (None, None, Some(file)) => Matcher::File(file),
+            (None, None, None) => {
+                return Err(invalid(
+                    "At least one of regex, values or file should be specified",
+                ))
+            }
+            _ => {
+                return Err(invalid(
+                    "only one of [regex, values, file] can be specified",
+                ))
+            }
+        };
+        Filter::with_matcher(&mtype, matcher)
+            .ok_or_else(|| de::Error::unknown_variant(&mtype, VALUE_TYPES))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> Result<SparseMarathonfile, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let result = parse(
+            r#"
+filteringConfiguration:
+  allowlist:
+    - type: composition
+      op: SUBTRACT
+      filters:
+        - type: package
+          regex: "com\\.example.*"
+        - type: simple-class-name
+          file: classes.txt
+"#,
+        )?;
+        assert_eq!(
+            serde_json::to_string(&result)?,
+            r#"{"filteringConfiguration":{"allowlist":[{"type":"composition","filters":[{"type":"package","regex":"com\\.example.*"},{"type":"simple-class-name","file":"classes.txt"}],"op":"SUBTRACT"}]}}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_points_to_nested_filter() {
+        let error = parse(
+            r#"
+filteringConfiguration:
+  allowlist:
+    - type: package
+      values: ["com.example"]
+    - type: composition
+      op: UNION
+      filters:
+        - type: method
+          regex: "test.*"
+        - type: simple-class-name
+"#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "filteringConfiguration.allowlist[1].filters[1]: Invalid configuration for filter simple-class-name: At least one of regex, values or file should be specified at line 11 column 11"
+        );
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let error = parse(
+            r#"
+filteringConfiguration:
+  blocklist:
+    - type: package
+      regexp: "com.*"
+"#,
+        )
+        .unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .starts_with("filteringConfiguration.blocklist[0]: unknown field `regexp`"),
+            "{}",
+            error
+        );
+        assert_eq!(error.location().map(|l| l.line()), Some(4));
+    }
+
+    #[test]
+    fn test_metadata_filters() -> anyhow::Result<()> {
+        let result = parse(
+            r#"
+filteringConfiguration:
+  allowlist:
+    - type: fragmentation
+      index: 0
+      count: 4
+    - type: allure
+    - type: annotationData
+      nameRegex: "io.qameta.allure.kotlin.Severity"
+      valueRegex: "critical"
+"#,
+        )?;
+        assert_eq!(
+            serde_json::to_string(&result)?,
+            r#"{"filteringConfiguration":{"allowlist":[{"type":"fragmentation","index":0,"count":4},{"type":"allure"},{"type":"annotationData","nameRegex":"io.qameta.allure.kotlin.Severity","valueRegex":"critical"}]}}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fragmentation_out_of_bounds() {
+        let error = parse(
+            r#"
+filteringConfiguration:
+  allowlist:
+    - type: fragmentation
+      index: 4
+      count: 4
+"#,
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Fragment index [4] is out of bounds (max 3)"),
+            "{}",
+            error
+        );
+
+        let error = parse(
+            r#"
+filteringConfiguration:
+  allowlist:
+    - type: annotationData
+      nameRegex: "Severity"
+"#,
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("missing field `valueRegex`"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_shard() {
+        assert_eq!(
+            "1/3".parse::<Shard>().unwrap(),
+            Shard { index: 1, count: 3 }
+        );
+        assert!("3/3".parse::<Shard>().is_err());
+        assert!("-1/3".parse::<Shard>().is_err());
+        assert!("1".parse::<Shard>().is_err());
+    }
 }
@@ -1,28 +1,21 @@
 use serde::Deserialize;
 
-//Version 1
-#[derive(Deserialize)]
-pub struct TestPlan {
-    #[serde[rename = "configurations"]]
-    pub configurations: Vec<Configuration>,
-    #[serde[rename = "defaultOptions"]]
-    pub default_options: Options,
-    #[serde[rename = "testTargets"]]
-    pub test_targets: Vec<TestTarget>,
-    #[serde[rename = "version"]]
-    pub version: u32,
-}
+pub mod update;
 
+//Version 1
 #[derive(Deserialize)]
 pub struct SparseTestPlan {
     #[serde[rename = "configurations"]]
     pub configurations: Vec<Configuration>,
+    #[serde(rename = "defaultOptions", default)]
+    pub default_options: Options,
     #[serde[rename = "testTargets"]]
     pub test_targets: Vec<TestTarget>,
 }
 
 #[derive(Deserialize)]
 pub struct Configuration {
+    #[allow(dead_code)]
     #[serde[rename = "id"]]
     pub id: String,
     #[serde[rename = "name"]]
@@ -31,7 +24,7 @@ pub struct Configuration {
     pub options: Options,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Default)]
 pub struct Options {
     #[serde[rename = "environmentVariableEntries"]]
     pub environmnent_variables: Option<Vec<EnvironmentVariableEntry>>,
@@ -91,17 +84,90 @@ pub struct Options {
     pub user_attachment_lifetime: Option<AttachmentLifetime>,
 }
 
-#[derive(Deserialize)]
+impl Options {
+    /// Options of a configuration fall back to the test plan's default options field by field
+    pub fn or(self, defaults: Options) -> Options {
+        Options {
+            environmnent_variables: self
+                .environmnent_variables
+                .or(defaults.environmnent_variables),
+            target_for_variable_expansion: self
+                .target_for_variable_expansion
+                .or(defaults.target_for_variable_expansion),
+            address_sanitizer: self.address_sanitizer.or(defaults.address_sanitizer),
+            thread_sanitizer_enabled: self
+                .thread_sanitizer_enabled
+                .or(defaults.thread_sanitizer_enabled),
+            undefined_behavior_sanitizer_enabled: self
+                .undefined_behavior_sanitizer_enabled
+                .or(defaults.undefined_behavior_sanitizer_enabled),
+            command_line_arguments: self
+                .command_line_arguments
+                .or(defaults.command_line_arguments),
+            language: self.language.or(defaults.language),
+            region: self.region.or(defaults.region),
+            location_scenario: self.location_scenario.or(defaults.location_scenario),
+            test_timeouts_enabled: self
+                .test_timeouts_enabled
+                .or(defaults.test_timeouts_enabled),
+            test_repetition_mode: self.test_repetition_mode.or(defaults.test_repetition_mode),
+            test_execution_ordering: self
+                .test_execution_ordering
+                .or(defaults.test_execution_ordering),
+            maximum_test_repetitions: self
+                .maximum_test_repetitions
+                .or(defaults.maximum_test_repetitions),
+            default_test_execution_time_allowance: self
+                .default_test_execution_time_allowance
+                .or(defaults.default_test_execution_time_allowance),
+            maximum_test_execution_time_allowance: self
+                .maximum_test_execution_time_allowance
+                .or(defaults.maximum_test_execution_time_allowance),
+            code_coverage: self.code_coverage.or(defaults.code_coverage),
+            ui_testing_screenshots_lifetime: self
+                .ui_testing_screenshots_lifetime
+                .or(defaults.ui_testing_screenshots_lifetime),
+            main_thread_checker_enabled: self
+                .main_thread_checker_enabled
+                .or(defaults.main_thread_checker_enabled),
+            nszombie_enabled: self.nszombie_enabled.or(defaults.nszombie_enabled),
+            guard_malloc_enabled: self.guard_malloc_enabled.or(defaults.guard_malloc_enabled),
+            malloc_guard_edges_enabled: self
+                .malloc_guard_edges_enabled
+                .or(defaults.malloc_guard_edges_enabled),
+            malloc_scribble_enabled: self
+                .malloc_scribble_enabled
+                .or(defaults.malloc_scribble_enabled),
+            malloc_stack_logging: self.malloc_stack_logging.or(defaults.malloc_stack_logging),
+            are_localization_screenshots_enabled: self
+                .are_localization_screenshots_enabled
+                .or(defaults.are_localization_screenshots_enabled),
+            diagnostic_collection_policy: self
+                .diagnostic_collection_policy
+                .or(defaults.diagnostic_collection_policy),
+            preferred_screen_capture_format: self
+                .preferred_screen_capture_format
+                .or(defaults.preferred_screen_capture_format),
+            user_attachment_lifetime: self
+                .user_attachment_lifetime
+                .or(defaults.user_attachment_lifetime),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
 pub struct Target {
+    #[allow(dead_code)]
     #[serde[rename = "containerPath"]]
     pub container_path: String,
+    #[allow(dead_code)]
     #[serde[rename = "identifier"]]
     pub identifier: String,
     #[serde[rename = "name"]]
     pub name: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct CommandLineArgumentEntry {
     #[serde[rename = "argument"]]
     pub argument: String,
@@ -109,7 +175,7 @@ pub struct CommandLineArgumentEntry {
     pub enabled: Option<bool>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct EnvironmentVariableEntry {
     #[serde[rename = "key"]]
     pub key: String,
@@ -119,16 +185,19 @@ pub struct EnvironmentVariableEntry {
     pub enabled: Option<bool>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct LocationScenario {
+    #[allow(dead_code)]
     #[serde[rename = "identifier"]]
     pub identifier: String,
+    #[allow(dead_code)]
     #[serde[rename = "referenceType"]]
     pub reference_type: Option<LocationReferenceType>,
 }
 
 #[derive(Deserialize)]
 pub struct TestTarget {
+    #[allow(dead_code)]
     #[serde[rename = "parallelizable"]]
     pub parallelizable: Option<bool>,
     #[serde[rename = "skippedTests"]]
@@ -141,32 +210,23 @@ pub struct TestTarget {
     pub enabled: Option<bool>,
 }
 
-#[derive(Deserialize)]
-pub struct SparseTestTarget {
-    #[serde[rename = "skippedTests"]]
-    pub skipped_tests: Option<Vec<String>>,
-    #[serde[rename = "selectedTests"]]
-    pub selected_tests: Option<Vec<String>>,
-    #[serde[rename = "target"]]
-    pub target: Target,
-}
-
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct AddressSanitizer {
+    #[allow(dead_code)]
     #[serde[rename = "detectStackUseAfterReturn"]]
     pub detect_stack_use_after_return: Option<bool>,
     #[serde[rename = "enabled"]]
     pub enabled: Option<bool>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct MallocStackLoggingOptions {
     #[serde[rename = "loggingType"]]
     pub logging_type: Option<MallocStackLoggingType>,
 }
 
 //None is represented as null value
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub enum MallocStackLoggingType {
     #[serde[rename = "liveAllocations"]]
     LiveAllocationsOnly,
@@ -174,7 +234,7 @@ pub enum MallocStackLoggingType {
     AllAllocationsAndFreeHistory,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub enum TestExecutionOrdering {
     #[serde[rename = "random"]]
     Random,
@@ -182,7 +242,7 @@ pub enum TestExecutionOrdering {
     Alphabetical,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub enum AttachmentLifetime {
     #[serde[rename = "keepAlways"]]
     OnAndKeepAll,
@@ -192,7 +252,7 @@ pub enum AttachmentLifetime {
     Off,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub enum TestRepetitionMode {
     #[serde[rename = "untilFailure"]]
     UntilFailure,
@@ -204,7 +264,7 @@ pub enum TestRepetitionMode {
     None,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub enum ScreenCaptureFormat {
     #[serde[rename = "screenshot"]]
     Screenshot,
@@ -212,7 +272,7 @@ pub enum ScreenCaptureFormat {
     RetryOnFailure,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub enum DiagnosticCollectionPolicy {
     #[serde[rename = "xcodebuild"]]
     WhenTestingWithXcodebuild,
@@ -222,7 +282,7 @@ pub enum DiagnosticCollectionPolicy {
     Always,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub enum LocationReferenceType {
     #[serde[rename = "built-in"]]
     BuiltIn,
@@ -0,0 +1,375 @@
+// Writes Marathon filters back into an existing test plan. The plan is edited as a JSON tree
+// with preserved key order and re-serialized in the style Xcode uses, so unrelated fields don't change.
+use std::io;
+
+use anyhow::Result;
+use serde_json::{ser::Formatter, Value};
+
+use crate::{
+    errors::{FilteringConfigurationError, InputError},
+    filtering::model::{Filter, FilteringConfiguration, Matcher, UnionOp},
+};
+
+/// Replaces `selectedTests` and `skippedTests` of the targets with the allowlist and blocklist
+pub fn update(plan: &str, cnf: &FilteringConfiguration, target_names: &[String]) -> Result<String> {
+    let selected = match cnf.allowlist.as_deref() {
+        None | Some([]) => None,
+        Some([filter]) => Some(test_ids(filter, "filteringConfiguration.allowlist[0]")?),
+        Some(_) => anyhow::bail!(not_convertible(
+            "filteringConfiguration.allowlist",
+            "several allowlist filters intersect, which a test plan can't express. Combine them into a single UNION composition"
+        )),
+    };
+    let skipped = match cnf.blocklist.as_deref() {
+        None | Some([]) => None,
+        Some(filters) => {
+            let mut ids = vec![];
+            for (index, filter) in filters.iter().enumerate() {
+                let path = format!("filteringConfiguration.blocklist[{}]", index);
+                ids.extend(test_ids(filter, &path)?);
+            }
+            Some(ids)
+        }
+    };
+
+    let mut json: Value = serde_json::from_str(plan)?;
+    let targets = json
+        .get_mut("testTargets")
+        .and_then(Value::as_array_mut)
+        .ok_or(InputError::XctestplanMissingTargets)?;
+    let available = || targets_names(targets).collect::<Vec<_>>().join(", ");
+    let indices: Vec<usize> = if target_names.is_empty() {
+        match targets.len() {
+            0 => anyhow::bail!(InputError::XctestplanMissingTargets),
+            1 => vec![0],
+            _ => anyhow::bail!(InputError::XctestplanMissingTarget {
+                name: "<none, pick one with --xctestplan-target-name>".to_owned(),
+                available: available(),
+            }),
+        }
+    } else {
+        let mut indices = vec![];
+        for name in target_names {
+            let index = targets_names(targets)
+                .position(|target| target == name)
+                .ok_or_else(|| InputError::XctestplanMissingTarget {
+                    name: name.clone(),
+                    available: available(),
+                })?;
+            indices.push(index);
+        }
+        indices
+    };
+
+    for index in indices {
+        let Some(target) = targets[index].as_object_mut() else {
+            continue;
+        };
+        for (key, ids) in [("selectedTests", &selected), ("skippedTests", &skipped)] {
+            match ids {
+                Some(ids) => {
+                    let ids = ids.iter().cloned().map(Value::String).collect();
+                    // Xcode writes keys sorted, a new key goes where Xcode would put it
+                    match target.get_mut(key) {
+                        Some(value) => *value = Value::Array(ids),
+                        None => {
+                            let position = target
+                                .keys()
+                                .position(|existing| existing.as_str() > key)
+                                .unwrap_or(target.len());
+                            target.shift_insert(position, key.to_owned(), Value::Array(ids));
+                        }
+                    }
+                }
+                None => {
+                    target.shift_remove(key);
+                }
+            }
+        }
+    }
+
+    // Plans written by Xcode use both, hand-formatted ones may escape slashes only
+    let spaced_colon = plan.contains("\" : ");
+    let style = Style {
+        spaced_colon,
+        escaped_slash: spaced_colon || plan.contains("\\/"),
+    };
+    let mut output = Vec::new();
+    let mut serializer =
+        serde_json::Serializer::with_formatter(&mut output, XcodeFormatter::new(style));
+    serde::Serialize::serialize(&json, &mut serializer)?;
+    let mut output = String::from_utf8(output)?;
+    if plan.ends_with('\n') {
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+fn targets_names(targets: &[Value]) -> impl Iterator<Item = &str> {
+    targets.iter().map(|target| {
+        target
+            .pointer("/target/name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+    })
+}
+
+fn not_convertible(path: &str, message: &str) -> FilteringConfigurationError {
+    FilteringConfigurationError::NotConvertible {
+        path: path.to_owned(),
+        message: message.to_owned(),
+    }
+}
+
+/// Test plan identifiers: `ClassName` or `ClassName/method()`
+fn test_ids(filter: &Filter, path: &str) -> Result<Vec<String>> {
+    match filter {
+        Filter::SimpleClassName(Matcher::Values(values)) => Ok(values.clone()),
+        Filter::SimpleTestName(Matcher::Values(values)) => values
+            .iter()
+            .map(|value| match value.split_once('#') {
+                Some((class, method)) => Ok(format!("{}/{}()", class, method)),
+                None => Err(not_convertible(
+                    path,
+                    &format!("test name {} should have the form ClassName#method", value),
+                )
+                .into()),
+            })
+            .collect(),
+        Filter::Composition {
+            filters,
+            op: UnionOp::Union,
+        } => {
+            let mut ids = vec![];
+            for (index, filter) in filters.iter().enumerate() {
+                ids.extend(test_ids(filter, &format!("{}.filters[{}]", path, index))?);
+            }
+            Ok(ids)
+        }
+        _ => Err(not_convertible(
+            path,
+            "only simple-class-name and simple-test-name filters with values and their UNION compositions can be written to a test plan",
+        )
+        .into()),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Style {
+    /// Xcode writes `"key" : value`
+    spaced_colon: bool,
+    /// Xcode writes `/` as `\/`
+    escaped_slash: bool,
+}
+
+/// Pretty printer matching the layout of test plans written by Xcode, including the blank line in empty objects
+struct XcodeFormatter {
+    style: Style,
+    indent: usize,
+    has_value: bool,
+}
+
+impl XcodeFormatter {
+    fn new(style: Style) -> Self {
+        XcodeFormatter {
+            style,
+            indent: 0,
+            has_value: false,
+        }
+    }
+
+    fn newline<W: ?Sized + io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b"\n")?;
+        for _ in 0..self.indent {
+            writer.write_all(b"  ")?;
+        }
+        Ok(())
+    }
+
+    fn begin<W: ?Sized + io::Write>(&mut self, writer: &mut W, bracket: &[u8]) -> io::Result<()> {
+        self.indent += 1;
+        self.has_value = false;
+        writer.write_all(bracket)
+    }
+
+    fn end<W: ?Sized + io::Write>(&mut self, writer: &mut W, bracket: &[u8]) -> io::Result<()> {
+        self.indent -= 1;
+        if self.has_value {
+            self.newline(writer)?;
+        } else if self.style.spaced_colon {
+            writer.write_all(b"\n")?;
+            self.newline(writer)?;
+        }
+        self.has_value = true;
+        writer.write_all(bracket)
+    }
+
+    fn element<W: ?Sized + io::Write>(&mut self, writer: &mut W, first: bool) -> io::Result<()> {
+        if !first {
+            writer.write_all(b",")?;
+        }
+        self.newline(writer)
+    }
+}
+
+impl Formatter for XcodeFormatter {
+    fn begin_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.begin(writer, b"[")
+    }
+
+    fn end_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.end(writer, b"]")
+    }
+
+    fn begin_array_value<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.element(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + io::Write>(&mut self, _writer: &mut W) -> io::Result<()> {
+        self.has_value = true;
+        Ok(())
+    }
+
+    fn begin_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.begin(writer, b"{")
+    }
+
+    fn end_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.end(writer, b"}")
+    }
+
+    fn begin_object_key<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.element(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if self.style.spaced_colon {
+            writer.write_all(b" : ")
+        } else {
+            writer.write_all(b": ")
+        }
+    }
+
+    fn end_object_value<W: ?Sized + io::Write>(&mut self, _writer: &mut W) -> io::Result<()> {
+        self.has_value = true;
+        Ok(())
+    }
+
+    fn write_string_fragment<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> io::Result<()> {
+        if self.style.escaped_slash {
+            writer.write_all(fragment.replace('/', "\\/").as_bytes())
+        } else {
+            writer.write_all(fragment.as_bytes())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAN: &str = r#"{
+  "configurations" : [
+    {
+      "id" : "0F2F3C4A",
+      "name" : "Configuration 1",
+      "options" : {
+
+      }
+    }
+  ],
+  "testTargets" : [
+    {
+      "parallelizable" : true,
+      "skippedTests" : [
+        "OldTests"
+      ],
+      "target" : {
+        "containerPath" : "container:App.xcodeproj",
+        "name" : "AppTests"
+      }
+    }
+  ],
+  "version" : 1
+}
+"#;
+
+    fn filters(yaml: &str) -> FilteringConfiguration {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_update_keeps_unrelated_fields() -> Result<()> {
+        let cnf = filters(
+            r#"
+allowlist:
+  - type: composition
+    op: UNION
+    filters:
+      - type: simple-class-name
+        values: ["LoginTests"]
+      - type: simple-test-name
+        values: ["UploadTest#testUpload"]
+"#,
+        );
+        let result = update(PLAN, &cnf, &[])?;
+
+        assert_eq!(
+            result,
+            PLAN.replace(
+                "      \"skippedTests\" : [\n        \"OldTests\"\n      ],",
+                "      \"selectedTests\" : [\n        \"LoginTests\",\n        \"UploadTest\\/testUpload()\"\n      ],"
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_replaces_in_place() -> Result<()> {
+        let cnf = filters(
+            r#"
+blocklist:
+  - type: simple-class-name
+    values: ["OldTests", "FlakyTests"]
+"#,
+        );
+        let result = update(PLAN, &cnf, &["AppTests".to_owned()])?;
+
+        assert_eq!(
+            result,
+            PLAN.replace("\"OldTests\"\n", "\"OldTests\",\n        \"FlakyTests\"\n")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_filter() {
+        let cnf = filters(
+            r#"
+allowlist:
+  - type: package
+    values: ["com.example"]
+"#,
+        );
+        let error = update(PLAN, &cnf, &[]).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("filteringConfiguration.allowlist[0]"),
+            "{}",
+            error
+        );
+    }
+}
@@ -1,4 +1,11 @@
+use anyhow::Result;
 use console::style;
+use serde::Serialize;
+
+use crate::{
+    api::{AndroidDevice, IosDevice},
+    cli::model::{FormFactor, OutputFormat},
+};
 
 pub trait Formatter {
     fn stage(&mut self, message: &str);
@@ -33,3 +40,157 @@ impl Formatter for StandardFormatter {
         println!("{}", &message);
     }
 }
+
+/// Prints a warning to stderr, independent of the log level
+pub(crate) fn warning(message: &str) {
+    eprintln!("{} {}", style("warning:").yellow().bold(), message);
+}
+
+/// Items that can be printed as rows of a table
+pub trait Tabular {
+    fn headers() -> &'static [&'static str];
+    fn row(&self) -> Vec<String>;
+
+    /// Fields of a single item, one per line
+    fn details(&self) -> Vec<(&'static str, String)> {
+        Self::headers().iter().copied().zip(self.row()).collect()
+    }
+}
+
+impl Tabular for AndroidDevice {
+    fn headers() -> &'static [&'static str] {
+        &["NAME", "ID", "MANUFACTURER", "RESOLUTION", "DPI"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.id.clone(),
+            self.manufacturer.clone(),
+            format!("{}x{}", self.width, self.height),
+            self.dpi.to_string(),
+        ]
+    }
+
+    fn details(&self) -> Vec<(&'static str, String)> {
+        let mut details: Vec<_> = Self::headers().iter().copied().zip(self.row()).collect();
+        details.push(("FORM FACTOR", FormFactor::of(self).to_string()));
+        details
+    }
+}
+
+impl Tabular for IosDevice {
+    fn headers() -> &'static [&'static str] {
+        &["DEVICE", "OS VERSION", "XCODE VERSION"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.device.clone(),
+            self.os_version.clone(),
+            self.xcode_version.clone(),
+        ]
+    }
+}
+
+pub fn render<T: Serialize + Tabular>(items: &[T], format: OutputFormat) -> Result<String> {
+    Ok(match format {
+        OutputFormat::Table => table(T::headers(), items.iter().map(Tabular::row).collect()),
+        OutputFormat::Json => serde_json::to_string_pretty(items)?,
+        OutputFormat::Yaml => serde_yaml::to_string(items)?,
+    })
+}
+
+pub fn render_details<T: Serialize + Tabular>(item: &T, format: OutputFormat) -> Result<String> {
+    Ok(match format {
+        OutputFormat::Table => table(
+            &[],
+            item.details()
+                .into_iter()
+                .map(|(header, value)| vec![header.to_owned(), value])
+                .collect(),
+        ),
+        OutputFormat::Json => serde_json::to_string_pretty(item)?,
+        OutputFormat::Yaml => serde_yaml::to_string(item)?,
+    })
+}
+
+/// Left-aligned columns separated by two spaces
+pub(crate) fn table(headers: &[&str], rows: Vec<Vec<String>>) -> String {
+    let columns = rows.iter().map(Vec::len).chain([headers.len()]).max();
+    let mut widths: Vec<usize> = vec![0; columns.unwrap_or_default()];
+    let headers: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    for row in rows.iter().chain([&headers]) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    (!headers.is_empty())
+        .then_some(headers)
+        .into_iter()
+        .chain(rows)
+        .map(|row: Vec<String>| {
+            row.iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() -> Result<()> {
+        let devices = vec![
+            IosDevice {
+                device: "iPhone-15".into(),
+                os_version: "17.5".into(),
+                xcode_version: "15.4".into(),
+            },
+            IosDevice {
+                device: "iPhone-16-Pro-Max".into(),
+                os_version: "18.2".into(),
+                xcode_version: "16.2".into(),
+            },
+        ];
+
+        assert_eq!(
+            render(&devices, OutputFormat::Table)?,
+            "DEVICE             OS VERSION  XCODE VERSION\n\
+             iPhone-15          17.5        15.4\n\
+             iPhone-16-Pro-Max  18.2        16.2"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_details() -> Result<()> {
+        let device = AndroidDevice {
+            name: "Pixel 6".into(),
+            id: "pixel_6".into(),
+            manufacturer: "Google".into(),
+            width: 1080,
+            height: 2400,
+            dpi: 411,
+        };
+
+        assert_eq!(
+            render_details(&device, OutputFormat::Table)?,
+            "NAME          Pixel 6\n\
+             ID            pixel_6\n\
+             MANUFACTURER  Google\n\
+             RESOLUTION    1080x2400\n\
+             DPI           411\n\
+             FORM FACTOR   phone"
+        );
+        Ok(())
+    }
+}
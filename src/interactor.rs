@@ -1,5 +1,9 @@
-use crate::{bundle::ApplicationBundle, cli::model::Platform, pull::PullFileConfig};
+use crate::{
+    cli::model::{DeviceQuery, OutputFormat},
+    spec::RunSpec,
+};
 use anyhow::Result;
+use futures::future::join_all;
 use globset::Glob;
 use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
 use serde::Serialize;
@@ -10,31 +14,28 @@ use std::{
 use url::{Position, Url};
 
 use log::debug;
-use tokio::{
-    fs::File,
-    io::AsyncWriteExt,
-    time::{sleep, Instant},
-};
+use tokio::{fs::File, io::AsyncWriteExt, time::Instant};
 
 use crate::{
-    api::{Artifact, RapiClient, RapiReqwestClient},
+    api::{Artifact, RapiClient, RapiReqwestClient, TestRun},
     artifacts::{download_artifacts, fetch_artifact_list, patch_allure_paths},
     errors::InputError,
-    filtering::model::SparseMarathonfile,
-    formatter::{Formatter, StandardFormatter},
-    progress::{TestRunFinished, TestRunStarted},
+    formatter::{render, render_details, table, Formatter, StandardFormatter},
+    matrix::Cell,
+    progress::{MatrixCellEvent, TestRunFinished, TestRunStarted},
 };
 
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
 pub struct DownloadArtifactsInteractor {}
 
 impl DownloadArtifactsInteractor {
     pub(crate) async fn execute(
         &self,
-        base_url: &str,
-        api_key: &str,
+        client: &RapiReqwestClient,
         id: &str,
         wait: bool,
-        output: &PathBuf,
+        output: &Path,
         glob: Option<String>,
         no_progress_bars: bool,
     ) -> Result<()> {
@@ -42,27 +43,19 @@ impl DownloadArtifactsInteractor {
         let mut formatter = StandardFormatter::new(4);
         formatter.stage("Checking test run state...");
 
-        let client = RapiReqwestClient::new(base_url, api_key);
         let stat = client.get_run(id).await?;
         if stat.completed.is_none() && wait {
-            loop {
-                if stat.completed.is_some() {
-                    break;
-                }
-                sleep(Duration::new(5, 0)).await;
-            }
-        } else {
-            debug!("Test run {} finished", &id);
+            client.wait_for_run(id, POLL_INTERVAL).await?;
         }
+        debug!("Test run {} finished", &id);
 
         formatter.stage("Fetching file list...");
-        let token = client.get_token().await?;
-        let artifacts = fetch_artifact_list(&client, id, &token).await?;
+        let artifacts = fetch_artifact_list(client, id).await?;
         let test_run_id_prefix = format!("{}/", id);
         let artifacts = filter_artifact_list(artifacts, glob, &test_run_id_prefix)?;
 
         formatter.stage("Downloading files...");
-        download_artifacts(&client, id, artifacts, output, &token, no_progress_bars).await?;
+        download_artifacts(client, id, artifacts, output, no_progress_bars).await?;
         formatter.stage("Patching local relative paths...");
         patch_allure_paths(output).await?;
 
@@ -98,47 +91,17 @@ fn filter_artifact_list(
 pub struct TriggerTestRunInteractor {}
 
 impl TriggerTestRunInteractor {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn execute(
         &self,
-        base_url: &str,
-        api_key: &str,
-        name: Option<String>,
-        link: Option<String>,
-        branch: Option<String>,
+        client: &RapiReqwestClient,
+        spec: RunSpec,
         wait: bool,
-        isolated: Option<bool>,
         ignore_test_failures: Option<bool>,
-        code_coverage: Option<bool>,
-        retry_quota_test_uncompleted: Option<u32>,
-        retry_quota_test_preventive: Option<u32>,
-        retry_quota_test_reactive: Option<u32>,
-        analytics_read_only: Option<bool>,
-        profiling: bool,
-        mock_location: bool,
-        filtering_configuration: Option<SparseMarathonfile>,
         output: &Option<PathBuf>,
-        application: Option<PathBuf>,
-        test_application: Option<PathBuf>,
-        os_version: Option<String>,
-        system_image: Option<String>,
-        device: Option<String>,
-        xcode_version: Option<String>,
-        flavor: Option<String>,
-        platform: String,
         no_progress_bars: bool,
         result_file: Option<PathBuf>,
-        env_args: Option<Vec<String>>,
-        test_env_args: Option<Vec<String>>,
-        pull_file_config: Option<PullFileConfig>,
-        concurrency_limit: Option<u32>,
-        test_timeout_default: Option<u32>,
-        test_timeout_max: Option<u32>,
-        project: Option<String>,
-        application_bundle: Option<Vec<ApplicationBundle>>,
-        library_bundle: Option<Vec<PathBuf>>,
-        granted_permission: Option<Vec<String>>,
     ) -> Result<bool> {
-        let client = RapiReqwestClient::new(base_url, api_key);
         let steps = match (wait, output) {
             (true, Some(_)) => 5,
             (true, None) => 2,
@@ -146,124 +109,42 @@ impl TriggerTestRunInteractor {
         };
         let mut formatter = StandardFormatter::new(steps);
 
-        let token = client.get_token().await?;
-
         formatter.stage("Submitting new run...");
-        let id = client
-            .create_run(
-                application,
-                test_application,
-                name,
-                link,
-                branch,
-                platform,
-                os_version,
-                system_image,
-                device,
-                xcode_version,
-                isolated,
-                code_coverage,
-                retry_quota_test_uncompleted,
-                retry_quota_test_preventive,
-                retry_quota_test_reactive,
-                analytics_read_only,
-                profiling,
-                mock_location,
-                filtering_configuration,
-                no_progress_bars,
-                flavor,
-                env_args,
-                test_env_args,
-                pull_file_config,
-                concurrency_limit,
-                test_timeout_default,
-                test_timeout_max,
-                project,
-                application_bundle,
-                library_bundle,
-                granted_permission,
-            )
-            .await?;
+        let id = client.create_run(spec, no_progress_bars).await?;
 
         if wait {
             formatter.stage("Waiting for test run to finish...");
-            let spinner = if !no_progress_bars {
-                let pb = ProgressBar::new_spinner();
-                pb.enable_steady_tick(Duration::from_millis(80));
-                pb.set_style(
-                    ProgressStyle::with_template("{spinner:.blue} {msg}")
-                        .unwrap()
-                        .tick_strings(&["⣾", "⣽", "⣻", "⢿", "⡿", "⣟", "⣯", "⣷"]),
-                );
-                pb.set_message("Test execution in progress...");
-                Some(pb)
-            } else {
-                None
-            };
-            loop {
-                let stat = client.get_run(&id).await?;
-                if stat.completed.is_some() {
-                    if let Some(s) = spinner {
-                        s.finish_and_clear()
-                    }
+            let spinner = execution_spinner(no_progress_bars);
+            let stat = client.wait_for_run(&id, POLL_INTERVAL).await?;
+            if let Some(s) = spinner {
+                s.finish_and_clear()
+            }
 
-                    let base_report_url = Url::parse(base_url)?;
-                    let base_report_url = &base_report_url[..Position::AfterPort];
-
-                    let state = stat.state.clone();
-                    let report = format!("{}/runs/{}/report", base_report_url, id);
-                    let passed = stat.passed;
-                    let failed = stat.failed;
-                    let ignored = stat.ignored;
-                    let billable_time = stat
-                        .total_run_time_seconds
-                        .map(|t| Duration::from_secs_f64(t))
-                        .unwrap_or(Duration::from_secs(0));
-
-                    let event = TestRunFinished {
-                        id: id.clone(),
-                        state,
-                        report,
-                        passed,
-                        failed,
-                        ignored,
-                        billable_time,
-                    };
-                    formatter.message(&format!("{}", event));
-                    if let Some(result_file) = result_file {
-                        let mut file = File::create(&result_file).await?;
-                        let data = serialize_event(&result_file, &event)?;
-                        file.write_all(data.as_bytes()).await?;
-                        file.flush().await?;
-                    }
-                    if let Some(error_message) = stat.error_message {
-                        formatter.message(&format!("Error message:"));
-                        let formatted_error_message = error_message.replace("\n", "\n\t");
-                        formatter.message(&format!("\t{}", formatted_error_message));
-                    }
+            let event = finished_event(client, &stat)?;
+            formatter.message(&format!("{}", event));
+            if let Some(result_file) = result_file {
+                let mut file = File::create(&result_file).await?;
+                let data = serialize_event(&result_file, &event)?;
+                file.write_all(data.as_bytes()).await?;
+                file.flush().await?;
+            }
+            if let Some(error_message) = stat.error_message {
+                formatter.message("Error message:");
+                let formatted_error_message = error_message.replace("\n", "\n\t");
+                formatter.message(&format!("\t{}", formatted_error_message));
+            }
 
-                    if let Some(output) = output {
-                        formatter.stage("Fetching file list...");
-                        let artifacts = fetch_artifact_list(&client, &id, &token).await?;
-                        formatter.stage("Downloading files...");
-                        download_artifacts(
-                            &client,
-                            &id,
-                            artifacts,
-                            output,
-                            &token,
-                            no_progress_bars,
-                        )
-                        .await?;
-                        formatter.stage("Patching local relative paths...");
-                        patch_allure_paths(output).await?;
-                    }
-                    return match (stat.state.as_str(), ignore_test_failures) {
-                        ("failure", Some(false) | None) => Ok(false),
-                        (_, _) => Ok(true),
-                    };
-                }
-                sleep(Duration::new(5, 0)).await;
+            if let Some(output) = output {
+                formatter.stage("Fetching file list...");
+                let artifacts = fetch_artifact_list(client, &id).await?;
+                formatter.stage("Downloading files...");
+                download_artifacts(client, &id, artifacts, output, no_progress_bars).await?;
+                formatter.stage("Patching local relative paths...");
+                patch_allure_paths(output).await?;
+            }
+            match (stat.state.as_str(), ignore_test_failures) {
+                ("failure", Some(false) | None) => Ok(false),
+                (_, _) => Ok(true),
             }
         } else {
             let event = TestRunStarted { id };
@@ -280,6 +161,224 @@ impl TriggerTestRunInteractor {
     }
 }
 
+pub struct TriggerMatrixRunInteractor {}
+
+impl TriggerMatrixRunInteractor {
+    /// Uploads the binaries once and submits a run for every cell of the matrix
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn execute(
+        &self,
+        client: &RapiReqwestClient,
+        runs: Vec<(Cell, RunSpec)>,
+        wait: bool,
+        ignore_test_failures: Option<bool>,
+        output: &Option<PathBuf>,
+        no_progress_bars: bool,
+        result_file: Option<PathBuf>,
+    ) -> Result<bool> {
+        let steps = match (wait, output) {
+            (true, Some(_)) => 6,
+            (true, None) => 3,
+            _ => 2,
+        };
+        let mut formatter = StandardFormatter::new(steps);
+
+        formatter.stage("Uploading binaries...");
+        let Some((_, spec)) = runs.first() else {
+            return Ok(true);
+        };
+        let binaries = client.upload_binaries(spec, no_progress_bars).await?;
+
+        formatter.stage(&format!("Submitting {} runs...", runs.len()));
+        let mut submitted = Vec::with_capacity(runs.len());
+        for (cell, mut spec) in runs {
+            spec.name = Some(match spec.name {
+                Some(name) => format!("{} ({})", name, cell.label()),
+                None => cell.label(),
+            });
+            let id = client.submit_run(spec, &binaries).await?;
+            formatter.message(&format!(
+                "{}: {}",
+                cell.label(),
+                TestRunStarted { id: id.clone() }
+            ));
+            submitted.push((cell, id));
+        }
+
+        if !wait {
+            if let Some(result_file) = result_file {
+                let events: Vec<_> = submitted
+                    .into_iter()
+                    .map(|(cell, id)| MatrixCellEvent {
+                        cell: cell.0.into_iter().collect(),
+                        run: TestRunStarted { id },
+                    })
+                    .collect();
+                write_event(&result_file, &events).await?;
+            }
+            return Ok(true);
+        }
+
+        formatter.stage("Waiting for test runs to finish...");
+        let spinner = execution_spinner(no_progress_bars);
+        // A cell that can't be waited for doesn't discard the results of the others
+        let results = join_all(
+            submitted
+                .iter()
+                .map(|(_, id)| client.wait_for_run(id, POLL_INTERVAL)),
+        )
+        .await;
+        if let Some(s) = spinner {
+            s.finish_and_clear()
+        }
+
+        let mut events = Vec::with_capacity(results.len());
+        for ((cell, id), result) in submitted.iter().zip(&results) {
+            let run = match result {
+                Ok(stat) => finished_event(client, stat)?,
+                Err(_) => errored_event(client, id)?,
+            };
+            events.push(MatrixCellEvent {
+                cell: cell.0.iter().cloned().collect(),
+                run,
+            });
+        }
+        formatter.message(&grid(&submitted, &events));
+        for ((cell, _), result) in submitted.iter().zip(&results) {
+            let error_message = match result {
+                Ok(stat) => stat.error_message.clone(),
+                Err(error) => Some(format!("{:#}", error)),
+            };
+            if let Some(error_message) = error_message {
+                formatter.message(&format!("Error message of {}:", cell.label()));
+                let formatted_error_message = error_message.replace("\n", "\n\t");
+                formatter.message(&format!("\t{}", formatted_error_message));
+            }
+        }
+        if let Some(result_file) = result_file {
+            write_event(&result_file, &events).await?;
+        }
+
+        // Runs that couldn't be waited for have no final artifacts
+        let finished: Vec<_> = submitted
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| result.is_ok())
+            .map(|(cell_and_id, _)| cell_and_id)
+            .collect();
+        if let Some(output) = output {
+            formatter.stage("Fetching file list...");
+            let mut artifacts = Vec::with_capacity(finished.len());
+            for (cell, id) in &finished {
+                artifacts.push((cell, id, fetch_artifact_list(client, id).await?));
+            }
+            formatter.stage("Downloading files...");
+            for (cell, id, artifacts) in artifacts {
+                let output = output.join(cell.dir_name());
+                download_artifacts(client, id, artifacts, &output, no_progress_bars).await?;
+            }
+            formatter.stage("Patching local relative paths...");
+            for (cell, _) in &finished {
+                patch_allure_paths(&output.join(cell.dir_name())).await?;
+            }
+        }
+
+        let errored = results.iter().any(Result::is_err);
+        let failed = results.iter().flatten().any(|stat| stat.state == "failure");
+        Ok(!errored && (!failed || ignore_test_failures == Some(true)))
+    }
+}
+
+/// One row per cell with its dimensions and results
+fn grid(submitted: &[(Cell, String)], events: &[MatrixCellEvent<TestRunFinished>]) -> String {
+    let dimensions: Vec<String> = submitted
+        .first()
+        .map(|(cell, _)| {
+            cell.0
+                .iter()
+                .map(|(dimension, _)| dimension.replace('_', " ").to_uppercase())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut headers: Vec<&str> = dimensions.iter().map(String::as_str).collect();
+    headers.extend(["STATE", "PASSED", "FAILED", "IGNORED", "REPORT"]);
+
+    let count = |value: Option<u32>| value.map(|x| x.to_string()).unwrap_or("-".to_owned());
+    let rows = submitted
+        .iter()
+        .zip(events)
+        .map(|((cell, _), event)| {
+            let mut row: Vec<String> = cell.0.iter().map(|(_, value)| value.clone()).collect();
+            row.extend([
+                event.run.state.clone(),
+                count(event.run.passed),
+                count(event.run.failed),
+                count(event.run.ignored),
+                event.run.report.clone(),
+            ]);
+            row
+        })
+        .collect();
+    table(&headers, rows)
+}
+
+fn execution_spinner(no_progress_bars: bool) -> Option<ProgressBar> {
+    if no_progress_bars {
+        return None;
+    }
+    let pb = ProgressBar::new_spinner();
+    pb.enable_steady_tick(Duration::from_millis(80));
+    pb.set_style(
+        ProgressStyle::with_template("{spinner:.blue} {msg}")
+            .unwrap()
+            .tick_strings(&["⣾", "⣽", "⣻", "⢿", "⡿", "⣟", "⣯", "⣷"]),
+    );
+    pb.set_message("Test execution in progress...");
+    Some(pb)
+}
+
+fn report_url(client: &RapiReqwestClient, id: &str) -> Result<String> {
+    let base_report_url = Url::parse(client.base_url())?;
+    let base_report_url = &base_report_url[..Position::AfterPort];
+    Ok(format!("{}/runs/{}/report", base_report_url, id))
+}
+
+fn finished_event(client: &RapiReqwestClient, stat: &TestRun) -> Result<TestRunFinished> {
+    Ok(TestRunFinished {
+        id: stat.id.clone(),
+        state: stat.state.clone(),
+        report: report_url(client, &stat.id)?,
+        passed: stat.passed,
+        failed: stat.failed,
+        ignored: stat.ignored,
+        billable_time: stat
+            .total_run_time_seconds
+            .map(Duration::from_secs_f64)
+            .unwrap_or(Duration::from_secs(0)),
+    })
+}
+
+/// Stands in for a run whose final state couldn't be fetched
+fn errored_event(client: &RapiReqwestClient, id: &str) -> Result<TestRunFinished> {
+    Ok(TestRunFinished {
+        id: id.to_owned(),
+        state: "error".to_owned(),
+        report: report_url(client, id)?,
+        passed: None,
+        failed: None,
+        ignored: None,
+        billable_time: Duration::from_secs(0),
+    })
+}
+
+async fn write_event<T: Serialize>(result_file: &Path, event: T) -> Result<()> {
+    let mut file = File::create(result_file).await?;
+    let data = serialize_event(result_file, &event)?;
+    file.write_all(data.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
 fn serialize_event<T: Serialize>(path: &Path, event: T) -> Result<String> {
     match path.extension().map(|f| f.to_str()) {
         //If no extension then treat as json
@@ -302,10 +401,10 @@ pub struct GetDeviceCatalogInteractor {}
 impl GetDeviceCatalogInteractor {
     pub(crate) async fn execute(
         &self,
-        base_url: &str,
-        api_key: &str,
-        platform: &Platform,
+        client: &RapiReqwestClient,
+        query: DeviceQuery,
         no_progress_bar: bool,
+        format: OutputFormat,
     ) -> Result<()> {
         let formatter = StandardFormatter::new(1);
 
@@ -319,20 +418,34 @@ impl GetDeviceCatalogInteractor {
             );
             pb.set_message("Fetching device catalog...");
             progress_bar = Some(pb);
-        } else {
+        } else if format == OutputFormat::Table {
             formatter.message("Fetching device catalog...");
         }
-        let client = RapiReqwestClient::new(base_url, api_key);
-
-        let token = client.get_token().await?;
-        let devices = match platform {
-            Platform::Android => client.get_devices_android(&token).await?,
-            Platform::iOS => todo!(),
+        let output = match query {
+            DeviceQuery::Ios => render(&client.get_devices_ios().await?, format)?,
+            DeviceQuery::Android(filter) => {
+                let devices: Vec<_> = client
+                    .get_devices_android()
+                    .await?
+                    .into_iter()
+                    .filter(|device| filter.matches(device))
+                    .collect();
+                render(&devices, format)?
+            }
+            DeviceQuery::AndroidDevice { id } => {
+                let device = client
+                    .get_devices_android()
+                    .await?
+                    .into_iter()
+                    .find(|device| device.id == id)
+                    .ok_or(InputError::UnknownDevice { id })?;
+                render_details(&device, format)?
+            }
         };
         if let Some(progress_bar) = progress_bar {
             progress_bar.finish_and_clear();
         }
-        println!("{}", serde_yaml::to_string(&devices)?);
+        println!("{}", output.trim_end());
         Ok(())
     }
 }
@@ -0,0 +1,316 @@
+// Reads the start elements of Android binary XML, the format aapt uses for
+// AndroidManifest.xml inside APKs. Namespaces, text and styles are ignored.
+
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_XML_TYPE: u16 = 0x0003;
+const RES_XML_RESOURCE_MAP_TYPE: u16 = 0x0180;
+const RES_XML_START_ELEMENT_TYPE: u16 = 0x0102;
+const UTF8_FLAG: u32 = 1 << 8;
+const NO_ENTRY: u32 = 0xffffffff;
+const ATTRIBUTE_SIZE: usize = 20;
+
+const TYPE_STRING: u8 = 0x03;
+const TYPE_INT_DEC: u8 = 0x10;
+const TYPE_INT_HEX: u8 = 0x11;
+
+#[derive(Debug)]
+pub(crate) struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<XmlAttribute>,
+}
+
+#[derive(Debug)]
+pub(crate) struct XmlAttribute {
+    pub name: String,
+    pub value: AttributeValue,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum AttributeValue {
+    String(String),
+    Int(u32),
+    /// References, booleans, dimensions etc.
+    Other,
+}
+
+/// Shrunk manifests can drop the names of framework attributes but keep their resource ids
+const FRAMEWORK_ATTRIBUTES: [(u32, &str); 4] = [
+    (0x01010003, "name"),
+    (0x01010021, "targetPackage"),
+    (0x0101020c, "minSdkVersion"),
+    (0x01010270, "targetSdkVersion"),
+];
+
+impl XmlElement {
+    pub fn attribute(&self, name: &str) -> Option<&AttributeValue> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+            .map(|attribute| &attribute.value)
+    }
+
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.attribute(name)? {
+            AttributeValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+struct Xml<'a> {
+    data: &'a [u8],
+    strings: Vec<String>,
+    resource_ids: Vec<u32>,
+}
+
+pub(crate) fn parse(data: &[u8]) -> Result<Vec<XmlElement>, String> {
+    let mut xml = Xml {
+        data,
+        strings: vec![],
+        resource_ids: vec![],
+    };
+    if xml.u16(0)? != RES_XML_TYPE {
+        return Err("missing binary xml header".into());
+    }
+    let header_size = xml.u16(2)? as usize;
+    let size = xml.u32(4)? as usize;
+    if size > data.len() {
+        return Err(format!(
+            "truncated file of {} bytes instead of {}",
+            data.len(),
+            size
+        ));
+    }
+
+    let mut elements = vec![];
+    let mut offset = header_size;
+    while offset + 8 <= size {
+        let chunk_type = xml.u16(offset)?;
+        let chunk_header_size = xml.u16(offset + 2)? as usize;
+        let chunk_size = xml.u32(offset + 4)? as usize;
+        if chunk_size < 8 || chunk_size > size - offset {
+            return Err(format!("invalid chunk size at {:#x}", offset));
+        }
+        match chunk_type {
+            RES_STRING_POOL_TYPE => xml.strings = xml.string_pool(offset)?,
+            RES_XML_RESOURCE_MAP_TYPE => {
+                xml.resource_ids = (offset + chunk_header_size..offset + chunk_size)
+                    .step_by(4)
+                    .map(|at| xml.u32(at))
+                    .collect::<Result<_, _>>()?;
+            }
+            RES_XML_START_ELEMENT_TYPE => {
+                elements.push(xml.start_element(offset + chunk_header_size)?)
+            }
+            _ => {}
+        }
+        offset += chunk_size;
+    }
+    Ok(elements)
+}
+
+impl Xml<'_> {
+    fn u8(&self, offset: usize) -> Result<u8, String> {
+        self.data
+            .get(offset)
+            .copied()
+            .ok_or_else(|| format!("unexpected end of file at {:#x}", offset))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, String> {
+        let bytes = self
+            .data
+            .get(offset..offset.saturating_add(2))
+            .ok_or_else(|| format!("unexpected end of file at {:#x}", offset))?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, String> {
+        let bytes = self
+            .data
+            .get(offset..offset.saturating_add(4))
+            .ok_or_else(|| format!("unexpected end of file at {:#x}", offset))?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Checks that `count` entries of `size` bytes fit after `offset`
+    fn table(&self, offset: usize, count: usize, size: usize) -> Result<(), String> {
+        count
+            .checked_mul(size)
+            .and_then(|len| offset.checked_add(len))
+            .filter(|&end| end <= self.data.len())
+            .map(|_| ())
+            .ok_or_else(|| format!("{} entries at {:#x} exceed the file", count, offset))
+    }
+
+    fn string_pool(&self, chunk: usize) -> Result<Vec<String>, String> {
+        let header_size = self.u16(chunk + 2)? as usize;
+        let count = self.u32(chunk + 8)? as usize;
+        let flags = self.u32(chunk + 16)?;
+        let strings_start = chunk + self.u32(chunk + 20)? as usize;
+        self.table(chunk + header_size, count, 4)?;
+
+        let mut strings = Vec::with_capacity(count);
+        for index in 0..count {
+            let offset = self.u32(chunk + header_size + index * 4)? as usize;
+            let offset = strings_start
+                .checked_add(offset)
+                .ok_or_else(|| format!("string {} is out of bounds", index))?;
+            let string = if flags & UTF8_FLAG != 0 {
+                self.utf8_string(offset)?
+            } else {
+                self.utf16_string(offset)?
+            };
+            strings.push(string);
+        }
+        Ok(strings)
+    }
+
+    fn utf8_string(&self, offset: usize) -> Result<String, String> {
+        // Length in UTF-16 code units followed by the length in bytes
+        let (_, skip) = self.utf8_length(offset)?;
+        let (length, skip_length) = self.utf8_length(offset + skip)?;
+        let offset = offset + skip + skip_length;
+        let bytes = self
+            .data
+            .get(offset..offset.saturating_add(length))
+            .ok_or_else(|| format!("unexpected end of file at {:#x}", offset))?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    /// One or two bytes, the high bit of the first one marks the long form
+    fn utf8_length(&self, offset: usize) -> Result<(usize, usize), String> {
+        let first = self.u8(offset)? as usize;
+        if first & 0x80 != 0 {
+            Ok((((first & 0x7f) << 8) | self.u8(offset + 1)? as usize, 2))
+        } else {
+            Ok((first, 1))
+        }
+    }
+
+    fn utf16_string(&self, offset: usize) -> Result<String, String> {
+        let mut length = self.u16(offset)? as usize;
+        let mut offset = offset + 2;
+        if length & 0x8000 != 0 {
+            length = ((length & 0x7fff) << 16) | self.u16(offset)? as usize;
+            offset += 2;
+        }
+        self.table(offset, length, 2)?;
+        let units = (0..length)
+            .map(|index| self.u16(offset + index * 2))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(String::from_utf16_lossy(&units))
+    }
+
+    fn string(&self, index: u32) -> Result<String, String> {
+        self.strings
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| format!("string index {} out of bounds", index))
+    }
+
+    fn start_element(&self, offset: usize) -> Result<XmlElement, String> {
+        let name = self.string(self.u32(offset + 4)?)?;
+        let attribute_start = self.u16(offset + 8)? as usize;
+        let attribute_size = (self.u16(offset + 10)? as usize).max(ATTRIBUTE_SIZE);
+        let attribute_count = self.u16(offset + 12)? as usize;
+        self.table(offset + attribute_start, attribute_count, attribute_size)?;
+
+        let mut attributes = Vec::with_capacity(attribute_count);
+        for index in 0..attribute_count {
+            let at = offset + attribute_start + index * attribute_size;
+            let name_index = self.u32(at + 4)?;
+            let raw_value = self.u32(at + 8)?;
+            let data_type = self.u8(at + 15)?;
+            let data = self.u32(at + 16)?;
+            let value = match data_type {
+                TYPE_STRING => AttributeValue::String(self.string(data)?),
+                TYPE_INT_DEC | TYPE_INT_HEX => AttributeValue::Int(data),
+                // Values that were kept as the original text
+                _ if raw_value != NO_ENTRY => AttributeValue::String(self.string(raw_value)?),
+                _ => AttributeValue::Other,
+            };
+            let mut name = self.string(name_index)?;
+            if name.is_empty() {
+                let resource_id = self.resource_ids.get(name_index as usize);
+                if let Some((_, framework_name)) = FRAMEWORK_ATTRIBUTES
+                    .iter()
+                    .find(|(id, _)| Some(id) == resource_id)
+                {
+                    name = (*framework_name).to_owned();
+                }
+            }
+            attributes.push(XmlAttribute { name, value });
+        }
+        Ok(XmlElement { name, attributes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_not_binary_xml() {
+        assert!(parse(b"<manifest/>").is_err());
+        assert!(parse(&[]).is_err());
+    }
+
+    /// Header, string pool with "manifest" and a start element without attributes
+    fn manifest() -> Vec<u8> {
+        let mut pool = vec![];
+        pool.extend(1u16.to_le_bytes());
+        pool.extend(28u16.to_le_bytes());
+        pool.extend(44u32.to_le_bytes());
+        pool.extend(1u32.to_le_bytes());
+        pool.extend(0u32.to_le_bytes());
+        pool.extend(UTF8_FLAG.to_le_bytes());
+        pool.extend(32u32.to_le_bytes());
+        pool.extend(0u32.to_le_bytes());
+        pool.extend(0u32.to_le_bytes());
+        pool.extend([8, 8]);
+        pool.extend(b"manifest\0\0");
+        let mut element = vec![];
+        element.extend(RES_XML_START_ELEMENT_TYPE.to_le_bytes());
+        element.extend(16u16.to_le_bytes());
+        element.extend(36u32.to_le_bytes());
+        element.extend([0; 8]);
+        element.extend(NO_ENTRY.to_le_bytes());
+        element.extend(0u32.to_le_bytes());
+        element.extend(20u16.to_le_bytes());
+        element.extend(20u16.to_le_bytes());
+        element.extend([0; 8]);
+        let mut data = vec![];
+        data.extend(RES_XML_TYPE.to_le_bytes());
+        data.extend(8u16.to_le_bytes());
+        data.extend(((8 + pool.len() + element.len()) as u32).to_le_bytes());
+        data.extend(pool);
+        data.extend(element);
+        data
+    }
+
+    #[test]
+    fn test_utf8_string_pool() {
+        let elements = parse(&manifest()).unwrap();
+
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].name, "manifest");
+    }
+
+    #[test]
+    fn test_truncated_manifest() {
+        let data = manifest();
+        for len in [4, 8, 20, data.len() - 1] {
+            assert!(parse(&data[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn test_oversized_string_pool() {
+        let mut data = manifest();
+        // String count of the pool chunk following the 8 byte file header
+        data[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        let error = parse(&data).unwrap_err();
+        assert!(error.contains("exceed the file"), "{}", error);
+    }
+}
@@ -0,0 +1,258 @@
+// Reads just enough of https://source.android.com/docs/core/runtime/dex-format
+// to find annotated classes and methods. Code items are never touched.
+
+const HEADER_SIZE: usize = 0x70;
+const ENDIAN_CONSTANT: u32 = 0x12345678;
+const NO_INDEX: u32 = 0xffffffff;
+const ACC_INTERFACE: u32 = 0x200;
+const ACC_ABSTRACT: u32 = 0x400;
+const VISIBILITY_SYSTEM: u8 = 0x02;
+
+#[derive(Debug)]
+pub(crate) struct DexClass {
+    pub name: String,
+    pub superclass: Option<String>,
+    pub is_abstract: bool,
+    pub annotations: Vec<String>,
+    /// Only methods carrying at least one annotation are listed
+    pub methods: Vec<DexMethod>,
+}
+
+#[derive(Debug)]
+pub(crate) struct DexMethod {
+    pub name: String,
+    pub annotations: Vec<String>,
+}
+
+struct Dex<'a> {
+    data: &'a [u8],
+    string_ids_size: u32,
+    string_ids_off: usize,
+    type_ids_size: u32,
+    type_ids_off: usize,
+    method_ids_size: u32,
+    method_ids_off: usize,
+}
+
+pub(crate) fn parse(data: &[u8]) -> Result<Vec<DexClass>, String> {
+    if data.len() < HEADER_SIZE || &data[0..4] != b"dex\n" {
+        return Err("missing dex magic".into());
+    }
+    let mut dex = Dex {
+        data,
+        string_ids_size: 0,
+        string_ids_off: 0,
+        type_ids_size: 0,
+        type_ids_off: 0,
+        method_ids_size: 0,
+        method_ids_off: 0,
+    };
+    if dex.u32(0x28)? != ENDIAN_CONSTANT {
+        return Err("unsupported endianness".into());
+    }
+    dex.string_ids_size = dex.u32(0x38)?;
+    dex.string_ids_off = dex.u32(0x3c)? as usize;
+    dex.type_ids_size = dex.u32(0x40)?;
+    dex.type_ids_off = dex.u32(0x44)? as usize;
+    dex.method_ids_size = dex.u32(0x58)?;
+    dex.method_ids_off = dex.u32(0x5c)? as usize;
+    let class_defs_size = dex.u32(0x60)? as usize;
+    let class_defs_off = dex.u32(0x64)? as usize;
+    // Entries are indexed without further checks once their table is known to fit
+    dex.table(dex.string_ids_off, dex.string_ids_size as usize, 4)?;
+    dex.table(dex.type_ids_off, dex.type_ids_size as usize, 4)?;
+    dex.table(dex.method_ids_off, dex.method_ids_size as usize, 8)?;
+    dex.table(class_defs_off, class_defs_size, 32)?;
+
+    let mut classes = Vec::with_capacity(class_defs_size);
+    for index in 0..class_defs_size {
+        let offset = class_defs_off + index * 32;
+        let access_flags = dex.u32(offset + 4)?;
+        if access_flags & ACC_INTERFACE != 0 {
+            continue;
+        }
+        let superclass_idx = dex.u32(offset + 8)?;
+        let annotations_off = dex.u32(offset + 20)? as usize;
+
+        let mut class = DexClass {
+            name: dex.type_name(dex.u32(offset)?)?,
+            superclass: match superclass_idx {
+                NO_INDEX => None,
+                idx => Some(dex.type_name(idx)?),
+            },
+            is_abstract: access_flags & ACC_ABSTRACT != 0,
+            annotations: vec![],
+            methods: vec![],
+        };
+
+        if annotations_off != 0 {
+            class.annotations = dex.annotation_set(dex.u32(annotations_off)? as usize)?;
+            let fields_size = dex.u32(annotations_off + 4)? as usize;
+            let methods_size = dex.u32(annotations_off + 8)? as usize;
+            let fields_off = annotations_off + 16;
+            let methods_off = dex.table(fields_off, fields_size, 8)?;
+            dex.table(methods_off, methods_size, 8)?;
+            for method in 0..methods_size {
+                let entry = methods_off + method * 8;
+                class.methods.push(DexMethod {
+                    name: dex.method_name(dex.u32(entry)?)?,
+                    annotations: dex.annotation_set(dex.u32(entry + 4)? as usize)?,
+                });
+            }
+        }
+        classes.push(class);
+    }
+    Ok(classes)
+}
+
+impl<'a> Dex<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], String> {
+        self.data
+            .get(offset..offset.saturating_add(len))
+            .ok_or_else(|| format!("offset {:#x} is out of bounds", offset))
+    }
+
+    /// Checks that `count` entries of `size` bytes fit after `offset`, returns the end offset
+    fn table(&self, offset: usize, count: usize, size: usize) -> Result<usize, String> {
+        count
+            .checked_mul(size)
+            .and_then(|len| offset.checked_add(len))
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| {
+                format!(
+                    "table of {} entries at offset {:#x} is out of bounds",
+                    count, offset
+                )
+            })
+    }
+
+    fn u8(&self, offset: usize) -> Result<u8, String> {
+        Ok(self.bytes(offset, 1)?[0])
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, String> {
+        let bytes = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn uleb128(&self, offset: &mut usize) -> Result<u32, String> {
+        let mut result = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.u8(*offset)?;
+            *offset += 1;
+            result |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err("malformed uleb128 value".into())
+    }
+
+    fn string(&self, idx: u32) -> Result<String, String> {
+        if idx >= self.string_ids_size {
+            return Err(format!("string index {} is out of bounds", idx));
+        }
+        let mut offset = self.u32(self.string_ids_off + idx as usize * 4)? as usize;
+        // utf16 length, not needed since the data is zero terminated
+        self.uleb128(&mut offset)?;
+        let tail = self.data.get(offset..).unwrap_or_default();
+        let end = tail
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or("unterminated string")?;
+        // MUTF-8 only differs from UTF-8 for NUL and supplementary characters
+        Ok(String::from_utf8_lossy(&tail[..end]).into_owned())
+    }
+
+    fn type_name(&self, idx: u32) -> Result<String, String> {
+        if idx >= self.type_ids_size {
+            return Err(format!("type index {} is out of bounds", idx));
+        }
+        let descriptor = self.string(self.u32(self.type_ids_off + idx as usize * 4)?)?;
+        Ok(descriptor_to_name(&descriptor))
+    }
+
+    fn method_name(&self, idx: u32) -> Result<String, String> {
+        if idx >= self.method_ids_size {
+            return Err(format!("method index {} is out of bounds", idx));
+        }
+        // class_idx (u16) and proto_idx (u16) precede the name
+        let offset = self.method_ids_off + idx as usize * 8 + 4;
+        self.string(self.u32(offset)?)
+    }
+
+    /// Type names of the build and runtime annotations in the set
+    fn annotation_set(&self, offset: usize) -> Result<Vec<String>, String> {
+        if offset == 0 {
+            return Ok(vec![]);
+        }
+        let size = self.u32(offset)? as usize;
+        self.table(offset + 4, size, 4)?;
+        let mut annotations = Vec::with_capacity(size);
+        for entry in 0..size {
+            let mut item = self.u32(offset + 4 + entry * 4)? as usize;
+            let visibility = self.u8(item)?;
+            item += 1;
+            let type_idx = self.uleb128(&mut item)?;
+            if visibility != VISIBILITY_SYSTEM {
+                annotations.push(self.type_name(type_idx)?);
+            }
+        }
+        Ok(annotations)
+    }
+}
+
+/// `Lcom/example/Foo$Bar;` -> `com.example.Foo$Bar`
+fn descriptor_to_name(descriptor: &str) -> String {
+    descriptor
+        .strip_prefix('L')
+        .and_then(|d| d.strip_suffix(';'))
+        .unwrap_or(descriptor)
+        .replace('/', ".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor_to_name() {
+        assert_eq!(
+            descriptor_to_name("Lcom/example/Foo$Bar;"),
+            "com.example.Foo$Bar"
+        );
+        assert_eq!(descriptor_to_name("I"), "I");
+    }
+
+    #[test]
+    fn test_invalid_magic() {
+        assert!(parse(b"not a dex file").is_err());
+        assert!(parse(&[0u8; HEADER_SIZE]).is_err());
+    }
+
+    fn header() -> Vec<u8> {
+        let mut data = vec![0u8; HEADER_SIZE];
+        data[0..8].copy_from_slice(b"dex\n035\0");
+        data[0x28..0x2c].copy_from_slice(&ENDIAN_CONSTANT.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_empty_dex() {
+        assert!(parse(&header()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_oversized_tables() {
+        let mut data = header();
+        data[0x60..0x64].copy_from_slice(&u32::MAX.to_le_bytes());
+        data[0x64..0x68].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        let error = parse(&data).unwrap_err();
+        assert!(error.contains("out of bounds"), "{}", error);
+
+        let mut data = header();
+        data[0x38..0x3c].copy_from_slice(&0x4000_0000u32.to_le_bytes());
+        data[0x3c..0x40].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse(&data).is_err());
+    }
+}
@@ -0,0 +1,378 @@
+// Reads the Objective-C class list of a 64-bit Mach-O image, see objc4's objc-runtime-new.h.
+// Swift test cases are visible there as well since XCTestCase is an Objective-C class.
+
+const FAT_MAGIC: u32 = 0xcafebabe;
+const FAT_MAGIC_64: u32 = 0xcafebabf;
+const MH_MAGIC_64: u32 = 0xfeedfacf;
+const CPU_TYPE_ARM64: u32 = 0x0100000c;
+const LC_SEGMENT_64: u32 = 0x19;
+
+// Chained fixups keep the target in the low 36 bits and flag binds with the top bit
+const POINTER_BIND: u64 = 1 << 63;
+const POINTER_TARGET_MASK: u64 = 0xf_ffff_ffff;
+// Low bits of class_t::data are Swift flags
+const CLASS_DATA_MASK: u64 = !7;
+const METHOD_LIST_SMALL: u32 = 0x80000000;
+const METHOD_LIST_DIRECT_SELECTORS: u32 = 0x40000000;
+const METHOD_LIST_ENTSIZE_MASK: u32 = 0xfffc;
+
+#[derive(Debug)]
+pub(crate) struct ObjcClass {
+    pub address: u64,
+    pub name: String,
+    /// Address of the superclass when it's defined in the same image
+    pub superclass: Option<u64>,
+    pub methods: Vec<ObjcMethod>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ObjcMethod {
+    pub selector: String,
+    pub types: String,
+}
+
+struct Segment {
+    vmaddr: u64,
+    fileoff: u64,
+    filesize: u64,
+}
+
+struct Image<'a> {
+    data: &'a [u8],
+    base: u64,
+    segments: Vec<Segment>,
+}
+
+pub(crate) fn parse(data: &[u8]) -> Result<Vec<ObjcClass>, String> {
+    let image = Image::load(thin_slice(data)?)?;
+    let Some((classlist, size)) = image.section("__objc_classlist")? else {
+        return Ok(vec![]);
+    };
+
+    let mut classes = vec![];
+    for entry in (0..size).step_by(8) {
+        let Some(address) = image.pointer(field(classlist, entry)?)? else {
+            continue;
+        };
+        let superclass = image.pointer(field(address, 8)?)?;
+        let ro = image
+            .pointer(field(address, 32)?)?
+            .ok_or("class without data")?
+            & CLASS_DATA_MASK;
+        let name = image.c_string(image.pointer(field(ro, 24)?)?.ok_or("class without name")?)?;
+        let methods = match image.pointer(field(ro, 32)?)? {
+            Some(list) => image.method_list(list)?,
+            None => vec![],
+        };
+        classes.push(ObjcClass {
+            address,
+            name,
+            superclass,
+            methods,
+        });
+    }
+    Ok(classes)
+}
+
+/// Picks the arm64 slice of a universal binary, or the first one if there's none
+fn thin_slice(data: &[u8]) -> Result<&[u8], String> {
+    let magic = read_u32_be(data, 0)?;
+    if magic != FAT_MAGIC && magic != FAT_MAGIC_64 {
+        return Ok(data);
+    }
+    let count = read_u32_be(data, 4)? as usize;
+    let arch_size = if magic == FAT_MAGIC { 20 } else { 32 };
+    let mut slices = vec![];
+    for index in 0..count {
+        let arch = 8 + index * arch_size;
+        let cputype = read_u32_be(data, arch)?;
+        let (offset, size) = if magic == FAT_MAGIC {
+            (
+                read_u32_be(data, arch + 8)? as usize,
+                read_u32_be(data, arch + 12)? as usize,
+            )
+        } else {
+            (
+                read_u64_be(data, arch + 8)? as usize,
+                read_u64_be(data, arch + 16)? as usize,
+            )
+        };
+        slices.push((cputype, offset, size));
+    }
+    let (_, offset, size) = slices
+        .iter()
+        .find(|(cputype, _, _)| *cputype == CPU_TYPE_ARM64)
+        .or(slices.first())
+        .ok_or("empty universal binary")?;
+    data.get(*offset..offset.saturating_add(*size))
+        .ok_or_else(|| "universal binary slice is out of bounds".to_owned())
+}
+
+impl<'a> Image<'a> {
+    fn load(data: &'a [u8]) -> Result<Image<'a>, String> {
+        if read_u32_le(data, 0)? != MH_MAGIC_64 {
+            return Err("not a 64-bit Mach-O image".into());
+        }
+        let ncmds = read_u32_le(data, 16)?;
+        let mut image = Image {
+            data,
+            base: 0,
+            segments: vec![],
+        };
+        let mut command = 32;
+        for _ in 0..ncmds {
+            let cmd = read_u32_le(data, command)?;
+            let cmdsize = read_u32_le(data, command + 4)? as usize;
+            if cmd == LC_SEGMENT_64 {
+                let segment = Segment {
+                    vmaddr: read_u64_le(data, command + 24)?,
+                    fileoff: read_u64_le(data, command + 40)?,
+                    filesize: read_u64_le(data, command + 48)?,
+                };
+                if segment.fileoff == 0 && segment.filesize > 0 {
+                    image.base = segment.vmaddr;
+                }
+                image.segments.push(segment);
+            }
+            if cmdsize == 0 {
+                return Err("malformed load command".into());
+            }
+            command += cmdsize;
+        }
+        Ok(image)
+    }
+
+    /// Address and size of the first section with the given name in any segment
+    fn section(&self, name: &str) -> Result<Option<(u64, u64)>, String> {
+        let ncmds = read_u32_le(self.data, 16)?;
+        let mut command = 32;
+        for _ in 0..ncmds {
+            let cmd = read_u32_le(self.data, command)?;
+            if cmd == LC_SEGMENT_64 {
+                let nsects = read_u32_le(self.data, command + 64)? as usize;
+                for index in 0..nsects {
+                    let section = command + 72 + index * 80;
+                    let sectname = self.bytes(section, 16)?;
+                    let end = sectname.iter().position(|&b| b == 0).unwrap_or(16);
+                    if &sectname[..end] == name.as_bytes() {
+                        return Ok(Some((
+                            read_u64_le(self.data, section + 32)?,
+                            read_u64_le(self.data, section + 40)?,
+                        )));
+                    }
+                }
+            }
+            command += read_u32_le(self.data, command + 4)? as usize;
+        }
+        Ok(None)
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], String> {
+        self.data
+            .get(offset..offset.saturating_add(len))
+            .ok_or_else(|| format!("offset {:#x} is out of bounds", offset))
+    }
+
+    fn offset(&self, address: u64) -> Result<usize, String> {
+        self.segments
+            .iter()
+            .find(|s| address >= s.vmaddr && address - s.vmaddr < s.filesize)
+            .and_then(|s| s.fileoff.checked_add(address - s.vmaddr))
+            .and_then(|offset| usize::try_from(offset).ok())
+            .ok_or_else(|| format!("address {:#x} is not mapped", address))
+    }
+
+    /// Resolves a pointer stored at the address, binds to other images resolve to None
+    fn pointer(&self, address: u64) -> Result<Option<u64>, String> {
+        let raw = read_u64_le(self.data, self.offset(address)?)?;
+        if raw == 0 || raw & POINTER_BIND != 0 {
+            return Ok(None);
+        }
+        let target = raw & POINTER_TARGET_MASK;
+        // DYLD_CHAINED_PTR_64_OFFSET stores the target relative to the image base
+        if target < self.base {
+            target
+                .checked_add(self.base)
+                .map(Some)
+                .ok_or_else(|| format!("pointer {:#x} is out of range", raw))
+        } else {
+            Ok(Some(target))
+        }
+    }
+
+    fn c_string(&self, address: u64) -> Result<String, String> {
+        let tail = self.data.get(self.offset(address)?..).unwrap_or_default();
+        let end = tail
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or("unterminated string")?;
+        Ok(String::from_utf8_lossy(&tail[..end]).into_owned())
+    }
+
+    fn relative(&self, address: u64) -> Result<u64, String> {
+        let offset = read_u32_le(self.data, self.offset(address)?)? as i32;
+        Ok(address.wrapping_add_signed(offset as i64))
+    }
+
+    fn method_list(&self, address: u64) -> Result<Vec<ObjcMethod>, String> {
+        let offset = self.offset(address)?;
+        let flags = read_u32_le(self.data, offset)?;
+        let count = read_u32_le(self.data, offset + 4)? as u64;
+        let entsize = (flags & METHOD_LIST_ENTSIZE_MASK) as u64;
+
+        // The count is untrusted, the list has to fit in the segment it starts in
+        count
+            .checked_mul(entsize)
+            .and_then(|len| len.checked_add(7))
+            .and_then(|len| field(address, len).ok())
+            .filter(|&last| (entsize > 0 || count == 0) && self.offset(last).is_ok())
+            .ok_or_else(|| format!("method list at {:#x} is out of bounds", address))?;
+
+        let mut methods =
+            Vec::with_capacity(count.min(self.data.len() as u64 / entsize.max(1)) as usize);
+        for index in 0..count {
+            let entry = address + 8 + index * entsize;
+            let method = if flags & METHOD_LIST_SMALL != 0 {
+                // Relative offsets to a selector reference and the type encoding
+                let name = self.relative(entry)?;
+                let selector = if flags & METHOD_LIST_DIRECT_SELECTORS != 0 {
+                    name
+                } else {
+                    self.pointer(name)?.ok_or("unresolved selector")?
+                };
+                ObjcMethod {
+                    selector: self.c_string(selector)?,
+                    types: self.c_string(self.relative(entry + 4)?)?,
+                }
+            } else {
+                ObjcMethod {
+                    selector: self.c_string(self.pointer(entry)?.ok_or("unresolved selector")?)?,
+                    types: match self.pointer(entry + 8)? {
+                        Some(types) => self.c_string(types)?,
+                        None => String::new(),
+                    },
+                }
+            };
+            methods.push(method);
+        }
+        Ok(methods)
+    }
+}
+
+/// Address of a field of the structure at `address`
+fn field(address: u64, offset: u64) -> Result<u64, String> {
+    address
+        .checked_add(offset)
+        .ok_or_else(|| format!("address {:#x} is out of range", address))
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Result<u32, String> {
+    read::<4>(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64_le(data: &[u8], offset: usize) -> Result<u64, String> {
+    read::<8>(data, offset).map(u64::from_le_bytes)
+}
+
+fn read_u32_be(data: &[u8], offset: usize) -> Result<u32, String> {
+    read::<4>(data, offset).map(u32::from_be_bytes)
+}
+
+fn read_u64_be(data: &[u8], offset: usize) -> Result<u64, String> {
+    read::<8>(data, offset).map(u64::from_be_bytes)
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N], String> {
+    data.get(offset..offset.saturating_add(N))
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("offset {:#x} is out of bounds", offset))
+}
+
+/// Splits `_TtC8AppTests10UploadTest` into module and class, other names are returned as is
+pub(crate) fn demangle_class_name(name: &str) -> (Option<String>, String) {
+    let parse = || {
+        let mut rest = name.strip_prefix("_TtC")?;
+        let mut parts = vec![];
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+            let len: usize = rest[..digits].parse().ok()?;
+            parts.push(rest.get(digits..digits + len)?.to_owned());
+            rest = &rest[digits + len..];
+        }
+        match parts.as_slice() {
+            [module, class] => Some((Some(module.clone()), class.clone())),
+            _ => None,
+        }
+    };
+    parse().unwrap_or((None, name.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demangle_class_name() {
+        assert_eq!(
+            demangle_class_name("_TtC8AppTests10UploadTest"),
+            (Some("AppTests".to_owned()), "UploadTest".to_owned())
+        );
+        assert_eq!(
+            demangle_class_name("LoginTests"),
+            (None, "LoginTests".to_owned())
+        );
+        assert_eq!(
+            demangle_class_name("_TtC8AppTests99Broken"),
+            (None, "_TtC8AppTests99Broken".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_not_macho() {
+        assert!(parse(b"not a mach-o image").is_err());
+    }
+
+    fn mapped(data: &[u8], vmaddr: u64, filesize: u64) -> Image<'_> {
+        Image {
+            data,
+            base: vmaddr,
+            segments: vec![Segment {
+                vmaddr,
+                fileoff: 0,
+                filesize,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_unmapped_address() {
+        let data = [0u8; 16];
+        let image = mapped(&data, u64::MAX - 8, u64::MAX);
+        assert!(image.offset(u64::MAX).is_ok());
+        assert!(image.offset(0).is_err());
+        assert!(image.pointer(u64::MAX - 8).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pointer_out_of_range() {
+        let mut data = [0u8; 16];
+        data[..8].copy_from_slice(&1u64.to_le_bytes());
+        let image = mapped(&data, u64::MAX, 1);
+        assert!(image.pointer(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_oversized_method_list() {
+        let mut data = vec![0u8; 64];
+        data[..4].copy_from_slice(&(METHOD_LIST_SMALL | 12).to_le_bytes());
+        data[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let image = mapped(&data, 0x1000, data.len() as u64);
+        let error = image.method_list(0x1000).unwrap_err();
+        assert!(error.contains("out of bounds"), "{}", error);
+
+        // Entries without a size would be read over and over
+        data[..4].copy_from_slice(&METHOD_LIST_SMALL.to_le_bytes());
+        let image = mapped(&data, 0x1000, data.len() as u64);
+        assert!(image.method_list(0x1000).is_err());
+    }
+}
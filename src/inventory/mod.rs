@@ -0,0 +1,596 @@
+//! Local test discovery and manifest inspection for test applications, without a device or simulator.
+mod axml;
+mod dex;
+mod macho;
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use async_zip::tokio::read::fs::ZipFileReader;
+use regex::Regex;
+use serde::Deserialize;
+use tokio::fs;
+
+use crate::{errors::InventoryError, filtering::evaluate::TestCase};
+
+const JUNIT4_TEST: &str = "org.junit.Test";
+const ANDROID_MANIFEST: &str = "AndroidManifest.xml";
+const INFO_PLIST: &str = "Info.plist";
+const XCTEST_PREFIX: &str = "test";
+
+/// Lists JUnit4 tests of an Android test APK, including the ones inherited from abstract base classes.
+/// Each test carries the annotations of its class followed by the annotations of the method.
+pub async fn android_tests(apk: &Path) -> Result<Vec<TestCase>> {
+    let invalid_archive = |error| InventoryError::InvalidArchive {
+        path: apk.to_path_buf(),
+        error,
+    };
+    let reader = ZipFileReader::new(apk).await.map_err(invalid_archive)?;
+    let dex_name = Regex::new(r"^classes\d*\.dex$")?;
+
+    let mut classes = HashMap::new();
+    let mut dex_count = 0;
+    for (index, entry) in reader.file().entries().iter().enumerate() {
+        let name = entry.filename().as_str().map_err(invalid_archive)?;
+        if !dex_name.is_match(name) {
+            continue;
+        }
+        let name = name.to_owned();
+        let mut buffer = Vec::new();
+        reader
+            .reader_with_entry(index)
+            .await
+            .map_err(invalid_archive)?
+            .read_to_end_checked(&mut buffer)
+            .await
+            .map_err(invalid_archive)?;
+        let parsed = dex::parse(&buffer).map_err(|message| InventoryError::InvalidDex {
+            path: apk.to_path_buf(),
+            entry: name,
+            message,
+        })?;
+        classes.extend(parsed.into_iter().map(|class| (class.name.clone(), class)));
+        dex_count += 1;
+    }
+    if dex_count == 0 {
+        anyhow::bail!(InventoryError::MissingDex {
+            path: apk.to_path_buf()
+        });
+    }
+
+    Ok(junit_tests(&classes))
+}
+
+/// JUnit4 tests of the concrete classes, including the ones inherited from superclasses
+fn junit_tests(classes: &HashMap<String, dex::DexClass>) -> Vec<TestCase> {
+    let mut tests = vec![];
+    for class in classes.values().filter(|class| !class.is_abstract) {
+        let (package, simple_name) = class.name.rsplit_once('.').unwrap_or(("", &class.name));
+        let mut seen = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut current = Some(class);
+        // A malformed dex can declare a class as its own ancestor
+        while let Some(declaring) = current.filter(|c| visited.insert(c.name.as_str())) {
+            for method in &declaring.methods {
+                // Overrides in subclasses shadow the parent declaration
+                if !seen.insert(method.name.as_str())
+                    || !method.annotations.iter().any(|a| a == JUNIT4_TEST)
+                {
+                    continue;
+                }
+                let mut annotations = class.annotations.clone();
+                for annotation in &method.annotations {
+                    if !annotations.contains(annotation) {
+                        annotations.push(annotation.clone());
+                    }
+                }
+                tests.push(TestCase {
+                    package: package.to_owned(),
+                    class: simple_name.to_owned(),
+                    method: method.name.clone(),
+                    annotations,
+                });
+            }
+            current = declaring
+                .superclass
+                .as_ref()
+                .and_then(|superclass| classes.get(superclass));
+        }
+    }
+    tests.sort_by_key(|test| test.fully_qualified_test_name());
+    tests
+}
+
+/// Parts of the AndroidManifest.xml of an APK that decide whether a run can work
+#[derive(Debug, Default, PartialEq)]
+pub struct AndroidManifest {
+    pub package: String,
+    /// `None` for preview SDK codenames
+    pub min_sdk_version: Option<u32>,
+    pub instrumentations: Vec<Instrumentation>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Instrumentation {
+    /// Class name of the runner, e.g. `androidx.test.runner.AndroidJUnitRunner`
+    pub name: String,
+    pub target_package: String,
+}
+
+/// Reads the binary AndroidManifest.xml of an APK
+pub async fn android_manifest(apk: &Path) -> Result<AndroidManifest> {
+    let invalid_archive = |error| InventoryError::InvalidArchive {
+        path: apk.to_path_buf(),
+        error,
+    };
+    let invalid_manifest = |message| InventoryError::InvalidManifest {
+        path: apk.to_path_buf(),
+        message,
+    };
+    let reader = ZipFileReader::new(apk).await.map_err(invalid_archive)?;
+    let mut index = None;
+    for (i, entry) in reader.file().entries().iter().enumerate() {
+        if entry.filename().as_str().map_err(invalid_archive)? == ANDROID_MANIFEST {
+            index = Some(i);
+            break;
+        }
+    }
+    let Some(index) = index else {
+        anyhow::bail!(InventoryError::MissingManifest {
+            path: apk.to_path_buf()
+        });
+    };
+    let mut buffer = Vec::new();
+    reader
+        .reader_with_entry(index)
+        .await
+        .map_err(invalid_archive)?
+        .read_to_end_checked(&mut buffer)
+        .await
+        .map_err(invalid_archive)?;
+
+    let elements = axml::parse(&buffer).map_err(invalid_manifest)?;
+    let package = elements
+        .iter()
+        .find(|element| element.name == "manifest")
+        .and_then(|element| element.string("package"))
+        .ok_or_else(|| invalid_manifest("manifest has no package".into()))?;
+    let mut manifest = AndroidManifest {
+        package: package.to_owned(),
+        ..Default::default()
+    };
+    for element in &elements {
+        match element.name.as_str() {
+            "uses-sdk" => {
+                manifest.min_sdk_version = match element.attribute("minSdkVersion") {
+                    Some(axml::AttributeValue::Int(version)) => Some(*version),
+                    Some(axml::AttributeValue::String(version)) => version.parse().ok(),
+                    // Without the attribute the APK runs on every API level
+                    _ => Some(1),
+                }
+            }
+            "instrumentation" => {
+                let (Some(name), Some(target_package)) =
+                    (element.string("name"), element.string("targetPackage"))
+                else {
+                    continue;
+                };
+                manifest.instrumentations.push(Instrumentation {
+                    name: name.to_owned(),
+                    target_package: target_package.to_owned(),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(manifest)
+}
+
+/// Lists XCTest methods of every `.xctest` bundle in the test application.
+/// Accepts the same inputs as `run ios --test-application`: `.app`/`.xctest` folders and `.zip`/`.ipa` archives.
+pub async fn ios_tests(test_application: &Path) -> Result<Vec<TestCase>> {
+    let mut binaries = vec![];
+    if test_application.is_dir() {
+        let bundles = match test_application.extension().and_then(OsStr::to_str) {
+            Some("xctest") => vec![test_application.to_path_buf()],
+            _ => xctest_bundles(&test_application.join("PlugIns")).await?,
+        };
+        for bundle in bundles {
+            let Some(module) = bundle.file_stem().and_then(OsStr::to_str) else {
+                continue;
+            };
+            let binary = bundle.join(module);
+            let data = fs::read(&binary).await?;
+            binaries.push((
+                module.to_owned(),
+                binary.to_string_lossy().into_owned(),
+                data,
+            ));
+        }
+    } else {
+        let invalid_archive = |error| InventoryError::InvalidArchive {
+            path: test_application.to_path_buf(),
+            error,
+        };
+        let reader = ZipFileReader::new(test_application)
+            .await
+            .map_err(invalid_archive)?;
+        let binary_name = Regex::new(r"(?:^|/)([^/]+)\.xctest/([^/]+)$")?;
+        for (index, entry) in reader.file().entries().iter().enumerate() {
+            let name = entry.filename().as_str().map_err(invalid_archive)?;
+            let Some(captures) = binary_name.captures(name) else {
+                continue;
+            };
+            if captures[1] != captures[2] {
+                continue;
+            }
+            let module = captures[1].to_owned();
+            let name = name.to_owned();
+            let mut buffer = Vec::new();
+            reader
+                .reader_with_entry(index)
+                .await
+                .map_err(invalid_archive)?
+                .read_to_end_checked(&mut buffer)
+                .await
+                .map_err(invalid_archive)?;
+            binaries.push((module, name, buffer));
+        }
+    }
+    if binaries.is_empty() {
+        anyhow::bail!(InventoryError::MissingXctest {
+            path: test_application.to_path_buf()
+        });
+    }
+
+    let mut tests = vec![];
+    for (module, entry, data) in binaries {
+        let classes = macho::parse(&data).map_err(|message| InventoryError::InvalidMachO {
+            path: test_application.to_path_buf(),
+            entry,
+            message,
+        })?;
+        tests.extend(xctest_tests(&module, &classes));
+    }
+    tests.sort_by_key(|test| test.xctest_identifier());
+    Ok(tests)
+}
+
+/// XCTest methods of the classes in an image, including the ones inherited from superclasses
+fn xctest_tests(module: &str, classes: &[macho::ObjcClass]) -> Vec<TestCase> {
+    let mut tests = vec![];
+    let by_address: HashMap<u64, &macho::ObjcClass> =
+        classes.iter().map(|class| (class.address, class)).collect();
+
+    for class in classes {
+        let (class_module, class_name) = macho::demangle_class_name(&class.name);
+        let mut seen = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut current = Some(class);
+        // A malformed image can declare a class as its own ancestor
+        while let Some(declaring) = current.filter(|c| visited.insert(c.address)) {
+            for method in &declaring.methods {
+                // XCTest only runs `test*` instance methods without arguments returning void
+                if !method.selector.starts_with(XCTEST_PREFIX)
+                    || method.selector.contains(':')
+                    || !(method.types.is_empty() || method.types.starts_with('v'))
+                    || !seen.insert(method.selector.as_str())
+                {
+                    continue;
+                }
+                tests.push(TestCase {
+                    package: class_module.clone().unwrap_or_else(|| module.to_owned()),
+                    class: class_name.clone(),
+                    method: method.selector.clone(),
+                    annotations: vec![],
+                });
+            }
+            current = declaring
+                .superclass
+                .and_then(|superclass| by_address.get(&superclass).copied());
+        }
+    }
+    tests
+}
+
+/// Info.plist of an iOS bundle
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct BundleInfo {
+    #[serde(rename = "CFBundleIdentifier")]
+    pub identifier: Option<String>,
+    #[serde(rename = "MinimumOSVersion")]
+    pub minimum_os_version: Option<String>,
+    /// e.g. `iPhoneSimulator` or `iPhoneOS`
+    #[serde(rename = "CFBundleSupportedPlatforms", default)]
+    pub supported_platforms: Vec<String>,
+}
+
+/// An `.app` or `.xctest` bundle with the `.xctest` bundles it contains
+#[derive(Debug, PartialEq)]
+pub struct IosBundle {
+    pub info: BundleInfo,
+    /// For an `.xctest` input the bundle itself
+    pub test_bundles: Vec<BundleInfo>,
+}
+
+/// Reads the Info.plist files of an iOS bundle.
+/// Accepts the same inputs as `run ios`: `.app`/`.xctest` folders and `.zip`/`.ipa` archives.
+pub async fn ios_bundle(path: &Path) -> Result<IosBundle> {
+    // Path of each Info.plist inside the input and its content
+    let mut plists: Vec<(String, Vec<u8>)> = vec![];
+    if path.is_dir() {
+        let mut bundles = vec![path.to_path_buf()];
+        bundles.extend(xctest_bundles(&path.join("PlugIns")).await?);
+        for bundle in bundles {
+            let info = bundle.join(INFO_PLIST);
+            let Ok(data) = fs::read(&info).await else {
+                continue;
+            };
+            let name = info.strip_prefix(path.parent().unwrap_or(path))?;
+            plists.push((name.to_string_lossy().into_owned(), data));
+        }
+    } else {
+        let invalid_archive = |error| InventoryError::InvalidArchive {
+            path: path.to_path_buf(),
+            error,
+        };
+        let reader = ZipFileReader::new(path).await.map_err(invalid_archive)?;
+        let plist_name = Regex::new(
+            r"^(?:Payload/)?[^/]+\.(?:app|xctest)/(?:PlugIns/[^/]+\.xctest/)?Info\.plist$",
+        )?;
+        for (index, entry) in reader.file().entries().iter().enumerate() {
+            let name = entry.filename().as_str().map_err(invalid_archive)?;
+            if !plist_name.is_match(name) {
+                continue;
+            }
+            let name = name.to_owned();
+            let mut buffer = Vec::new();
+            reader
+                .reader_with_entry(index)
+                .await
+                .map_err(invalid_archive)?
+                .read_to_end_checked(&mut buffer)
+                .await
+                .map_err(invalid_archive)?;
+            plists.push((name, buffer));
+        }
+    }
+    // The bundle itself comes before its plug-ins
+    plists.sort_by_key(|(name, _)| name.contains("/PlugIns/"));
+
+    let mut infos = vec![];
+    for (entry, data) in plists {
+        let info: BundleInfo =
+            plist::from_bytes(&data).map_err(|error| InventoryError::InvalidInfoPlist {
+                path: path.to_path_buf(),
+                entry: entry.clone(),
+                message: error.to_string(),
+            })?;
+        infos.push((entry, info));
+    }
+    let mut infos = infos.into_iter();
+    let Some((entry, info)) = infos
+        .next()
+        .filter(|(entry, _)| !entry.contains("/PlugIns/"))
+    else {
+        anyhow::bail!(InventoryError::MissingInfoPlist {
+            path: path.to_path_buf()
+        });
+    };
+    let test_bundles = if entry.trim_end_matches(INFO_PLIST).ends_with(".xctest/") {
+        vec![info.clone()]
+    } else {
+        infos.map(|(_, info)| info).collect()
+    };
+    Ok(IosBundle { info, test_bundles })
+}
+
+async fn xctest_bundles(plugins: &Path) -> Result<Vec<PathBuf>> {
+    let mut bundles = vec![];
+    let Ok(mut entries) = fs::read_dir(plugins).await else {
+        return Ok(bundles);
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.is_dir() && path.extension().and_then(OsStr::to_str) == Some("xctest") {
+            bundles.push(path);
+        }
+    }
+    bundles.sort();
+    Ok(bundles)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        Path::new(&manifest_dir)
+            .join("fixture")
+            .join("inventory")
+            .join(name)
+    }
+
+    #[tokio::test]
+    async fn test_android_tests() -> Result<()> {
+        let tests = android_tests(&fixture("app-androidTest.apk")).await?;
+
+        let names: Vec<String> = tests
+            .iter()
+            .map(|test| test.fully_qualified_test_name())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "com.example.LoginTest#testBase",
+                "com.example.LoginTest#testLogin",
+                "com.example.LoginTest#testLogout",
+                "com.example.slow.UploadTest#testBase",
+                "com.example.slow.UploadTest#testUpload",
+            ]
+        );
+        assert_eq!(
+            tests[1].annotations,
+            vec![
+                "org.junit.runner.RunWith",
+                "org.junit.Test",
+                "com.example.Smoke"
+            ]
+        );
+        assert_eq!(
+            tests[4].annotations,
+            vec!["androidx.test.filters.LargeTest", "org.junit.Test"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_junit_tests_cyclic_superclass() {
+        let class = |name: &str, superclass: &str| dex::DexClass {
+            name: name.to_owned(),
+            superclass: Some(superclass.to_owned()),
+            is_abstract: false,
+            annotations: vec![],
+            methods: vec![dex::DexMethod {
+                name: format!("test{}", name),
+                annotations: vec![JUNIT4_TEST.to_owned()],
+            }],
+        };
+        let classes = HashMap::from([
+            ("A".to_owned(), class("A", "B")),
+            ("B".to_owned(), class("B", "A")),
+        ]);
+
+        let names: Vec<String> = junit_tests(&classes)
+            .iter()
+            .map(|test| test.fully_qualified_test_name())
+            .collect();
+        assert_eq!(names, vec!["A#testA", "A#testB", "B#testA", "B#testB"]);
+    }
+
+    #[tokio::test]
+    async fn test_android_manifest() -> Result<()> {
+        let manifest = android_manifest(&fixture("app-androidTest.apk")).await?;
+
+        assert_eq!(
+            manifest,
+            AndroidManifest {
+                package: "com.example.test".into(),
+                min_sdk_version: Some(26),
+                instrumentations: vec![Instrumentation {
+                    name: "androidx.test.runner.AndroidJUnitRunner".into(),
+                    target_package: "com.example".into(),
+                }],
+            }
+        );
+        let manifest = android_manifest(&fixture("app.apk")).await?;
+        assert_eq!(manifest.package, "com.example");
+        assert!(manifest.instrumentations.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ios_bundle() -> Result<()> {
+        let app = ios_bundle(&fixture("App.app")).await?;
+        assert_eq!(app.info.identifier.as_deref(), Some("com.example.App"));
+        assert_eq!(app.info.supported_platforms, vec!["iPhoneSimulator"]);
+        assert!(app.test_bundles.is_empty());
+
+        let runner = ios_bundle(&fixture("AppUITests-Runner.app")).await?;
+        assert_eq!(runner.info.minimum_os_version.as_deref(), Some("16.0"));
+        assert_eq!(
+            runner.test_bundles[0].identifier.as_deref(),
+            Some("com.example.AppUITests")
+        );
+
+        let xctest = fixture("AppUITests-Runner.app/PlugIns/AppTests.xctest");
+        let xctest = ios_bundle(&xctest).await?;
+        assert_eq!(xctest.test_bundles, vec![xctest.info.clone()]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ios_bundle_from_zip() -> Result<()> {
+        let app = fixture("AppUITests-Runner.app");
+        let dir = tempfile::tempdir()?;
+        let zip = dir.path().join("AppUITests-Runner.zip");
+        let file = tokio::fs::File::create(&zip).await?;
+        crate::compression::zip_dir(&app, file).await?;
+
+        assert_eq!(ios_bundle(&zip).await?, ios_bundle(&app).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_android_tests_without_dex() {
+        let result = android_tests(&fixture("../tls/client.crt")).await;
+        assert!(result.is_err());
+    }
+
+    fn xctest_identifiers(tests: &[TestCase]) -> Vec<String> {
+        tests.iter().map(|test| test.xctest_identifier()).collect()
+    }
+
+    const IOS_TESTS: [&str; 7] = [
+        "BaseTests/testBase",
+        "LoginTests/testLogin",
+        "LoginTests/testLogout",
+        "SubclassTests/testBase",
+        "SubclassTests/testSub",
+        "UploadTest/testUpload",
+        "UploadTest/testUploadLarge",
+    ];
+
+    #[test]
+    fn test_xctest_tests_cyclic_superclass() {
+        let class = |address: u64, superclass: u64| macho::ObjcClass {
+            address,
+            name: format!("Test{}", address),
+            superclass: Some(superclass),
+            methods: vec![macho::ObjcMethod {
+                selector: format!("test{}", address),
+                types: "v16@0:8".to_owned(),
+            }],
+        };
+        let classes = vec![class(1, 2), class(2, 1)];
+
+        let names: Vec<String> = xctest_tests("AppTests", &classes)
+            .iter()
+            .map(|test| test.xctest_identifier())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["Test1/test1", "Test1/test2", "Test2/test2", "Test2/test1",]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ios_tests_from_app() -> Result<()> {
+        let tests = ios_tests(&fixture("AppUITests-Runner.app")).await?;
+
+        assert_eq!(xctest_identifiers(&tests), IOS_TESTS);
+        assert!(tests.iter().all(|test| test.package == "AppTests"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ios_tests_from_zip() -> Result<()> {
+        let app = fixture("AppUITests-Runner.app");
+        let dir = tempfile::tempdir()?;
+        let zip = dir.path().join("AppUITests-Runner.zip");
+        let file = tokio::fs::File::create(&zip).await?;
+        crate::compression::zip_dir(&app, file).await?;
+
+        let tests = ios_tests(&zip).await?;
+
+        assert_eq!(xctest_identifiers(&tests), IOS_TESTS);
+        Ok(())
+    }
+}
@@ -1,11 +1,54 @@
-mod api;
-mod artifacts;
-mod bundle;
+//! Client library for [Marathon Cloud](https://cloud.marathonlabs.io).
+//!
+//! The `marathon-cloud` binary is a thin layer over this crate, so everything it does
+//! can be done from Rust directly:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use marathon_cloud::{
+//!     api::{RapiClient, RapiReqwestClient},
+//!     artifacts::{download_artifacts, fetch_artifact_list},
+//!     filtering,
+//!     spec::{IosRunSpec, RunSpec},
+//! };
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let client = RapiReqwestClient::new("https://cloud.marathonlabs.io/api", "<api key>");
+//!
+//! let filter = filtering::convert::convert("filter.yaml".into()).await?;
+//! let ios = IosRunSpec {
+//!     application: "sample.zip".into(),
+//!     test_application: "sampleUITests-Runner.zip".into(),
+//!     ..Default::default()
+//! };
+//! let spec = RunSpec::builder(ios)
+//!     .name("nightly".to_owned())
+//!     .filtering_configuration(filter)
+//!     .build()?;
+//!
+//! let id = client.create_run(spec, true).await?;
+//! let run = client.wait_for_run(&id, Duration::from_secs(5)).await?;
+//! println!("{} finished with state {}", run.id, run.state);
+//!
+//! let artifacts = fetch_artifact_list(&client, &id).await?;
+//! download_artifacts(&client, &id, artifacts, "output".as_ref(), true).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod api;
+pub mod artifacts;
+pub mod bundle;
 pub mod cli;
 mod compression;
-mod errors;
-mod filtering;
+pub mod constraints;
+pub mod errors;
+pub mod filtering;
 mod formatter;
 mod interactor;
+pub mod inventory;
+pub mod matrix;
 mod progress;
-mod pull;
+pub mod pull;
+pub mod spec;
@@ -0,0 +1,230 @@
+use std::str::FromStr;
+
+use crate::errors::InputError;
+
+/// One `--matrix DIMENSION=VALUE,VALUE` argument, e.g. `os-version=11,13,15`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatrixAxis {
+    /// Name of the run argument without dashes, e.g. `os_version`
+    pub dimension: String,
+    pub values: Vec<String>,
+}
+
+impl FromStr for MatrixAxis {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: &str| InputError::InvalidMatrix {
+            axis: s.to_owned(),
+            message: message.to_owned(),
+        };
+        let (dimension, values) = s
+            .split_once('=')
+            .ok_or_else(|| invalid("expected DIMENSION=VALUE,VALUE"))?;
+        let dimension = dimension.trim().trim_start_matches("--").replace('-', "_");
+        if dimension.is_empty() {
+            return Err(invalid("dimension is empty"));
+        }
+        let mut unique: Vec<String> = vec![];
+        for value in values.split(',').map(str::trim) {
+            if value.is_empty() {
+                return Err(invalid("values can't be empty"));
+            }
+            if !unique.iter().any(|v| v == value) {
+                unique.push(value.to_owned());
+            }
+        }
+        Ok(MatrixAxis {
+            dimension,
+            values: unique,
+        })
+    }
+}
+
+/// Values of the matrix dimensions for a single run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cell(pub Vec<(String, String)>);
+
+impl Cell {
+    fn get(&self, dimension: &str) -> Option<&String> {
+        self.0
+            .iter()
+            .find(|(d, _)| d == dimension)
+            .map(|(_, value)| value)
+    }
+
+    /// Human readable, e.g. `device=tv, os-version=15`
+    pub fn label(&self) -> String {
+        self.0
+            .iter()
+            .map(|(dimension, value)| format!("{}={}", dimension.replace('_', "-"), value))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Name of the output subdirectory, e.g. `device-tv_os-version-15`
+    pub fn dir_name(&self) -> String {
+        self.0
+            .iter()
+            .map(|(dimension, value)| {
+                let value: String = value
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                            c
+                        } else {
+                            '-'
+                        }
+                    })
+                    .collect();
+                format!("{}-{}", dimension.replace('_', "-"), value)
+            })
+            .collect::<Vec<_>>()
+            .join("_")
+    }
+}
+
+/// A cell with the values of all dimensions, in the order of the dimensions
+pub type Configuration = (Cell, Vec<Option<String>>);
+
+/// Cartesian product of the axes. Each cell comes with the values of all `dimensions`,
+/// taken from the cell or else from `provided`. Without axes there is a single empty cell
+pub fn expand(
+    axes: &[MatrixAxis],
+    dimensions: &[&str],
+    provided: &[Option<String>],
+) -> Result<Vec<Configuration>, InputError> {
+    for (index, axis) in axes.iter().enumerate() {
+        let invalid = |message: String| InputError::InvalidMatrix {
+            axis: format!(
+                "{}={}",
+                axis.dimension.replace('_', "-"),
+                axis.values.join(",")
+            ),
+            message,
+        };
+        let position = dimensions
+            .iter()
+            .position(|d| *d == axis.dimension)
+            .ok_or_else(|| {
+                invalid(format!(
+                    "unsupported dimension, supported are {}",
+                    dimensions
+                        .iter()
+                        .map(|d| d.replace('_', "-"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })?;
+        if axes[..index].iter().any(|a| a.dimension == axis.dimension) {
+            return Err(invalid("dimension is repeated".to_owned()));
+        }
+        if provided[position].is_some() {
+            return Err(invalid(format!(
+                "--{} is also set, list its values in the matrix instead",
+                axis.dimension.replace('_', "-")
+            )));
+        }
+    }
+
+    let mut cells = vec![Cell::default()];
+    for axis in axes {
+        cells = cells
+            .into_iter()
+            .flat_map(|cell| {
+                axis.values.iter().map(move |value| {
+                    let mut cell = cell.clone();
+                    cell.0.push((axis.dimension.clone(), value.clone()));
+                    cell
+                })
+            })
+            .collect();
+    }
+
+    Ok(cells
+        .into_iter()
+        .map(|cell| {
+            let values = dimensions
+                .iter()
+                .zip(provided)
+                .map(|(dimension, value)| cell.get(dimension).cloned().or_else(|| value.clone()))
+                .collect();
+            (cell, values)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIMENSIONS: [&str; 3] = ["device", "flavor", "os_version"];
+
+    #[test]
+    fn test_parse_axis() {
+        assert_eq!(
+            "os-version=11, 13,15,13".parse::<MatrixAxis>().unwrap(),
+            MatrixAxis {
+                dimension: "os_version".into(),
+                values: vec!["11".into(), "13".into(), "15".into()],
+            }
+        );
+        assert!("os-version".parse::<MatrixAxis>().is_err());
+        assert!("os-version=11,".parse::<MatrixAxis>().is_err());
+        assert!("=11".parse::<MatrixAxis>().is_err());
+    }
+
+    #[test]
+    fn test_expand() {
+        let axes = vec![
+            "os-version=11,15".parse().unwrap(),
+            "device=pixel,tv".parse().unwrap(),
+        ];
+        let provided = vec![None, Some("native".to_owned()), None];
+
+        let cells = expand(&axes, &DIMENSIONS, &provided).unwrap();
+
+        let labels: Vec<_> = cells.iter().map(|(cell, _)| cell.label()).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "os-version=11, device=pixel",
+                "os-version=11, device=tv",
+                "os-version=15, device=pixel",
+                "os-version=15, device=tv",
+            ]
+        );
+        assert_eq!(cells[1].0.dir_name(), "os-version-11_device-tv");
+        assert_eq!(
+            cells[1].1,
+            vec![
+                Some("tv".to_owned()),
+                Some("native".to_owned()),
+                Some("11".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_without_axes() {
+        let provided = vec![Some("tv".to_owned()), None, None];
+        let cells = expand(&[], &DIMENSIONS, &provided).unwrap();
+        assert_eq!(cells, vec![(Cell::default(), provided)]);
+    }
+
+    #[test]
+    fn test_expand_invalid_axes() {
+        let axis = |s: &str| s.parse::<MatrixAxis>().unwrap();
+        let none = vec![None, None, None];
+
+        let error = expand(&[axis("xcode-version=16.2")], &DIMENSIONS, &none).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("supported are device, flavor, os-version"));
+        assert!(expand(&[axis("device=a"), axis("device=b")], &DIMENSIONS, &none).is_err());
+
+        let provided = vec![Some("tv".to_owned()), None, None];
+        let error = expand(&[axis("device=pixel")], &DIMENSIONS, &provided).unwrap_err();
+        assert!(error.to_string().contains("--device is also set"));
+    }
+}
@@ -1,5 +1,5 @@
 use serde_with::DurationSecondsWithFrac;
-use std::{fmt::Display, time::Duration};
+use std::{collections::BTreeMap, fmt::Display, time::Duration};
 
 use serde::Serialize;
 use serde_with::serde_as;
@@ -70,3 +70,11 @@ impl Display for TestRunFinished {
         Ok(())
     }
 }
+
+/// [`TestRunStarted`] or [`TestRunFinished`] of one run of a matrix
+#[derive(Serialize)]
+pub struct MatrixCellEvent<T> {
+    pub cell: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub run: T,
+}
@@ -0,0 +1,401 @@
+use std::{collections::HashMap, fmt::Display, path::PathBuf};
+
+use anyhow::Result;
+
+use crate::{
+    api::vec_to_hashmap,
+    bundle::ApplicationBundle,
+    errors::{ConfigurationError, InputError},
+    filtering::model::SparseMarathonfile,
+    pull::PullFileConfig,
+};
+
+/// Complete description of a test run: platform independent settings plus
+/// an [`AndroidRunSpec`] or [`IosRunSpec`]. Use [`RunSpec::builder`] to create one.
+#[derive(Debug, Clone)]
+pub struct RunSpec {
+    pub name: Option<String>,
+    pub link: Option<String>,
+    pub branch: Option<String>,
+    pub project: Option<String>,
+    pub isolated: Option<bool>,
+    pub code_coverage: Option<bool>,
+    pub retry_quota_test_uncompleted: Option<u32>,
+    pub retry_quota_test_preventive: Option<u32>,
+    pub retry_quota_test_reactive: Option<u32>,
+    pub analytics_read_only: Option<bool>,
+    pub filtering_configuration: Option<SparseMarathonfile>,
+    pub concurrency_limit: Option<u32>,
+    pub test_timeout_default: Option<u32>,
+    pub test_timeout_max: Option<u32>,
+    pub platform: PlatformRunSpec,
+}
+
+#[derive(Debug, Clone)]
+pub enum PlatformRunSpec {
+    Android(AndroidRunSpec),
+    #[allow(non_camel_case_types)]
+    iOS(IosRunSpec),
+}
+
+impl Display for PlatformRunSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlatformRunSpec::Android(_) => f.write_str("Android"),
+            PlatformRunSpec::iOS(_) => f.write_str("iOS"),
+        }
+    }
+}
+
+impl From<AndroidRunSpec> for PlatformRunSpec {
+    fn from(value: AndroidRunSpec) -> Self {
+        PlatformRunSpec::Android(value)
+    }
+}
+
+impl From<IosRunSpec> for PlatformRunSpec {
+    fn from(value: IosRunSpec) -> Self {
+        PlatformRunSpec::iOS(value)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct AndroidRunSpec {
+    pub application: Option<PathBuf>,
+    pub test_application: Option<PathBuf>,
+    pub application_bundle: Option<Vec<ApplicationBundle>>,
+    pub library_bundle: Option<Vec<PathBuf>>,
+    pub os_version: Option<String>,
+    pub system_image: Option<String>,
+    pub device: Option<String>,
+    pub flavor: Option<String>,
+    pub instrumentation_args: Option<HashMap<String, String>>,
+    pub pull_file_config: Option<PullFileConfig>,
+    pub profiling: bool,
+    pub mock_location: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct IosRunSpec {
+    pub application: PathBuf,
+    pub test_application: PathBuf,
+    pub os_version: Option<String>,
+    pub device: Option<String>,
+    pub xcode_version: Option<String>,
+    pub xctestrun_env: Option<HashMap<String, String>>,
+    pub xctestrun_test_env: Option<HashMap<String, String>>,
+    pub granted_permission: Option<Vec<String>>,
+    pub language: Option<String>,
+    pub country: Option<String>,
+}
+
+pub const IOS_PERMISSIONS: [&str; 12] = [
+    "calendar",
+    "contacts-limited",
+    "contacts",
+    "location",
+    "location-always",
+    "photos-add",
+    "photos",
+    "media-library",
+    "microphone",
+    "motion",
+    "reminders",
+    "siri",
+];
+
+impl RunSpec {
+    pub fn builder(platform: impl Into<PlatformRunSpec>) -> RunSpecBuilder {
+        RunSpecBuilder {
+            spec: RunSpec {
+                name: None,
+                link: None,
+                branch: None,
+                project: None,
+                isolated: None,
+                code_coverage: None,
+                retry_quota_test_uncompleted: None,
+                retry_quota_test_preventive: None,
+                retry_quota_test_reactive: None,
+                analytics_read_only: None,
+                filtering_configuration: None,
+                concurrency_limit: None,
+                test_timeout_default: None,
+                test_timeout_max: None,
+                platform: platform.into(),
+            },
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (arg, value) in [
+            ("--concurrency-limit", self.concurrency_limit),
+            ("--test-timeout-default", self.test_timeout_default),
+            ("--test-timeout-max", self.test_timeout_max),
+        ] {
+            if value == Some(0) {
+                return Err(InputError::NonPositiveValue {
+                    arg: arg.to_owned(),
+                })?;
+            }
+        }
+
+        match &self.platform {
+            PlatformRunSpec::Android(android) => android.validate(),
+            PlatformRunSpec::iOS(ios) => ios.validate(),
+        }
+    }
+}
+
+impl AndroidRunSpec {
+    /// Parses `KEY=VALUE` instrumentation arguments
+    pub fn with_instrumentation_args(mut self, args: Option<Vec<String>>) -> Result<Self> {
+        self.instrumentation_args = vec_to_hashmap(args)?;
+        Ok(self)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let has_application = self.application.is_some();
+        let has_test_application = self.test_application.is_some();
+        let has_bundles = self.application_bundle.is_some() || self.library_bundle.is_some();
+
+        if !has_application && !has_test_application && !has_bundles {
+            return Err(ConfigurationError::UnsupportedRunConfiguration {
+                message:
+                    "Please set up APKs for testing. The following argument combinations are possible:
+--application <APPLICATION> --test-application <TEST_APPLICATION> - for application testing
+--application-bundle <APPLICATION>,<TEST_APPLICATION> - advanced mode that allows setting up one or more application bundles for testing
+--library-bundle <TEST_APPLICATION> - advanced mode that allows setting up one or more library bundles for testing"
+                        .into(),
+            }
+            .into());
+        }
+
+        if has_application && !has_test_application && !has_bundles {
+            return Err(ConfigurationError::UnsupportedRunConfiguration {
+                message: "Please set up Testing APK:
+--test-application <TEST_APPLICATION>"
+                    .into(),
+            }
+            .into());
+        }
+
+        if !has_application && has_test_application && !has_bundles {
+            return Err(ConfigurationError::UnsupportedRunConfiguration {
+                message: "Please set up Application APK:
+--application <TEST_APPLICATION>
+If you are interesting in library testing then please use advance mode with --library-bundle argument"
+                    .into(),
+            }
+            .into());
+        }
+
+        if let Some(bundles) = &self.application_bundle {
+            if bundles.len() > 1 && self.mock_location {
+                return Err(ConfigurationError::UnsupportedRunConfiguration {
+                    message: "Mock location access doesn't support multiple application bundles"
+                        .into(),
+                }
+                .into());
+            }
+        }
+
+        if self.application_bundle.is_none() && !has_application && self.mock_location {
+            return Err(ConfigurationError::UnsupportedRunConfiguration {
+                message: "There is no Application where mock location can be used".into(),
+            }
+            .into());
+        }
+
+        let bundle_paths = self
+            .application_bundle
+            .iter()
+            .flatten()
+            .flat_map(|bundle| [&bundle.app_path, &bundle.test_app_path]);
+        for path in self
+            .application
+            .iter()
+            .chain(self.test_application.iter())
+            .chain(bundle_paths)
+            .chain(self.library_bundle.iter().flatten())
+        {
+            if !path.exists() {
+                return Err(InputError::InvalidFileName {
+                    path: path.to_owned(),
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl IosRunSpec {
+    /// Parses `KEY=VALUE` xctestrun environment and testing environment variables
+    pub fn with_xctestrun_env(
+        mut self,
+        env: Option<Vec<String>>,
+        test_env: Option<Vec<String>>,
+    ) -> Result<Self> {
+        self.xctestrun_env = vec_to_hashmap(env)?;
+        self.xctestrun_test_env = vec_to_hashmap(test_env)?;
+        Ok(self)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(granted_permission) = &self.granted_permission {
+            let invalid_permissions: Vec<_> = granted_permission
+                .iter()
+                .filter(|perm| !IOS_PERMISSIONS.contains(&perm.as_str()))
+                .cloned()
+                .collect();
+
+            if !invalid_permissions.is_empty() {
+                return Err(InputError::IncorrectPermission {
+                    permissions: invalid_permissions,
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct RunSpecBuilder {
+    spec: RunSpec,
+}
+
+impl RunSpecBuilder {
+    pub fn name(mut self, name: impl Into<Option<String>>) -> Self {
+        self.spec.name = name.into();
+        self
+    }
+
+    pub fn link(mut self, link: impl Into<Option<String>>) -> Self {
+        self.spec.link = link.into();
+        self
+    }
+
+    pub fn branch(mut self, branch: impl Into<Option<String>>) -> Self {
+        self.spec.branch = branch.into();
+        self
+    }
+
+    pub fn project(mut self, project: impl Into<Option<String>>) -> Self {
+        self.spec.project = project.into();
+        self
+    }
+
+    pub fn isolated(mut self, isolated: impl Into<Option<bool>>) -> Self {
+        self.spec.isolated = isolated.into();
+        self
+    }
+
+    pub fn code_coverage(mut self, code_coverage: impl Into<Option<bool>>) -> Self {
+        self.spec.code_coverage = code_coverage.into();
+        self
+    }
+
+    pub fn retry_quota_test_uncompleted(mut self, quota: impl Into<Option<u32>>) -> Self {
+        self.spec.retry_quota_test_uncompleted = quota.into();
+        self
+    }
+
+    pub fn retry_quota_test_preventive(mut self, quota: impl Into<Option<u32>>) -> Self {
+        self.spec.retry_quota_test_preventive = quota.into();
+        self
+    }
+
+    pub fn retry_quota_test_reactive(mut self, quota: impl Into<Option<u32>>) -> Self {
+        self.spec.retry_quota_test_reactive = quota.into();
+        self
+    }
+
+    pub fn analytics_read_only(mut self, analytics_read_only: impl Into<Option<bool>>) -> Self {
+        self.spec.analytics_read_only = analytics_read_only.into();
+        self
+    }
+
+    pub fn filtering_configuration(
+        mut self,
+        filtering_configuration: impl Into<Option<SparseMarathonfile>>,
+    ) -> Self {
+        self.spec.filtering_configuration = filtering_configuration.into();
+        self
+    }
+
+    pub fn concurrency_limit(mut self, concurrency_limit: impl Into<Option<u32>>) -> Self {
+        self.spec.concurrency_limit = concurrency_limit.into();
+        self
+    }
+
+    pub fn test_timeout_default(mut self, timeout: impl Into<Option<u32>>) -> Self {
+        self.spec.test_timeout_default = timeout.into();
+        self
+    }
+
+    pub fn test_timeout_max(mut self, timeout: impl Into<Option<u32>>) -> Self {
+        self.spec.test_timeout_max = timeout.into();
+        self
+    }
+
+    pub fn build(self) -> Result<RunSpec> {
+        self.spec.validate()?;
+        Ok(self.spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_rejects_zero_concurrency_limit() {
+        let ios = IosRunSpec::default();
+        let result = RunSpec::builder(ios).concurrency_limit(0).build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_build_rejects_unknown_permission() {
+        let ios = IosRunSpec {
+            granted_permission: Some(vec!["location".into(), "camera".into()]),
+            ..Default::default()
+        };
+        let result = RunSpec::builder(ios).build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_build_requires_android_binaries() {
+        let android = AndroidRunSpec::default();
+        let result = RunSpec::builder(android).build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_build_rejects_mock_location_without_application() {
+        let android = AndroidRunSpec {
+            library_bundle: Some(vec![]),
+            mock_location: true,
+            ..Default::default()
+        };
+        let result = RunSpec::builder(android).build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_build_valid_ios() -> Result<()> {
+        let ios = IosRunSpec {
+            granted_permission: Some(vec!["location".into()]),
+            ..Default::default()
+        }
+        .with_xctestrun_env(Some(vec!["FOO=BAR".into()]), None)?;
+        let spec = RunSpec::builder(ios)
+            .name("run".to_owned())
+            .test_timeout_default(300)
+            .build()?;
+        assert_eq!(spec.name.as_deref(), Some("run"));
+        assert_eq!(spec.platform.to_string(), "iOS");
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+mod fake_server;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use fake_server::{Endpoint, FakeServer, Scenario, API_KEY, RUN_ID};
+use marathon_cloud::{
+    api::{ClientConfig, RapiClient, RapiReqwestClient},
+    artifacts::{download_artifacts, fetch_artifact_list},
+};
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_token_is_fetched_once_and_key_only_sent_for_token() -> Result<()> {
+    let server = FakeServer::start(Scenario::default()).await;
+    let client = RapiReqwestClient::new(&server.base_url(), API_KEY);
+
+    client.get_run(RUN_ID).await?;
+    client.clone().get_devices_android().await?;
+
+    server.requests(|requests| {
+        assert_eq!(requests.count(Endpoint::Token), 1);
+        assert!(requests.queries.iter().all(|(_, q)| !q.contains(API_KEY)));
+    });
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_token_falls_back_to_query_api_key() -> Result<()> {
+    let scenario = Scenario {
+        query_api_key: true,
+        ..Default::default()
+    };
+    let server = FakeServer::start(scenario).await;
+    let client = RapiReqwestClient::new(&server.base_url(), API_KEY);
+
+    client.get_run(RUN_ID).await?;
+
+    server.requests(|requests| {
+        assert_eq!(requests.count(Endpoint::Token), 2);
+        assert!(requests
+            .queries
+            .iter()
+            .all(|(endpoint, q)| *endpoint == Endpoint::Token || !q.contains(API_KEY)));
+    });
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_api_key_is_rejected() {
+    let server = FakeServer::start(Scenario::default()).await;
+    let client = RapiReqwestClient::new(&server.base_url(), "wrong-api-key");
+
+    let Err(error) = client.get_run(RUN_ID).await else {
+        panic!("the key was accepted");
+    };
+
+    assert!(
+        !format!("{:?}", error).contains("wrong-api-key"),
+        "{:?}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn test_expired_token_is_refreshed() -> Result<()> {
+    let scenario = Scenario {
+        expired_tokens: 1,
+        ..Default::default()
+    };
+    let server = FakeServer::start(scenario).await;
+    let client = RapiReqwestClient::new(&server.base_url(), API_KEY);
+
+    let run = client.get_run(RUN_ID).await?;
+
+    assert_eq!(run.state, "passed");
+    server.requests(|requests| assert_eq!(requests.count(Endpoint::Token), 2));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_slow_run_is_polled_until_completed() -> Result<()> {
+    let server = FakeServer::start(Scenario::slow_run(3)).await;
+    let client = RapiReqwestClient::new(&server.base_url(), API_KEY);
+
+    let run = client
+        .wait_for_run(RUN_ID, Duration::from_millis(10))
+        .await?;
+
+    assert!(run.completed.is_some());
+    server.requests(|requests| assert_eq!(requests.count(Endpoint::GetRun), 3));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_artifact_download_survives_server_error_burst() -> Result<()> {
+    let server = FakeServer::start(Scenario::server_errors(Endpoint::DownloadArtifact, 2)).await;
+    let client = RapiReqwestClient::new(&server.base_url(), API_KEY);
+    let output = tempdir()?;
+
+    let artifacts = fetch_artifact_list(&client, RUN_ID).await?;
+    assert_eq!(artifacts.len(), 2);
+    download_artifacts(&client, RUN_ID, artifacts, output.path(), true).await?;
+
+    assert!(output.path().join("tests/junit.xml").is_file());
+    assert!(output
+        .path()
+        .join("report/allure-results/result.json")
+        .is_file());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_errors_are_reported() {
+    let server = FakeServer::start(Scenario::server_errors(Endpoint::GetRun, 1)).await;
+    let client = RapiReqwestClient::new(&server.base_url(), API_KEY);
+
+    let result = client.get_run(RUN_ID).await;
+
+    let error = result.err().unwrap().to_string();
+    assert!(error.contains("503"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_requests_go_through_proxy() -> Result<()> {
+    let server = FakeServer::start(Scenario::default()).await;
+    let config = ClientConfig {
+        proxy: Some(server.base_url().replace("/api", "")),
+        ..Default::default()
+    };
+    // Host is unresolvable, so the request can only succeed via the proxy
+    let client = RapiReqwestClient::with_config("http://marathon.invalid/api", API_KEY, &config)?;
+
+    let devices = client.get_devices_android().await?;
+
+    assert_eq!(devices.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_devices_ios() -> Result<()> {
+    let server = FakeServer::start(Scenario::default()).await;
+    let client = RapiReqwestClient::new(&server.base_url(), API_KEY);
+
+    let devices = client.get_devices_ios().await?;
+
+    assert_eq!(devices.len(), 3);
+    assert_eq!(devices[0].device, "iPhone-15");
+    assert_eq!(devices[0].os_version, "17.5");
+    assert_eq!(devices[0].xcode_version, "15.4");
+    Ok(())
+}
@@ -0,0 +1,421 @@
+//! In-process fake of the Marathon Cloud API for offline tests.
+//!
+//! Every test starts its own server on a random port with a scripted [`Scenario`]
+//! and inspects the recorded [`Requests`] afterwards.
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, RawQuery, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde_json::{json, Value};
+
+pub const API_KEY: &str = "fake-api-key";
+pub const RUN_ID: &str = "run-1";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Endpoint {
+    Token,
+    PresignedUrl,
+    Upload,
+    CreateRun,
+    GetRun,
+    ListArtifacts,
+    DownloadArtifact,
+    AndroidDevices,
+    IosDevices,
+    DeviceConstraints,
+}
+
+pub struct Scenario {
+    /// Number of `GET /v1/run/{id}` calls after which the run is reported as completed
+    pub polls_until_complete: u32,
+    pub final_state: String,
+    pub error_message: Option<String>,
+    /// Number of consecutive 5xx responses before an endpoint starts to succeed
+    pub server_errors: HashMap<Endpoint, u32>,
+    /// Number of bearer requests rejected with 401 as if the JWT has expired
+    pub expired_tokens: u32,
+    /// Only accept the API key in the `api_key` query parameter of the token exchange
+    pub query_api_key: bool,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            polls_until_complete: 1,
+            final_state: "passed".into(),
+            error_message: None,
+            server_errors: HashMap::new(),
+            expired_tokens: 0,
+            query_api_key: false,
+        }
+    }
+}
+
+impl Scenario {
+    pub fn slow_run(polls: u32) -> Self {
+        Self {
+            polls_until_complete: polls,
+            ..Default::default()
+        }
+    }
+
+    pub fn failed_run() -> Self {
+        Self {
+            final_state: "failure".into(),
+            error_message: Some("1 test failed".into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn server_errors(endpoint: Endpoint, count: u32) -> Self {
+        Self {
+            server_errors: HashMap::from([(endpoint, count)]),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Requests {
+    pub counts: HashMap<Endpoint, u32>,
+    /// Raw query strings of every request, used to check where credentials end up in urls
+    pub queries: Vec<(Endpoint, String)>,
+    pub uploads: HashMap<String, usize>,
+    pub runs: Vec<Value>,
+}
+
+impl Requests {
+    pub fn count(&self, endpoint: Endpoint) -> u32 {
+        self.counts.get(&endpoint).copied().unwrap_or(0)
+    }
+}
+
+struct Inner {
+    addr: SocketAddr,
+    scenario: Scenario,
+    requests: Requests,
+    issued_tokens: u32,
+}
+
+type Shared = Arc<Mutex<Inner>>;
+
+pub struct FakeServer {
+    addr: SocketAddr,
+    inner: Shared,
+}
+
+impl FakeServer {
+    pub async fn start(scenario: Scenario) -> FakeServer {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let inner = Arc::new(Mutex::new(Inner {
+            addr,
+            scenario,
+            requests: Requests::default(),
+            issued_tokens: 0,
+        }));
+
+        let app = Router::new()
+            .route("/api/v1/user/jwt", get(token))
+            .route("/api/v2/upload/presigned-url", post(presigned_url))
+            .route("/upload/:name", put(upload))
+            .route("/api/v2/run", post(create_run))
+            .route("/api/v1/run/:id", get(get_run))
+            .route("/api/v1/artifact", get(download_artifact))
+            .route("/api/v1/artifact/*id", get(list_artifacts))
+            .route("/api/v1/devices/android", get(android_devices))
+            .route("/api/v1/devices/ios", get(ios_devices))
+            .route("/api/v1/devices/constraints", get(device_constraints))
+            .with_state(inner.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        FakeServer { addr, inner }
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}/api", self.addr)
+    }
+
+    pub fn requests<T>(&self, f: impl FnOnce(&Requests) -> T) -> T {
+        f(&self.inner.lock().unwrap().requests)
+    }
+}
+
+/// Records the request and plays back scripted failures.
+/// Returns the response to send instead of the regular one, if any.
+fn intercept(
+    inner: &mut Inner,
+    endpoint: Endpoint,
+    headers: &HeaderMap,
+    query: Option<String>,
+) -> Option<Response> {
+    *inner.requests.counts.entry(endpoint).or_default() += 1;
+    let query = query.unwrap_or_default();
+    inner.requests.queries.push((endpoint, query.clone()));
+
+    if let Some(remaining) = inner.scenario.server_errors.get_mut(&endpoint) {
+        if *remaining > 0 {
+            *remaining -= 1;
+            return Some((StatusCode::SERVICE_UNAVAILABLE, "scripted failure").into_response());
+        }
+    }
+
+    let authorization = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    match endpoint {
+        // Presigned urls carry their own credentials
+        Endpoint::Upload => None,
+        Endpoint::Token => {
+            let valid = if inner.scenario.query_api_key {
+                query == format!("api_key={}", API_KEY)
+            } else {
+                headers
+                    .get("x-api-key")
+                    .is_some_and(|value| value.as_bytes() == API_KEY.as_bytes())
+            };
+            (!valid).then(|| StatusCode::UNAUTHORIZED.into_response())
+        }
+        _ => {
+            let valid = authorization
+                .strip_prefix("Bearer jwt-")
+                .and_then(|n| n.parse::<u32>().ok())
+                .is_some_and(|n| n == inner.issued_tokens);
+            if !valid {
+                Some(StatusCode::UNAUTHORIZED.into_response())
+            } else if inner.scenario.expired_tokens > 0 {
+                inner.scenario.expired_tokens -= 1;
+                Some(StatusCode::UNAUTHORIZED.into_response())
+            } else {
+                None
+            }
+        }
+    }
+}
+
+async fn token(State(inner): State<Shared>, headers: HeaderMap, RawQuery(q): RawQuery) -> Response {
+    let mut inner = inner.lock().unwrap();
+    if let Some(response) = intercept(&mut inner, Endpoint::Token, &headers, q) {
+        return response;
+    }
+    inner.issued_tokens += 1;
+    Json(json!({ "token": format!("jwt-{}", inner.issued_tokens) })).into_response()
+}
+
+async fn presigned_url(
+    State(inner): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(q): RawQuery,
+    Json(body): Json<Value>,
+) -> Response {
+    let mut inner = inner.lock().unwrap();
+    if let Some(response) = intercept(&mut inner, Endpoint::PresignedUrl, &headers, q) {
+        return response;
+    }
+    let filename = body["filename"].as_str().unwrap_or_default();
+    Json(json!({
+        "file_path": format!("uploads/{}", filename),
+        "url": format!("http://{}/upload/{}", inner.addr, filename),
+    }))
+    .into_response()
+}
+
+async fn upload(
+    State(inner): State<Shared>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    RawQuery(q): RawQuery,
+    body: Bytes,
+) -> Response {
+    let mut inner = inner.lock().unwrap();
+    if let Some(response) = intercept(&mut inner, Endpoint::Upload, &headers, q) {
+        return response;
+    }
+    inner.requests.uploads.insert(name, body.len());
+    StatusCode::OK.into_response()
+}
+
+async fn create_run(
+    State(inner): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(q): RawQuery,
+    Json(body): Json<Value>,
+) -> Response {
+    let mut inner = inner.lock().unwrap();
+    if let Some(response) = intercept(&mut inner, Endpoint::CreateRun, &headers, q) {
+        return response;
+    }
+    inner.requests.runs.push(body);
+    Json(json!({ "run_id": RUN_ID, "status": "queued" })).into_response()
+}
+
+async fn get_run(
+    State(inner): State<Shared>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    RawQuery(q): RawQuery,
+) -> Response {
+    let mut inner = inner.lock().unwrap();
+    if let Some(response) = intercept(&mut inner, Endpoint::GetRun, &headers, q) {
+        return response;
+    }
+    if id != RUN_ID {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let completed = inner.requests.count(Endpoint::GetRun) >= inner.scenario.polls_until_complete;
+    let scenario = &inner.scenario;
+    if completed {
+        let failed = if scenario.final_state == "failure" {
+            1
+        } else {
+            0
+        };
+        Json(json!({
+            "id": RUN_ID,
+            "state": scenario.final_state,
+            "passed": 10,
+            "failed": failed,
+            "ignored": 0,
+            "completed": "2024-05-01T10:00:00Z",
+            "total_run_time": 42.5,
+            "error_message": scenario.error_message,
+        }))
+        .into_response()
+    } else {
+        Json(json!({
+            "id": RUN_ID,
+            "state": "running",
+            "passed": null,
+            "failed": null,
+            "ignored": null,
+            "completed": null,
+            "total_run_time": null,
+            "error_message": null,
+        }))
+        .into_response()
+    }
+}
+
+fn artifact_tree(dir: &str) -> Option<Value> {
+    let entry = |id: &str, is_file: bool| json!({ "id": id, "name": id.rsplit('/').next(), "is_file": is_file });
+    let listing = match dir.strip_prefix(RUN_ID)? {
+        "" => vec![entry("run-1/tests", false), entry("run-1/report", false)],
+        "/tests" => vec![entry("run-1/tests/junit.xml", true)],
+        "/report" => vec![entry("run-1/report/allure-results", false)],
+        "/report/allure-results" => {
+            vec![entry("run-1/report/allure-results/result.json", true)]
+        }
+        _ => return None,
+    };
+    Some(Value::Array(listing))
+}
+
+fn artifact_content(key: &str) -> Option<&'static str> {
+    match key {
+        "run-1/tests/junit.xml" => Some("<testsuite tests=\"10\"/>"),
+        "run-1/report/allure-results/result.json" => {
+            Some(r#"{"attachments":[{"name":"log","source":"/data/run-1/logs/omni/device.log"}]}"#)
+        }
+        _ => None,
+    }
+}
+
+async fn list_artifacts(
+    State(inner): State<Shared>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    RawQuery(q): RawQuery,
+) -> Response {
+    let mut inner = inner.lock().unwrap();
+    if let Some(response) = intercept(&mut inner, Endpoint::ListArtifacts, &headers, q) {
+        return response;
+    }
+    match artifact_tree(&id) {
+        Some(listing) => Json(listing).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn download_artifact(
+    State(inner): State<Shared>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    RawQuery(q): RawQuery,
+) -> Response {
+    let mut inner = inner.lock().unwrap();
+    if let Some(response) = intercept(&mut inner, Endpoint::DownloadArtifact, &headers, q) {
+        return response;
+    }
+    match params.get("key").and_then(|key| artifact_content(key)) {
+        Some(content) => content.into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn android_devices(
+    State(inner): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(q): RawQuery,
+) -> Response {
+    let mut inner = inner.lock().unwrap();
+    if let Some(response) = intercept(&mut inner, Endpoint::AndroidDevices, &headers, q) {
+        return response;
+    }
+    Json(json!([
+        { "name": "Pixel 6", "id": "pixel_6", "manufacturer": "Google", "width": 1080, "height": 2400, "dpi": 411 },
+        { "name": "Android TV (1080p)", "id": "tv_1080p", "manufacturer": "Google", "width": 1920, "height": 1080, "dpi": 320 },
+    ]))
+    .into_response()
+}
+
+async fn ios_devices(
+    State(inner): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(q): RawQuery,
+) -> Response {
+    let mut inner = inner.lock().unwrap();
+    if let Some(response) = intercept(&mut inner, Endpoint::IosDevices, &headers, q) {
+        return response;
+    }
+    Json(json!([
+        { "device": "iPhone-15", "os_version": "17.5", "xcode_version": "15.4" },
+        { "device": "iPhone-16", "os_version": "18.2", "xcode_version": "16.2" },
+        { "device": "iPhone-11", "os_version": "18.2", "xcode_version": "16.2" },
+    ]))
+    .into_response()
+}
+
+/// Differs from the catalog built into the CLI so tests can tell which one was used
+async fn device_constraints(
+    State(inner): State<Shared>,
+    headers: HeaderMap,
+    RawQuery(q): RawQuery,
+) -> Response {
+    let mut inner = inner.lock().unwrap();
+    if let Some(response) = intercept(&mut inner, Endpoint::DeviceConstraints, &headers, q) {
+        return response;
+    }
+    Json(json!({
+        "ios": [
+            { "device": ["iPhone-17"], "os_version": ["26.0"], "xcode_version": ["26.0"] },
+        ],
+        "android": [
+            { "device": ["tv"], "flavor": ["native"], "system_image": ["google_apis"], "os_version": ["15"] },
+            { "system_image": ["default", "google_apis"], "os_version": ["14", "15"] },
+        ],
+    }))
+    .into_response()
+}
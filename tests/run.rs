@@ -0,0 +1,369 @@
+mod fake_server;
+
+use std::{fs, path::Path, process::Output};
+
+use anyhow::Result;
+use async_zip::{tokio::write::ZipFileWriter, Compression, ZipEntryBuilder};
+use fake_server::{Endpoint, FakeServer, Scenario, API_KEY};
+use tempfile::{tempdir, TempDir};
+use tokio::process::Command;
+
+fn binaries() -> Result<TempDir> {
+    let dir = tempdir()?;
+    fs::write(dir.path().join("app.apk"), b"app")?;
+    fs::write(dir.path().join("app-androidTest.apk"), b"test app")?;
+    Ok(dir)
+}
+
+async fn run_android(server: &FakeServer, binaries: &Path, extra: &[&str]) -> Result<Output> {
+    let output = Command::new(env!("CARGO_BIN_EXE_marathon-cloud"))
+        .args(["run", "android", "--no-progress-bars", "--base-url"])
+        .arg(server.base_url())
+        .arg("--application")
+        .arg(binaries.join("app.apk"))
+        .arg("--test-application")
+        .arg(binaries.join("app-androidTest.apk"))
+        .args(extra)
+        .env("MARATHON_CLOUD_API_KEY", API_KEY)
+        .env("XDG_CACHE_HOME", binaries.join("cache"))
+        .output()
+        .await?;
+    Ok(output)
+}
+
+#[tokio::test]
+async fn test_run_wait_output() -> Result<()> {
+    let server = FakeServer::start(Scenario::default()).await;
+    let binaries = binaries()?;
+    let output_dir = tempdir()?;
+    let output_arg = output_dir.path().to_str().unwrap();
+
+    let output = run_android(&server, binaries.path(), &["--output", output_arg]).await?;
+
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("state: passed"), "{}", stdout);
+    server.requests(|requests| {
+        assert_eq!(requests.uploads.get("app.apk"), Some(&3));
+        assert_eq!(requests.uploads.get("app-androidTest.apk"), Some(&8));
+        assert_eq!(requests.runs.len(), 1);
+        assert_eq!(requests.runs[0]["platform"], "Android");
+        assert_eq!(requests.runs[0]["s3_app_path"], "uploads/app.apk");
+    });
+    assert!(output_dir.path().join("tests/junit.xml").is_file());
+    let allure = fs::read_to_string(output_dir.path().join("report/allure-results/result.json"))?;
+    assert!(allure.contains("../../logs/omni/device.log"), "{}", allure);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_run_exits_with_error() -> Result<()> {
+    let server = FakeServer::start(Scenario::failed_run()).await;
+    let binaries = binaries()?;
+
+    let output = run_android(&server, binaries.path(), &[]).await?;
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("1 test failed"), "{}", stdout);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_run_with_ignored_failures() -> Result<()> {
+    let server = FakeServer::start(Scenario::failed_run()).await;
+    let binaries = binaries()?;
+
+    let output = run_android(
+        &server,
+        binaries.path(),
+        &["--ignore-test-failures", "true"],
+    )
+    .await?;
+
+    assert!(output.status.success(), "{:?}", output);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_submission_server_error() -> Result<()> {
+    let server = FakeServer::start(Scenario::server_errors(Endpoint::CreateRun, 1)).await;
+    let binaries = binaries()?;
+
+    let output = run_android(&server, binaries.path(), &[]).await?;
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("503"), "{}", stderr);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_without_wait() -> Result<()> {
+    let server = FakeServer::start(Scenario::slow_run(100)).await;
+    let binaries = binaries()?;
+
+    let output = run_android(&server, binaries.path(), &["--wait", "false"]).await?;
+
+    assert!(output.status.success(), "{:?}", output);
+    server.requests(|requests| assert_eq!(requests.count(Endpoint::GetRun), 0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_infers_device_configuration_from_server() -> Result<()> {
+    let server = FakeServer::start(Scenario::default()).await;
+    let binaries = binaries()?;
+
+    let output = run_android(&server, binaries.path(), &["--device", "tv"]).await?;
+
+    assert!(output.status.success(), "{:?}", output);
+    server.requests(|requests| {
+        assert_eq!(requests.count(Endpoint::DeviceConstraints), 1);
+        assert_eq!(requests.runs[0]["system_image"], "google_apis");
+        assert_eq!(requests.runs[0]["os_version"], "15");
+        assert_eq!(requests.runs[0]["flavor"], "native");
+    });
+    assert!(binaries
+        .path()
+        .join("cache/marathon-cloud/constraints.json")
+        .is_file());
+
+    let output = run_android(
+        &server,
+        binaries.path(),
+        &["--device", "tv", "--os-version", "14"],
+    )
+    .await?;
+
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("--device tv --os-version 14 is not supported"),
+        "{}",
+        stderr
+    );
+    server.requests(|requests| {
+        // The second run is served from the cache
+        assert_eq!(requests.count(Endpoint::DeviceConstraints), 1);
+        assert_eq!(requests.runs.len(), 1);
+    });
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_matrix_run() -> Result<()> {
+    let server = FakeServer::start(Scenario::default()).await;
+    let binaries = binaries()?;
+    let output_dir = tempdir()?;
+    let output_arg = output_dir.path().to_str().unwrap();
+
+    let output = run_android(
+        &server,
+        binaries.path(),
+        &[
+            "--matrix",
+            "os-version=14,15",
+            "--device",
+            "pixel",
+            "--name",
+            "nightly",
+            "--output",
+            output_arg,
+        ],
+    )
+    .await?;
+
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("OS VERSION  STATE"), "{}", stdout);
+    server.requests(|requests| {
+        assert_eq!(requests.count(Endpoint::Upload), 2);
+        assert_eq!(requests.runs.len(), 2);
+        assert_eq!(requests.runs[0]["os_version"], "14");
+        assert_eq!(requests.runs[1]["os_version"], "15");
+        assert_eq!(requests.runs[1]["device"], "pixel");
+        assert_eq!(requests.runs[1]["name"], "nightly (os-version=15)");
+    });
+    for cell in ["os-version-14", "os-version-15"] {
+        assert!(output_dir
+            .path()
+            .join(cell)
+            .join("tests/junit.xml")
+            .is_file());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_matrix_run_with_failed_cell() -> Result<()> {
+    let server = FakeServer::start(Scenario::failed_run()).await;
+    let binaries = binaries()?;
+
+    let output = run_android(&server, binaries.path(), &["--matrix", "device=pixel,tv"]).await?;
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("failure"), "{}", stdout);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_matrix_run_with_errored_cell() -> Result<()> {
+    let server = FakeServer::start(Scenario::server_errors(Endpoint::GetRun, 1)).await;
+    let binaries = binaries()?;
+    let output_dir = tempdir()?;
+    let output_arg = output_dir.path().to_str().unwrap();
+
+    let output = run_android(
+        &server,
+        binaries.path(),
+        &["--matrix", "device=pixel,tv", "--output", output_arg],
+    )
+    .await?;
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("error"), "{}", stdout);
+    assert!(stdout.contains("passed"), "{}", stdout);
+    let downloaded: Vec<bool> = ["device-pixel", "device-tv"]
+        .iter()
+        .map(|cell| {
+            output_dir
+                .path()
+                .join(cell)
+                .join("tests/junit.xml")
+                .is_file()
+        })
+        .collect();
+    assert_eq!(downloaded.iter().filter(|d| **d).count(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_with_mismatched_apks_fails_before_upload() -> Result<()> {
+    let server = FakeServer::start(Scenario::default()).await;
+    let binaries = binaries()?;
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixture/inventory");
+    for name in ["app.apk", "app-androidTest.apk"] {
+        fs::copy(
+            fixture.join("app-androidTest.apk"),
+            binaries.path().join(name),
+        )?;
+    }
+
+    let output = run_android(&server, binaries.path(), &[]).await?;
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("built for a different application"),
+        "{}",
+        stderr
+    );
+    server.requests(|requests| assert!(requests.uploads.is_empty()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_with_apk_set_uploads_universal_apk() -> Result<()> {
+    let server = FakeServer::start(Scenario::default()).await;
+    let binaries = binaries()?;
+    let apks = binaries.path().join("app.apks");
+    let mut writer = ZipFileWriter::with_tokio(tokio::fs::File::create(&apks).await?);
+    for (name, data) in [("toc.pb", &b""[..]), ("universal.apk", b"universal")] {
+        let entry = ZipEntryBuilder::new(name.into(), Compression::Deflate);
+        writer.write_entry_whole(entry, data).await?;
+    }
+    writer.close().await?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_marathon-cloud"))
+        .args(["run", "android", "--no-progress-bars", "--base-url"])
+        .arg(server.base_url())
+        .arg("--application")
+        .arg(&apks)
+        .arg("--test-application")
+        .arg(binaries.path().join("app-androidTest.apk"))
+        .env("MARATHON_CLOUD_API_KEY", API_KEY)
+        .env("XDG_CACHE_HOME", binaries.path().join("cache"))
+        .output()
+        .await?;
+
+    assert!(output.status.success(), "{:?}", output);
+    server.requests(|requests| assert_eq!(requests.uploads.get("app.apk"), Some(&9)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_ios_from_xctestrun() -> Result<()> {
+    let server = FakeServer::start(Scenario::default()).await;
+    let cache = tempdir()?;
+    let xctestrun =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixture/xctestrun/App_v2.xctestrun");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_marathon-cloud"))
+        .args(["run", "ios", "--no-progress-bars", "--base-url"])
+        .arg(server.base_url())
+        .arg("--xctestrun")
+        .arg(&xctestrun)
+        .args(["--xctestrun-env", "API_URL=https://example.com"])
+        .env("MARATHON_CLOUD_API_KEY", API_KEY)
+        .env("XDG_CACHE_HOME", cache.path())
+        .output()
+        .await?;
+
+    assert!(output.status.success(), "{:?}", output);
+    server.requests(|requests| {
+        assert!(requests.uploads.contains_key("App.zip"));
+        assert!(requests.uploads.contains_key("AppUITests-Runner.zip"));
+        let run = &requests.runs[0];
+        assert_eq!(run["platform"], "iOS");
+        assert_eq!(run["env_args"]["API_URL"], "https://example.com");
+        assert!(run["env_args"].get("DYLD_FRAMEWORK_PATH").is_none());
+        assert_eq!(run["test_env_args"]["SNAPSHOT_MODE"], "record");
+    });
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_devices_android_server_error() -> Result<()> {
+    let server = FakeServer::start(Scenario::server_errors(Endpoint::AndroidDevices, 1)).await;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_marathon-cloud"))
+        .args(["devices", "android", "--no-progress-bars", "--base-url"])
+        .arg(server.base_url())
+        .env("MARATHON_CLOUD_API_KEY", API_KEY)
+        .output()
+        .await?;
+
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("503"), "{}", stderr);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_download_server_error() -> Result<()> {
+    let server = FakeServer::start(Scenario::server_errors(Endpoint::GetRun, 1)).await;
+    let output_dir = tempdir()?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_marathon-cloud"))
+        .args([
+            "download",
+            "--no-progress-bars",
+            "--id",
+            "run-1",
+            "--base-url",
+        ])
+        .arg(server.base_url())
+        .arg("--output")
+        .arg(output_dir.path())
+        .env("MARATHON_CLOUD_API_KEY", API_KEY)
+        .output()
+        .await?;
+
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("503"), "{}", stderr);
+    Ok(())
+}